@@ -1,3 +1,5 @@
+use std::time::Instant;
+
 use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::WindowBuilder;
@@ -25,6 +27,9 @@ pub async fn run() {
 }
 
 fn run_event_loop(event_loop: EventLoop<()>, mut state: state::State) {
+    // time between updates, so movement doesn't depend on the frame rate
+    let mut last_update = Instant::now();
+
     // start window event loop
     event_loop.run(move |event, _, control_flow| match event {
         Event::WindowEvent { ref event, window_id } if window_id == state.window().id() => {
@@ -51,8 +56,14 @@ fn run_event_loop(event_loop: EventLoop<()>, mut state: state::State) {
                 }
             }
         }
+        // raw mouse motion for looking around; not clamped to the window like CursorMoved
+        Event::DeviceEvent { ref event, .. } => {
+            state.device_input(event);
+        }
         Event::RedrawRequested(window_id) if window_id == state.window.id() => {
-            state.update();
+            let now = Instant::now();
+            state.update(now - last_update);
+            last_update = now;
             match state.render() {
                 Ok(_) => {}
                 // Reconfigure the surface if lost
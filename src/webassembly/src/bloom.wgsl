@@ -0,0 +1,81 @@
+// Bloom: bright parts of the HDR scene are downsampled into a mip chain, upsampled back
+// with a tent filter and added to the scene
+
+struct BloomUniform {
+    threshold: f32, // brightness where bloom starts
+    knee: f32, // soft transition below the threshold
+    intensity: f32, // how much of the bloom is added to the scene
+    filter_radius: f32, // upsample tent radius in uv units
+};
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+@group(0) @binding(2)
+var<uniform> bloom: BloomUniform;
+@group(0) @binding(3) // composite only
+var t_bloom: texture_2d<f32>;
+
+// 13 tap downsample from Call of Duty: Advanced Warfare
+fn downsample(uv: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_source));
+    let a = textureSample(t_source, s_source, uv + texel * vec2<f32>(-2.0, 2.0)).rgb;
+    let b = textureSample(t_source, s_source, uv + texel * vec2<f32>(0.0, 2.0)).rgb;
+    let c = textureSample(t_source, s_source, uv + texel * vec2<f32>(2.0, 2.0)).rgb;
+    let d = textureSample(t_source, s_source, uv + texel * vec2<f32>(-2.0, 0.0)).rgb;
+    let e = textureSample(t_source, s_source, uv).rgb;
+    let f = textureSample(t_source, s_source, uv + texel * vec2<f32>(2.0, 0.0)).rgb;
+    let g = textureSample(t_source, s_source, uv + texel * vec2<f32>(-2.0, -2.0)).rgb;
+    let h = textureSample(t_source, s_source, uv + texel * vec2<f32>(0.0, -2.0)).rgb;
+    let i = textureSample(t_source, s_source, uv + texel * vec2<f32>(2.0, -2.0)).rgb;
+    let j = textureSample(t_source, s_source, uv + texel * vec2<f32>(-1.0, 1.0)).rgb;
+    let k = textureSample(t_source, s_source, uv + texel * vec2<f32>(1.0, 1.0)).rgb;
+    let l = textureSample(t_source, s_source, uv + texel * vec2<f32>(-1.0, -1.0)).rgb;
+    let m = textureSample(t_source, s_source, uv + texel * vec2<f32>(1.0, -1.0)).rgb;
+    return e * 0.125 + (a + c + g + i) * 0.03125 + (b + d + f + h) * 0.0625 + (j + k + l + m) * 0.125;
+}
+
+// soft threshold so only bright parts bloom
+fn prefilter(color: vec3<f32>) -> vec3<f32> {
+    let brightness = max(color.r, max(color.g, color.b));
+    var soft = clamp(brightness - bloom.threshold + bloom.knee, 0.0, 2.0 * bloom.knee);
+    soft = soft * soft / (4.0 * bloom.knee + 0.00001);
+    let contribution = max(soft, brightness - bloom.threshold) / max(brightness, 0.00001);
+    return color * contribution;
+}
+
+// first downsample from the scene
+@fragment
+fn fs_downsample_prefilter(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    // clamp to keep single very bright pixels from flickering
+    return vec4<f32>(prefilter(min(downsample(in.uv), vec3<f32>(1000.0))), 1.0);
+}
+
+@fragment
+fn fs_downsample(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(downsample(in.uv), 1.0);
+}
+
+// 3x3 tent filter; additively blended onto the next larger mip
+@fragment
+fn fs_upsample(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let r = bloom.filter_radius;
+    var color = textureSample(t_source, s_source, in.uv).rgb * 4.0;
+    color += (textureSample(t_source, s_source, in.uv + vec2<f32>(0.0, r)).rgb
+        + textureSample(t_source, s_source, in.uv + vec2<f32>(-r, 0.0)).rgb
+        + textureSample(t_source, s_source, in.uv + vec2<f32>(r, 0.0)).rgb
+        + textureSample(t_source, s_source, in.uv + vec2<f32>(0.0, -r)).rgb) * 2.0;
+    color += textureSample(t_source, s_source, in.uv + vec2<f32>(-r, r)).rgb
+        + textureSample(t_source, s_source, in.uv + vec2<f32>(r, r)).rgb
+        + textureSample(t_source, s_source, in.uv + vec2<f32>(-r, -r)).rgb
+        + textureSample(t_source, s_source, in.uv + vec2<f32>(r, -r)).rgb;
+    return vec4<f32>(color / 16.0, 1.0);
+}
+
+@fragment
+fn fs_composite(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let scene = textureSample(t_source, s_source, in.uv);
+    let glow = textureSample(t_bloom, s_source, in.uv).rgb;
+    return vec4<f32>(scene.rgb + glow * bloom.intensity, scene.a);
+}
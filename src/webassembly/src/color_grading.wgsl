@@ -0,0 +1,36 @@
+// Color grading: looks up the display encoded color in a 3D LUT
+
+struct ColorGradingUniform {
+    strength: f32, // blend between the original (0) and the graded (1) color
+};
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+@group(0) @binding(2)
+var<uniform> grading: ColorGradingUniform;
+@group(0) @binding(3)
+var t_lut: texture_3d<f32>;
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+@fragment
+fn fs_color_grading(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_source, s_source, in.uv);
+    // LUTs are authored on display encoded colors; sample at texel centers
+    let size = f32(textureDimensions(t_lut).x);
+    let coords = linear_to_srgb(clamp(color.rgb, vec3<f32>(0.0), vec3<f32>(1.0))) * ((size - 1.0) / size) + 0.5 / size;
+    let graded = srgb_to_linear(textureSample(t_lut, s_source, coords).rgb);
+    return vec4<f32>(mix(color.rgb, graded, grading.strength), color.a);
+}
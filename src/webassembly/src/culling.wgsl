@@ -0,0 +1,169 @@
+// GPU culling: cs_cull tests every instance's bounds against the frustum and, optionally, the
+// Hi-Z depth pyramid of the previous frame, then appends the indices of the visible ones to the
+// region of their mesh's level of detail in the visible buffer, counted in that level's indirect
+// draw args; cs_reduce builds the pyramid one level at a time
+
+// model::InstanceRaw
+struct InstanceRaw {
+    model: mat4x4<f32>,
+    normal: mat3x3<f32>,
+    color: vec4<f32>,
+    custom: vec4<f32>,
+    pose: u32,
+    mesh: u32,
+};
+
+struct CullUniform {
+    view_proj: mat4x4<f32>,
+    planes: array<vec4<f32>, 6>, // culling::Frustum, all zero with a positive w to keep everything
+    instance_count: u32,
+    hi_z: u32, // 1 to test against the pyramid
+    far_depth: f32, // 1, or 0 with reverse-Z
+    pyramid_levels: u32, // textureNumLevels isn't available everywhere
+    mesh_count: u32,
+    projection_scale: f32, // the projection's y scale
+};
+
+struct CullMesh {
+    bounds_min: vec4<f32>, // mesh bounds in model space
+    bounds_max: vec4<f32>,
+    lod_screen_sizes: array<vec4<f32>, 2>, // mesh::GpuLod::screen_size of each level
+    lod_count: u32,
+    first_draw: u32, // index of the mesh's first level in draws
+};
+
+// wgpu::util::DrawIndexedIndirect
+struct DrawIndexedIndirect {
+    index_count: u32,
+    instance_count: atomic<u32>,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> cull: CullUniform;
+
+@group(1) @binding(0)
+var<storage, read> instances: array<InstanceRaw>;
+@group(1) @binding(1)
+var<storage, read_write> visible: array<u32>; // indices into instances
+@group(1) @binding(2)
+var<storage, read_write> draws: array<DrawIndexedIndirect>; // one per level of detail of each mesh
+@group(1) @binding(3)
+var t_pyramid: texture_2d<f32>; // every level, read with textureLoad
+@group(1) @binding(4)
+var<storage, read> meshes: array<CullMesh>;
+
+// cs_reduce's own group 1; an entry point only needs layouts for the bindings it uses
+@group(1) @binding(0)
+var t_source: texture_2d<f32>; // the depth buffer or the level before
+@group(1) @binding(1)
+var t_level: texture_storage_2d<r32float, write>;
+
+fn farthest(a: f32, b: f32) -> f32 {
+    return select(min(a, b), max(a, b), cull.far_depth == 1.0);
+}
+
+fn nearest(a: f32, b: f32) -> f32 {
+    return select(max(a, b), min(a, b), cull.far_depth == 1.0);
+}
+
+fn load_pyramid(coords: vec2<i32>, level: i32) -> f32 {
+    return textureLoad(t_pyramid, coords, level).r;
+}
+
+// whether the box is behind what the pyramid saw; boxes reaching behind the camera never are
+fn occluded(center: vec3<f32>, extents: vec3<f32>) -> bool {
+    var uv_min = vec2<f32>(1.0);
+    var uv_max = vec2<f32>(0.0);
+    var depth = cull.far_depth;
+    for (var i = 0u; i < 8u; i += 1u) {
+        let corner = center + extents * vec3<f32>(
+            select(-1.0, 1.0, (i & 1u) != 0u),
+            select(-1.0, 1.0, (i & 2u) != 0u),
+            select(-1.0, 1.0, (i & 4u) != 0u),
+        );
+        let clip = cull.view_proj * vec4<f32>(corner, 1.0);
+        if clip.w <= 0.0 {
+            return false;
+        }
+        let ndc = clip.xyz / clip.w;
+        let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+        uv_min = min(uv_min, uv);
+        uv_max = max(uv_max, uv);
+        depth = nearest(depth, ndc.z);
+    }
+    uv_min = clamp(uv_min, vec2<f32>(0.0), vec2<f32>(1.0));
+    uv_max = clamp(uv_max, vec2<f32>(0.0), vec2<f32>(1.0));
+
+    // the level where the box covers at most 2x2 texels
+    let size = (uv_max - uv_min) * vec2<f32>(textureDimensions(t_pyramid));
+    let level = min(i32(ceil(log2(max(max(size.x, size.y), 1.0)))), i32(cull.pyramid_levels) - 1);
+    let level_size = vec2<i32>(textureDimensions(t_pyramid, level));
+    let low = clamp(vec2<i32>(uv_min * vec2<f32>(level_size)), vec2<i32>(0), level_size - 1);
+    let high = clamp(vec2<i32>(uv_max * vec2<f32>(level_size)), vec2<i32>(0), level_size - 1);
+    let occluder = farthest(
+        farthest(load_pyramid(low, level), load_pyramid(vec2<i32>(high.x, low.y), level)),
+        farthest(load_pyramid(vec2<i32>(low.x, high.y), level), load_pyramid(high, level)),
+    );
+    return depth != occluder && nearest(depth, occluder) == occluder;
+}
+
+@compute @workgroup_size(64)
+fn cs_cull(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= cull.instance_count || instances[index].mesh >= cull.mesh_count {
+        return;
+    }
+    let model = instances[index].model;
+    let mesh = meshes[instances[index].mesh];
+
+    // world space box around the transformed mesh bounds
+    let center = (model * vec4<f32>((mesh.bounds_min.xyz + mesh.bounds_max.xyz) * 0.5, 1.0)).xyz;
+    let half_size = (mesh.bounds_max.xyz - mesh.bounds_min.xyz) * 0.5;
+    let extents = mat3x3<f32>(abs(model[0].xyz), abs(model[1].xyz), abs(model[2].xyz)) * half_size;
+
+    for (var i = 0u; i < 6u; i += 1u) {
+        let plane = cull.planes[i];
+        if dot(plane.xyz, center) + dot(abs(plane.xyz), extents) + plane.w < 0.0 {
+            return;
+        }
+    }
+    if cull.hi_z != 0u && occluded(center, extents) {
+        return;
+    }
+
+    // like mesh::GpuMesh::select_lod with culling::screen_size
+    let w = (cull.view_proj * vec4<f32>(center, 1.0)).w;
+    let screen_size = length(extents) * cull.projection_scale / max(w, 1.1920929e-7);
+    var lod = 0u;
+    for (var i = 1u; i < mesh.lod_count; i += 1u) {
+        if screen_size < meshes[instances[index].mesh].lod_screen_sizes[i / 4u][i % 4u] {
+            lod = i;
+        }
+    }
+    // each level has room for every instance
+    let draw = mesh.first_draw + lod;
+    visible[draw * cull.instance_count + atomicAdd(&draws[draw].instance_count, 1u)] = index;
+}
+
+// each texel keeps the farthest depth of the 2x2 texels below it; the last row and column also
+// take in the extra texel of an odd sized source, so nothing is missed
+@compute @workgroup_size(8, 8)
+fn cs_reduce(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(t_level);
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+    let source_size = vec2<i32>(textureDimensions(t_source));
+    let first = vec2<i32>(id.xy) * 2;
+    let last = min(first + 1 + vec2<i32>(id.xy == size - 1u), source_size - 1);
+    var depth = 1.0 - cull.far_depth; // the near plane
+    for (var y = first.y; y <= last.y; y += 1) {
+        for (var x = first.x; x <= last.x; x += 1) {
+            depth = farthest(depth, textureLoad(t_source, vec2<i32>(x, y), 0).r);
+        }
+    }
+    textureStore(t_level, vec2<i32>(id.xy), vec4<f32>(depth, 0.0, 0.0, 0.0));
+}
@@ -0,0 +1,84 @@
+// Deferred lighting: reads the G-buffer written by fs_gbuffer in shader.wgsl and adds light
+// into the HDR target. fullscreen.wgsl and pbr.wgsl are prepended when the module is created
+
+@group(0) @binding(0)
+var t_albedo: texture_2d<f32>; // occlusion in a
+@group(0) @binding(1)
+var t_normal: texture_2d<f32>;
+@group(0) @binding(2)
+var t_material: texture_2d<f32>; // metallic, roughness
+@group(0) @binding(3)
+var t_depth: texture_2d<f32>;
+
+@group(3) @binding(0)
+var t_ssao: texture_2d<f32>;
+@group(3) @binding(1)
+var s_ssao: sampler;
+
+fn load_surface(pixel: vec2<i32>, depth: f32) -> Surface {
+    let albedo = textureLoad(t_albedo, pixel, 0);
+    let material = textureLoad(t_material, pixel, 0);
+    // world position from the depth buffer
+    let uv = (vec2<f32>(pixel) + 0.5) / vec2<f32>(textureDimensions(t_depth));
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let world = camera.inv_view_proj * ndc;
+
+    var surface: Surface;
+    surface.position = world.xyz / world.w;
+    surface.normal = normalize(textureLoad(t_normal, pixel, 0).xyz);
+    surface.albedo = albedo.rgb;
+    surface.metallic = material.r;
+    surface.roughness = material.g;
+    surface.occlusion = albedo.a;
+    return surface;
+}
+
+// ambient and directional light for every covered pixel
+@fragment
+fn fs_directional(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.position.xy);
+    let depth = textureLoad(t_depth, pixel, 0).r;
+    if depth == camera.view_position.w {
+        discard; // background, left at the far depth
+    }
+    var surface = load_surface(pixel, depth);
+    surface.occlusion *= textureSampleLevel(t_ssao, s_ssao, in.uv, 0.0).r;
+    return vec4<f32>(ambient_light(surface) + directional_light(surface), 0.0);
+}
+
+// point lights are drawn as spheres covering their radius
+struct LightVolumeInput {
+    @location(0) position: vec3<f32>, // unit sphere
+};
+
+struct PointLightInput {
+    @location(5) position: vec4<f32>, // radius in w
+    @location(6) color: vec4<f32>, // intensity in w
+};
+
+struct LightVolumeOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) @interpolate(flat) light_position: vec4<f32>,
+    @location(1) @interpolate(flat) light_color: vec4<f32>,
+};
+
+@vertex
+fn vs_point_light(volume: LightVolumeInput, point: PointLightInput) -> LightVolumeOutput {
+    let world_position = point.position.xyz + volume.position * point.position.w;
+    var out: LightVolumeOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.light_position = point.position;
+    out.light_color = point.color;
+    return out;
+}
+
+@fragment
+fn fs_point_light(in: LightVolumeOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.clip_position.xy);
+    let depth = textureLoad(t_depth, pixel, 0).r;
+    if depth == camera.view_position.w {
+        discard; // background, left at the far depth
+    }
+    let surface = load_surface(pixel, depth);
+    return vec4<f32>(point_light(surface, PointLight(in.light_position, in.light_color)), 0.0);
+}
@@ -0,0 +1,17 @@
+// Shared vertex stage for passes that draw one triangle over the whole target;
+// prepended to the shaders created with fullscreen::create_shader()
+
+struct FullscreenOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> FullscreenOutput {
+    // triangle that covers the screen: uv (0,0), (2,0), (0,2)
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: FullscreenOutput;
+    out.position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
@@ -0,0 +1,51 @@
+// FXAA: smooths aliased edges by blending along the direction of the local luma gradient
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+
+const REDUCE_MIN: f32 = 0.0078125; // 1 / 128
+const REDUCE_MUL: f32 = 0.125; // 1 / 8
+const SPAN_MAX: f32 = 8.0;
+
+// luma of the display encoded color; the texture holds linear values
+fn luma(color: vec3<f32>) -> f32 {
+    return dot(sqrt(color), vec3<f32>(0.299, 0.587, 0.114));
+}
+
+@fragment
+fn fs_fxaa(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_source));
+    let center = textureSample(t_source, s_source, in.uv);
+    let luma_nw = luma(textureSample(t_source, s_source, in.uv + vec2<f32>(-1.0, -1.0) * texel).rgb);
+    let luma_ne = luma(textureSample(t_source, s_source, in.uv + vec2<f32>(1.0, -1.0) * texel).rgb);
+    let luma_sw = luma(textureSample(t_source, s_source, in.uv + vec2<f32>(-1.0, 1.0) * texel).rgb);
+    let luma_se = luma(textureSample(t_source, s_source, in.uv + vec2<f32>(1.0, 1.0) * texel).rgb);
+    let luma_m = luma(center.rgb);
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    // edge direction, perpendicular to the gradient
+    var direction = vec2<f32>(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let direction_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    let reciprocal_min = 1.0 / (min(abs(direction.x), abs(direction.y)) + direction_reduce);
+    direction = clamp(direction * reciprocal_min, vec2<f32>(-SPAN_MAX), vec2<f32>(SPAN_MAX)) * texel;
+
+    let color_a = 0.5 * (
+        textureSample(t_source, s_source, in.uv + direction * (1.0 / 3.0 - 0.5)).rgb
+        + textureSample(t_source, s_source, in.uv + direction * (2.0 / 3.0 - 0.5)).rgb);
+    let color_b = color_a * 0.5 + 0.25 * (
+        textureSample(t_source, s_source, in.uv + direction * -0.5).rgb
+        + textureSample(t_source, s_source, in.uv + direction * 0.5).rgb);
+
+    // the wide blend overshoots on thin features; fall back to the narrow one then
+    let luma_b = luma(color_b);
+    if luma_b < luma_min || luma_b > luma_max {
+        return vec4<f32>(color_a, center.a);
+    }
+    return vec4<f32>(color_b, center.a);
+}
@@ -0,0 +1,74 @@
+// Tonemapping: maps the Rgba16Float scene color into the [0, 1] range of the surface
+
+struct TonemapUniform {
+    exposure: f32, // in stops; 0 leaves the scene color as is
+    tonemapper: u32, // 0 = ACES, 1 = Reinhard, 2 = AgX
+};
+
+@group(0) @binding(0)
+var t_hdr: texture_2d<f32>;
+@group(0) @binding(1)
+var s_hdr: sampler;
+@group(0) @binding(2)
+var<uniform> tonemap: TonemapUniform;
+
+// ACES fit by Stephen Hill
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let input = mat3x3<f32>(
+        vec3<f32>(0.59719, 0.07600, 0.02840),
+        vec3<f32>(0.35458, 0.90834, 0.13383),
+        vec3<f32>(0.04823, 0.01566, 0.83777),
+    );
+    let output = mat3x3<f32>(
+        vec3<f32>(1.60475, -0.10208, -0.00327),
+        vec3<f32>(-0.53108, 1.10813, -0.07276),
+        vec3<f32>(-0.07367, -0.00605, 1.07602),
+    );
+    let v = input * color;
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+    return clamp(output * (a / b), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (1.0 + color);
+}
+
+// AgX with the polynomial contrast curve by Benjamin Wrensch
+fn agx_contrast(x: vec3<f32>) -> vec3<f32> {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+}
+
+fn agx(color: vec3<f32>) -> vec3<f32> {
+    let inset = mat3x3<f32>(
+        vec3<f32>(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+        vec3<f32>(0.0784335999999992, 0.878468636469772, 0.0784336),
+        vec3<f32>(0.0792237451477643, 0.0791661274605434, 0.879142973793104),
+    );
+    let outset = mat3x3<f32>(
+        vec3<f32>(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+        vec3<f32>(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+        vec3<f32>(-0.0990297440797205, -0.0989611768448433, 1.15107367264116),
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+    let encoded = clamp(log2(max(inset * color, vec3<f32>(1e-10))), vec3<f32>(min_ev), vec3<f32>(max_ev));
+    let curved = agx_contrast((encoded - min_ev) / (max_ev - min_ev));
+    // the curve outputs display encoded values; the sRGB surface expects linear ones
+    return pow(max(outset * curved, vec3<f32>(0.0)), vec3<f32>(2.2));
+}
+
+@fragment
+fn fs_tonemap(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let hdr = textureSample(t_hdr, s_hdr, in.uv);
+    let color = hdr.rgb * exp2(tonemap.exposure);
+    var mapped: vec3<f32>;
+    switch tonemap.tonemapper {
+        case 1u: { mapped = reinhard(color); }
+        case 2u: { mapped = agx(color); }
+        default: { mapped = aces(color); }
+    }
+    return vec4<f32>(mapped, hdr.a);
+}
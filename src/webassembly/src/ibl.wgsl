@@ -0,0 +1,201 @@
+// Startup passes for image-based lighting; every pass draws one fullscreen triangle into
+// a single face + mip level of a cube texture (or into the 2D BRDF lookup table)
+
+const PI: f32 = 3.14159265359;
+
+struct FaceUniform {
+    face: u32, // cube face index: +X, -X, +Y, -Y, +Z, -Z
+    roughness: f32, // only used by the prefilter pass
+    sample_count: u32,
+    source_lod: f32, // the source mip the environment's resampling passes read
+};
+
+@group(0) @binding(0) // dynamic offset selects the face and mip level that is drawn
+var<uniform> params: FaceUniform;
+@group(0) @binding(1)
+var t_source: texture_cube<f32>;
+@group(0) @binding(2)
+var s_source: sampler;
+@group(0) @binding(3) // only bound for fs_equirectangular, which reads no t_source
+var t_equirectangular: texture_2d<f32>;
+
+// direction through a texel of a cube face; uv has its origin in the top left of the face
+fn face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let st = uv * 2.0 - 1.0;
+    var direction: vec3<f32>;
+    switch face {
+        case 0u: { direction = vec3<f32>(1.0, -st.y, -st.x); }
+        case 1u: { direction = vec3<f32>(-1.0, -st.y, st.x); }
+        case 2u: { direction = vec3<f32>(st.x, 1.0, st.y); }
+        case 3u: { direction = vec3<f32>(st.x, -1.0, -st.y); }
+        case 4u: { direction = vec3<f32>(st.x, -st.y, 1.0); }
+        default: { direction = vec3<f32>(-st.x, -st.y, -1.0); }
+    }
+    return normalize(direction);
+}
+
+// procedural sky used as environment unless Environment::from_source is given one
+fn sky(direction: vec3<f32>) -> vec3<f32> {
+    let zenith = vec3<f32>(0.15, 0.3, 0.65);
+    let horizon = vec3<f32>(0.7, 0.75, 0.8);
+    let ground = vec3<f32>(0.2, 0.18, 0.16);
+    var color: vec3<f32>;
+    if direction.y >= 0.0 {
+        color = mix(horizon, zenith, pow(direction.y, 0.5));
+    } else {
+        color = mix(horizon, ground, pow(-direction.y, 0.3));
+    }
+    // sun in the direction the default light comes from
+    let sun_direction = normalize(vec3<f32>(0.5, 1.0, 0.3));
+    let sun = smoothstep(0.9995, 0.9998, dot(direction, sun_direction));
+    return color + vec3<f32>(sun * 40.0);
+}
+
+@fragment
+fn fs_sky(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(sky(face_direction(params.face, in.uv)), 1.0);
+}
+
+// a cube texture resampled into the environment map
+@fragment
+fn fs_cube(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let direction = face_direction(params.face, in.uv);
+    return vec4<f32>(textureSampleLevel(t_source, s_source, direction, params.source_lod).rgb, 1.0);
+}
+
+// an equirectangular image resampled into the environment map; u is -X at 0, -Z at 0.25, +X at
+// 0.5 and +Z at 0.75, and v goes from +Y down to -Y
+@fragment
+fn fs_equirectangular(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let direction = face_direction(params.face, in.uv);
+    let uv = vec2<f32>(atan2(direction.z, direction.x) / (2.0 * PI) + 0.5, acos(clamp(direction.y, -1.0, 1.0)) / PI);
+    return vec4<f32>(textureSampleLevel(t_equirectangular, s_source, uv, params.source_lod).rgb, 1.0);
+}
+
+// diffuse convolution of the environment over the hemisphere around the normal
+@fragment
+fn fs_irradiance(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let normal = face_direction(params.face, in.uv);
+    var up = vec3<f32>(0.0, 1.0, 0.0);
+    if abs(normal.y) > 0.999 {
+        up = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    let right = normalize(cross(up, normal));
+    up = cross(normal, right);
+
+    let delta = 0.05;
+    var irradiance = vec3<f32>(0.0);
+    var count = 0.0;
+    for (var phi = 0.0; phi < 2.0 * PI; phi += delta) {
+        for (var theta = 0.0; theta < 0.5 * PI; theta += delta) {
+            let tangent = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            let sample_direction = tangent.x * right + tangent.y * up + tangent.z * normal;
+            irradiance += textureSampleLevel(t_source, s_source, sample_direction, 0.0).rgb * cos(theta) * sin(theta);
+            count += 1.0;
+        }
+    }
+    return vec4<f32>(PI * irradiance / count, 1.0);
+}
+
+fn radical_inverse_vdc(index: u32) -> f32 {
+    var bits = index;
+    bits = (bits << 16u) | (bits >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return f32(bits) * 2.3283064365386963e-10;
+}
+
+fn hammersley(index: u32, count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(index) / f32(count), radical_inverse_vdc(index));
+}
+
+// half vector around the normal, distributed by the GGX lobe of the given roughness
+fn importance_sample_ggx(xi: vec2<f32>, normal: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    let h = vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+
+    var up = vec3<f32>(0.0, 0.0, 1.0);
+    if abs(normal.z) > 0.999 {
+        up = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    let tangent = normalize(cross(up, normal));
+    let bitangent = cross(normal, tangent);
+    return normalize(tangent * h.x + bitangent * h.y + normal * h.z);
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// specular convolution for one roughness per mip level (split sum, first half)
+@fragment
+fn fs_prefilter(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let normal = face_direction(params.face, in.uv);
+    // assume view direction == normal, the usual split sum approximation
+    let view = normal;
+    let roughness = params.roughness;
+    let resolution = f32(textureDimensions(t_source).x);
+    let texel_solid_angle = 4.0 * PI / (6.0 * resolution * resolution);
+
+    var color = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < params.sample_count; i++) {
+        let h = importance_sample_ggx(hammersley(i, params.sample_count), normal, roughness);
+        let l = normalize(2.0 * dot(view, h) * h - view);
+        let n_dot_l = dot(normal, l);
+        if n_dot_l > 0.0 {
+            // sample a blurrier mip for unlikely directions to avoid bright dots
+            let n_dot_h = max(dot(normal, h), 0.0);
+            let h_dot_v = max(dot(h, view), 0.0);
+            let pdf = distribution_ggx(n_dot_h, roughness) * n_dot_h / (4.0 * h_dot_v) + 0.0001;
+            let sample_solid_angle = 1.0 / (f32(params.sample_count) * pdf + 0.0001);
+            var mip_level = 0.0;
+            if roughness > 0.0 {
+                mip_level = 0.5 * log2(sample_solid_angle / texel_solid_angle);
+            }
+            color += textureSampleLevel(t_source, s_source, l, mip_level).rgb * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+    return vec4<f32>(color / max(weight, 0.0001), 1.0);
+}
+
+fn geometry_schlick_ggx_ibl(n_dot_v: f32, roughness: f32) -> f32 {
+    let k = roughness * roughness / 2.0;
+    return n_dot_v / (n_dot_v * (1.0 - k) + k);
+}
+
+// scale and bias to F0 for every (n_dot_v, roughness) (split sum, second half)
+@fragment
+fn fs_brdf_lut(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let n_dot_v = max(in.uv.x, 0.001);
+    let roughness = in.uv.y;
+    let view = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+    let normal = vec3<f32>(0.0, 0.0, 1.0);
+
+    var scale = 0.0;
+    var bias = 0.0;
+    for (var i = 0u; i < params.sample_count; i++) {
+        let h = importance_sample_ggx(hammersley(i, params.sample_count), normal, roughness);
+        let l = normalize(2.0 * dot(view, h) * h - view);
+        let n_dot_l = max(l.z, 0.0);
+        let n_dot_h = max(h.z, 0.0);
+        let v_dot_h = max(dot(view, h), 0.0);
+        if n_dot_l > 0.0 {
+            let g = geometry_schlick_ggx_ibl(n_dot_v, roughness) * geometry_schlick_ggx_ibl(n_dot_l, roughness);
+            let g_vis = g * v_dot_h / max(n_dot_h * n_dot_v, 0.0001);
+            let fc = pow(1.0 - v_dot_h, 5.0);
+            scale += (1.0 - fc) * g_vis;
+            bias += fc * g_vis;
+        }
+    }
+    return vec4<f32>(scale, bias, 0.0, 1.0) / vec4<f32>(f32(params.sample_count), f32(params.sample_count), 1.0, 1.0);
+}
@@ -0,0 +1,122 @@
+// Physically based shading shared by the forward shader and the deferred lighting passes;
+// prepended to them together with the camera and lighting bindings they have in common
+
+struct CameraUniform {
+    view_position: vec4<f32>, // depth of the background in w, 1 or 0 with reverse-Z
+    view_proj: mat4x4<f32>,
+    inv_view_proj: mat4x4<f32>,
+};
+
+struct LightUniform {
+    direction: vec4<f32>,
+    color: vec4<f32>, // intensity in w
+};
+
+struct PointLight {
+    position: vec4<f32>, // radius in w
+    color: vec4<f32>, // intensity in w
+};
+
+// light::MAX_FORWARD_POINT_LIGHTS
+struct PointLightsUniform {
+    lights: array<PointLight, 16>,
+    count: u32,
+};
+
+@group(1) @binding(0) // 1.
+var<uniform> camera: CameraUniform;
+
+@group(2) @binding(0)
+var<uniform> light: LightUniform;
+@group(2) @binding(1)
+var t_irradiance: texture_cube<f32>;
+@group(2) @binding(2)
+var t_prefiltered: texture_cube<f32>;
+@group(2) @binding(3)
+var t_brdf_lut: texture_2d<f32>;
+@group(2) @binding(4)
+var s_environment: sampler;
+@group(2) @binding(5) // forward path only; the deferred path draws a volume per light
+var<uniform> point_lights: PointLightsUniform;
+
+const PI: f32 = 3.14159265359;
+const MAX_REFLECTION_LOD: f32 = 4.0; // ibl::Environment::PREFILTERED_MIP_LEVELS - 1
+
+// everything the lighting needs to know about a point on a surface
+struct Surface {
+    position: vec3<f32>,
+    normal: vec3<f32>,
+    albedo: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    occlusion: f32,
+};
+
+// Cook-Torrance terms
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+fn geometry_schlick_ggx(n_dot_v: f32, roughness: f32) -> f32 {
+    let r = roughness + 1.0;
+    let k = r * r / 8.0;
+    return n_dot_v / (n_dot_v * (1.0 - k) + k);
+}
+
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    return geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+fn fresnel_schlick_roughness(cos_theta: f32, f0: vec3<f32>, roughness: f32) -> vec3<f32> {
+    return f0 + (max(vec3<f32>(1.0 - roughness), f0) - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// light arriving from direction l with the given radiance
+fn direct_light(surface: Surface, l: vec3<f32>, radiance: vec3<f32>) -> vec3<f32> {
+    let n = surface.normal;
+    let v = normalize(camera.view_position.xyz - surface.position);
+    let n_dot_v = max(dot(n, v), 0.0001);
+    let f0 = mix(vec3<f32>(0.04), surface.albedo, surface.metallic);
+    let h = normalize(v + l);
+    let n_dot_l = max(dot(n, l), 0.0);
+    let f = fresnel_schlick(max(dot(h, v), 0.0), f0);
+    let specular = distribution_ggx(max(dot(n, h), 0.0), surface.roughness) * geometry_smith(n_dot_v, n_dot_l, surface.roughness) * f
+        / (4.0 * n_dot_v * n_dot_l + 0.0001);
+    let k_d = (vec3<f32>(1.0) - f) * (1.0 - surface.metallic);
+    return (k_d * surface.albedo / PI + specular) * radiance * n_dot_l;
+}
+
+fn directional_light(surface: Surface) -> vec3<f32> {
+    return direct_light(surface, normalize(-light.direction.xyz), light.color.rgb * light.color.w);
+}
+
+// inverse square falloff, windowed to reach zero at the light radius
+fn point_light(surface: Surface, point: PointLight) -> vec3<f32> {
+    let to_light = point.position.xyz - surface.position;
+    let distance = length(to_light);
+    let falloff = clamp(1.0 - pow(distance / point.position.w, 4.0), 0.0, 1.0);
+    let attenuation = falloff * falloff / (distance * distance + 1.0);
+    return direct_light(surface, to_light / max(distance, 0.0001), point.color.rgb * point.color.w * attenuation);
+}
+
+// image-based ambient light
+fn ambient_light(surface: Surface) -> vec3<f32> {
+    let n = surface.normal;
+    let v = normalize(camera.view_position.xyz - surface.position);
+    let n_dot_v = max(dot(n, v), 0.0001);
+    let f0 = mix(vec3<f32>(0.04), surface.albedo, surface.metallic);
+    let f_ambient = fresnel_schlick_roughness(n_dot_v, f0, surface.roughness);
+    let k_d_ambient = (vec3<f32>(1.0) - f_ambient) * (1.0 - surface.metallic);
+    let diffuse_ambient = textureSampleLevel(t_irradiance, s_environment, n, 0.0).rgb * surface.albedo;
+    let prefiltered = textureSampleLevel(t_prefiltered, s_environment, reflect(-v, n), surface.roughness * MAX_REFLECTION_LOD).rgb;
+    let brdf = textureSampleLevel(t_brdf_lut, s_environment, vec2<f32>(n_dot_v, surface.roughness), 0.0).rg;
+    let specular_ambient = prefiltered * (f_ambient * brdf.x + brdf.y);
+    return (k_d_ambient * diffuse_ambient + specular_ambient) * surface.occlusion;
+}
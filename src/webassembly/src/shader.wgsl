@@ -1,44 +1,94 @@
+// pbr.wgsl (camera, lights and shading functions) is prepended when the module is created
+
 // Data types
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) joints: vec4<u32>,
+    @location(4) weights: vec4<f32>, // all zero for vertices that don't follow the skeleton
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
+    @location(1) world_position: vec3<f32>,
+    @location(2) world_normal: vec3<f32>,
+    @location(3) screen_position: vec4<f32>, // clip position, for looking up screen-space ambient occlusion
+    @location(4) tint: vec4<f32>,
+    @location(5) @interpolate(flat) custom: vec4<f32>, // model::Instance::custom, free for custom shaders
 };
 
-struct CameraUniform {
-    view_proj: mat4x4<f32>,
+struct InstanceInput {
+    @location(5) index: u32, // into instances
 };
 
-struct InstanceInput {
-    @location(5) model_matrix_0: vec4<f32>,
-    @location(6) model_matrix_1: vec4<f32>,
-    @location(7) model_matrix_2: vec4<f32>,
-    @location(8) model_matrix_3: vec4<f32>,
+// model::InstanceRaw
+struct Instance {
+    model: mat4x4<f32>,
+    normal: mat3x3<f32>,
+    color: vec4<f32>,
+    custom: vec4<f32>,
+    pose: u32,
+    mesh: u32, // index into State's meshes
+};
+
+// animation::JointBuffer
+struct Skinning {
+    joint_count: u32, // 0 without a skeleton
+    pose_count: u32,
+    joint_matrices: array<mat4x4<f32>>, // pose_count poses of joint_count each
 };
 
+@group(1) @binding(1) // next to the camera, since every other group is taken
+var<storage, read> skinning: Skinning;
+@group(1) @binding(2) // every instance, drawn by index so culling only has to write indices
+var<storage, read> instances: array<Instance>;
+
 // BEFORE VERTEX FUNCTION:
 // Input Assembly: read vertex + index buffer and gather vertex for each index
 // Use 'vertex pulling': cache result of vertex function when vertex used more than once
 
-@group(1) @binding(0) // 1.
-var<uniform> camera: CameraUniform;
+// the joint matrices of the instance's pose by the vertex's weights, normalized; identity without
+// weights or a skeleton. Poses and joints past the end count as the last one
+fn skin_matrix(VERTEX_IN: VertexInput, pose: u32) -> mat4x4<f32> {
+    let total = dot(VERTEX_IN.weights, vec4<f32>(1.0));
+    if total == 0.0 || skinning.joint_count == 0u {
+        return mat4x4<f32>(
+            vec4<f32>(1.0, 0.0, 0.0, 0.0),
+            vec4<f32>(0.0, 1.0, 0.0, 0.0),
+            vec4<f32>(0.0, 0.0, 1.0, 0.0),
+            vec4<f32>(0.0, 0.0, 0.0, 1.0),
+        );
+    }
+    let first = min(pose, skinning.pose_count - 1u) * skinning.joint_count;
+    var matrix = mat4x4<f32>(vec4<f32>(0.0), vec4<f32>(0.0), vec4<f32>(0.0), vec4<f32>(0.0));
+    for (var i = 0; i < 4; i += 1) {
+        let joint = min(VERTEX_IN.joints[i], skinning.joint_count - 1u);
+        matrix += skinning.joint_matrices[first + joint] * VERTEX_IN.weights[i];
+    }
+    return matrix * (1.0 / total);
+}
 
 @vertex
-fn vertex(VERTEX_IN: VertexInput, INSTANCE: InstanceInput) -> VertexOutput {
-    let model_matrix = mat4x4<f32>(
-        INSTANCE.model_matrix_0,
-        INSTANCE.model_matrix_1,
-        INSTANCE.model_matrix_2,
-        INSTANCE.model_matrix_3,
-    );
+fn vertex(VERTEX_IN: VertexInput, INSTANCE_IN: InstanceInput) -> VertexOutput {
+    let INSTANCE = instances[INSTANCE_IN.index];
+    let model_matrix = INSTANCE.model;
+    let normal_matrix = INSTANCE.normal;
+    let skin = skin_matrix(VERTEX_IN, INSTANCE.pose);
+    let world_position = model_matrix * skin * vec4<f32>(VERTEX_IN.position, 1.0);
     var VERTEX_OUT: VertexOutput;
     VERTEX_OUT.tex_coords = VERTEX_IN.tex_coords;
-    VERTEX_OUT.clip_position = camera.view_proj * model_matrix * vec4<f32>(VERTEX_IN.position, 1.0);
+    VERTEX_OUT.world_position = world_position.xyz;
+    // the inverse transpose keeps normals perpendicular to surfaces under non-uniform scale; joints
+    // are expected to scale uniformly, so their own matrices do
+    let skinned_normal = mat3x3<f32>(skin[0].xyz, skin[1].xyz, skin[2].xyz) * VERTEX_IN.normal;
+    VERTEX_OUT.world_normal = normal_matrix * skinned_normal;
+    VERTEX_OUT.clip_position = camera.view_proj * world_position;
+    VERTEX_OUT.screen_position = VERTEX_OUT.clip_position;
+    VERTEX_OUT.tint = INSTANCE.color;
+    VERTEX_OUT.custom = INSTANCE.custom;
     return VERTEX_OUT;
 }
 
@@ -49,14 +99,103 @@ fn vertex(VERTEX_IN: VertexInput, INSTANCE: InstanceInput) -> VertexOutput {
 // Rasterization: convert each primitive into set of fragments
 // Depth + Stencil test: discard fragments on depth/stencil value; might also be done after fragment function
 
+struct MaterialUniform {
+    base_color: vec4<f32>,
+    emissive: vec4<f32>,
+    metallic: f32,
+    roughness: f32,
+    occlusion_strength: f32,
+};
+
 @group(0) @binding(0) // group index from set_bind_group(), binding index from BindGroupLayout and BindGroup
-var t_diffuse: texture_2d<f32>;
+var<uniform> material: MaterialUniform;
 @group(0) @binding(1)
-var s_diffuse: sampler;
+var s_material: sampler;
+@group(0) @binding(2)
+var t_base_color: texture_2d<f32>;
+@group(0) @binding(3)
+var t_metallic_roughness: texture_2d<f32>;
+@group(0) @binding(4)
+var t_occlusion: texture_2d<f32>;
+@group(0) @binding(5)
+var t_emissive: texture_2d<f32>;
+
+@group(3) @binding(0)
+var t_ssao: texture_2d<f32>;
+@group(3) @binding(1)
+var s_ssao: sampler;
+
+// material inputs at this fragment
+fn material_surface(VERTEX: VertexOutput) -> Surface {
+    let metallic_roughness = textureSample(t_metallic_roughness, s_material, VERTEX.tex_coords);
+    var surface: Surface;
+    surface.position = VERTEX.world_position;
+    surface.normal = normalize(VERTEX.world_normal);
+    surface.albedo = (textureSample(t_base_color, s_material, VERTEX.tex_coords) * material.base_color * VERTEX.tint).rgb;
+    surface.metallic = material.metallic * metallic_roughness.b;
+    surface.roughness = clamp(material.roughness * metallic_roughness.g, 0.04, 1.0);
+    surface.occlusion = mix(1.0, textureSample(t_occlusion, s_material, VERTEX.tex_coords).r, material.occlusion_strength);
+    return surface;
+}
 
+fn material_emissive(VERTEX: VertexOutput) -> vec3<f32> {
+    return textureSample(t_emissive, s_material, VERTEX.tex_coords).rgb * material.emissive.rgb;
+}
+
+// forward path: all lighting in one pass
 @fragment
 fn fragment(VERTEX: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, VERTEX.tex_coords);
+    let alpha = (textureSample(t_base_color, s_material, VERTEX.tex_coords) * material.base_color * VERTEX.tint).a;
+    var surface = material_surface(VERTEX);
+    let screen_uv = VERTEX.screen_position.xy / VERTEX.screen_position.w * vec2<f32>(0.5, -0.5) + 0.5;
+    surface.occlusion *= textureSample(t_ssao, s_ssao, screen_uv).r;
+
+    var color = ambient_light(surface) + directional_light(surface) + material_emissive(VERTEX);
+    for (var i = 0u; i < point_lights.count; i += 1u) {
+        color += point_light(surface, point_lights.lights[i]);
+    }
+    return vec4<f32>(color, alpha);
+}
+
+// deferred path: material inputs go to the G-buffer, lighting happens in later passes
+struct GBufferOutput {
+    @location(0) albedo: vec4<f32>, // occlusion in a
+    @location(1) normal: vec4<f32>,
+    @location(2) material: vec4<f32>, // metallic, roughness
+    @location(3) emissive: vec4<f32>, // written straight into the HDR target the lights are added to
+};
+
+@fragment
+fn fs_gbuffer(VERTEX: VertexOutput) -> GBufferOutput {
+    let surface = material_surface(VERTEX);
+    var out: GBufferOutput;
+    out.albedo = vec4<f32>(surface.albedo, surface.occlusion);
+    out.normal = vec4<f32>(surface.normal, 0.0);
+    out.material = vec4<f32>(surface.metallic, surface.roughness, 0.0, 0.0);
+    out.emissive = vec4<f32>(material_emissive(VERTEX), 1.0);
+    return out;
+}
+
+// ID buffer for picking: the index of each instance plus one, so 0 is the background
+
+struct IdOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) @interpolate(flat) id: u32,
+};
+
+@vertex
+fn vs_id(VERTEX_IN: VertexInput, INSTANCE_IN: InstanceInput) -> IdOutput {
+    let INSTANCE = instances[INSTANCE_IN.index];
+    let world_position = INSTANCE.model * skin_matrix(VERTEX_IN, INSTANCE.pose) * vec4<f32>(VERTEX_IN.position, 1.0);
+    var out: IdOutput;
+    out.clip_position = camera.view_proj * world_position;
+    out.id = INSTANCE_IN.index + 1u;
+    return out;
+}
+
+@fragment
+fn fs_id(in: IdOutput) -> @location(0) u32 {
+    return in.id;
 }
 
 // AFTER FRAGMENT FUNCTION:
@@ -0,0 +1,99 @@
+// Screen-space ambient occlusion: view-space positions are reconstructed from the depth buffer,
+// normals from neighbouring depths, and a hemisphere of samples around each point is tested
+// against the depth buffer
+
+struct SsaoUniform {
+    proj: mat4x4<f32>,
+    inv_proj: mat4x4<f32>,
+    kernel: array<vec4<f32>, 64>, // hemisphere samples around +z, only the first sample_count are used
+    radius: f32, // sample radius in view space units
+    bias: f32, // depth bias against self occlusion
+    intensity: f32, // exponent applied to the result
+    sample_count: u32,
+    far_depth: f32, // what the depth buffer is cleared to
+};
+
+@group(0) @binding(0)
+var t_depth: texture_2d<f32>; // depth bound as unfilterable float, readable on every backend
+@group(0) @binding(1)
+var<uniform> ssao: SsaoUniform;
+
+const PI: f32 = 3.14159265359;
+
+fn load_depth(uv: vec2<f32>) -> f32 {
+    let size = vec2<i32>(textureDimensions(t_depth));
+    let coords = clamp(vec2<i32>(uv * vec2<f32>(size)), vec2<i32>(0), size - 1);
+    return textureLoad(t_depth, coords, 0).r;
+}
+
+fn view_position(uv: vec2<f32>) -> vec3<f32> {
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, load_depth(uv), 1.0);
+    let view = ssao.inv_proj * ndc;
+    return view.xyz / view.w;
+}
+
+fn hash(p: vec2<u32>) -> f32 {
+    var h = p.x * 1597334677u ^ p.y * 3812015801u;
+    h = h * 1597334677u;
+    return f32(h >> 8u) / 16777216.0;
+}
+
+@fragment
+fn fs_ssao(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    // nothing to occlude on the background
+    if load_depth(in.uv) == ssao.far_depth {
+        return vec4<f32>(1.0);
+    }
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_depth));
+    let p = view_position(in.uv);
+
+    // normal from neighbouring depths; the smaller difference on each axis keeps edges sharp
+    let right = view_position(in.uv + vec2<f32>(texel.x, 0.0)) - p;
+    let left = p - view_position(in.uv - vec2<f32>(texel.x, 0.0));
+    let down = view_position(in.uv + vec2<f32>(0.0, texel.y)) - p;
+    let up = p - view_position(in.uv - vec2<f32>(0.0, texel.y));
+    let dx = select(right, left, abs(left.z) < abs(right.z));
+    let dy = select(down, up, abs(up.z) < abs(down.z));
+    var n = normalize(cross(dx, dy));
+    if dot(n, p) > 0.0 {
+        n = -n; // face the camera at the origin
+    }
+
+    // random rotation around the normal; repeats every 4x4 pixels so the blur removes the pattern
+    let angle = hash(vec2<u32>(in.position.xy) % vec2<u32>(4u)) * 2.0 * PI;
+    let random = vec3<f32>(cos(angle), sin(angle), 0.0);
+    let t = normalize(random - n * dot(random, n));
+    let tbn = mat3x3<f32>(t, cross(n, t), n);
+
+    var occlusion = 0.0;
+    for (var i = 0u; i < ssao.sample_count; i += 1u) {
+        let sample_position = p + tbn * ssao.kernel[i].xyz * ssao.radius;
+        let offset = ssao.proj * vec4<f32>(sample_position, 1.0);
+        let sample_uv = offset.xy / offset.w * vec2<f32>(0.5, -0.5) + 0.5;
+        let scene_z = view_position(sample_uv).z;
+        // fade out occluders far outside the radius
+        let range = smoothstep(0.0, 1.0, ssao.radius / abs(p.z - scene_z));
+        occlusion += select(0.0, 1.0, scene_z >= sample_position.z + ssao.bias) * range;
+    }
+    let ao = pow(1.0 - occlusion / f32(ssao.sample_count), ssao.intensity);
+    return vec4<f32>(ao, ao, ao, 1.0);
+}
+
+@group(0) @binding(0)
+var t_ao: texture_2d<f32>;
+
+// 4x4 box blur matching the noise tile
+@fragment
+fn fs_blur(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(t_ao));
+    let center = vec2<i32>(in.position.xy);
+    var ao = 0.0;
+    for (var y = -2; y < 2; y += 1) {
+        for (var x = -2; x < 2; x += 1) {
+            let coords = clamp(center + vec2<i32>(x, y), vec2<i32>(0), size - 1);
+            ao += textureLoad(t_ao, coords, 0).r;
+        }
+    }
+    ao /= 16.0;
+    return vec4<f32>(ao, ao, ao, 1.0);
+}
@@ -1,12 +1,73 @@
 use wgpu::Color;
 use wgpu::util::DeviceExt;
-use winit::event::WindowEvent;
+use winit::event::{DeviceEvent, ElementState, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent};
 use winit::window::Window;
 
 mod model;
+mod mesh;
 mod resources;
 mod texture;
 mod camera;
+mod material;
+mod light;
+mod ibl;
+mod hdr;
+mod fullscreen;
+mod post;
+mod ssao;
+mod animation;
+mod deferred;
+mod bounds;
+mod bvh;
+mod culling;
+mod picking;
+mod instance_buffer;
+mod scene;
+mod ecs;
+mod scene_file;
+
+pub use animation::{AnimationClip, Channel, Interpolation, Joint, Keyframes, Pose, Skeleton};
+pub use bounds::{Aabb, BoundingSphere};
+pub use bvh::Bvh;
+pub use camera::{CameraKeyframe, CameraMode, CameraPath, CameraPathError, Projection};
+pub use culling::{CullingMode, CullingStats, Frustum};
+pub use ecs::{CameraComponent, Components, Entity, Light, MeshRenderer, System, World};
+pub use hdr::Tonemapper;
+pub use ibl::EnvironmentImage;
+pub use light::PointLight;
+pub use mesh::{Lod, Mesh, Primitive};
+pub use model::{Instance, Vertex};
+pub use picking::{Hit, IdPick, Ray};
+pub use resources::ResourceError;
+pub use material::MaterialFactors;
+pub use scene_file::{MeshSource, SceneCamera, SceneFile, SceneFileError, SceneInstance, SceneLight, SceneMaterial};
+pub use scene::{MeshBatch, NodeId, NodeMesh, SceneGraph, SceneGraphError, Transform};
+pub use ssao::{SsaoQuality, SsaoSettings};
+pub use texture::DepthMode;
+pub use post::{
+    Bloom, BloomSettings, ColorGrading, ColorGradingSettings, Fxaa, PostEffect, PostProcessStack, PostStage, Vignette,
+    VignetteSettings,
+};
+
+// how the scene is lit
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum RenderPath {
+    // every light is evaluated while drawing the scene; up to light::MAX_FORWARD_POINT_LIGHTS point lights
+    #[default]
+    Forward,
+    // the scene is drawn into a G-buffer once and each light is added afterwards; scales to many point lights
+    Deferred,
+}
+
+// options that are fixed once the State is created
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    pub render_path: RenderPath,
+    pub depth_mode: DepthMode, // ReverseZ avoids z-fighting far away
+    pub infinite_far_plane: bool, // the default perspective projection gets no far plane
+    pub culling: CullingMode,
+    pub id_buffer_picking: bool, // allows State::request_id_pick
+}
 
 pub struct State {
     surface: wgpu::Surface,
@@ -17,24 +78,71 @@ pub struct State {
     pub window: Window,
     bg_color: Color,
     render_pipeline: wgpu::RenderPipeline,
-    diffuse_bind_group: wgpu::BindGroup,
-    diffuse_texture: texture::Texture,
+    depth_prepass_pipeline: wgpu::RenderPipeline,
+    material: material::Material,
+    material_layout: wgpu::BindGroupLayout,
+    material_source: SceneMaterial, // the texture paths the material was loaded from, for scene files
+    light_uniform: light::LightUniform,
+    light_buffer: wgpu::Buffer,
+    lighting_bind_group: wgpu::BindGroup,
+    lighting_layout: wgpu::BindGroupLayout, // to bind a new environment
+    point_lights: Vec<light::PointLight>,
+    entity_lights: Vec<light::PointLight>, // from the world's Light components, drawn after point_lights
+    point_lights_buffer: wgpu::Buffer,
     camera: camera::Camera,
     camera_uniform: camera::CameraUniform,
     camera_buffer: wgpu::Buffer,
     camera_bind_group: wgpu::BindGroup,
+    camera_layout: wgpu::BindGroupLayout, // to bind a grown joint or instance buffer
     camera_controller: camera::CameraController,
+    camera_smoothing: f32,
+    camera_path: Option<camera::CameraPath>, // playing back instead of the controller
+    camera_path_time: f32,
     instances: Vec<model::Instance>,
-    instance_buffer: wgpu::Buffer,
-    vertex_buffer: wgpu::Buffer, // later additions in tutorials from here
-    index_buffer: wgpu::Buffer,
-    num_indices: u32,
+    instance_data: Vec<model::InstanceRaw>, // instances as uploaded, same order, then the scene graph's
+    scene_graph: SceneGraph,
+    scene_nodes: Vec<NodeId>, // the node of each instance after the flat ones
+    entities: Vec<Entity>, // the entity of each instance after the scene graph's
+    world: World,
+    systems: Vec<System>,
+    instance_buffer: instance_buffer::InstanceBuffer, // every instance, uploaded where it changed
+    visible_buffer: instance_buffer::InstanceBuffer<u32>, // indices of the instances that passed CPU culling, by level of detail
+    lod_ranges: Vec<Vec<std::ops::Range<u32>>>, // of each level of detail of each mesh in visible_buffer
+    bvh: Bvh, // world space bounds of instance_data as of the last update, for culling and picking
+    bvh_dirty: bool,
+    frustum_culling: bool,
+    culling_stats: CullingStats,
+    gpu_culling: Option<culling::GpuCulling>,
+    cursor_position: Option<glam::Vec2>, // in pixels, None while outside the window
+    click_position: Option<glam::Vec2>, // where the left button went down
+    selected_instance: Option<usize>,
+    id_buffer: Option<picking::IdBuffer>,
+    meshes: Vec<mesh::GpuMesh>, // what instances draw, by InstanceRaw::mesh
+    mesh_sources: Vec<Option<MeshSource>>, // of each mesh, None for meshes scene files can't describe
+    mesh_lods: usize, // levels of detail asked of generate_lods, for scene files
+    meshes_dirty: bool, // meshes changed since the scene graph and the world were extracted
+    skeleton: Option<Skeleton>, // what the meshes' joints and weights refer to
+    joint_matrices: Vec<Vec<glam::Mat4>>, // of each pose, see set_pose
+    pose_bounds: Vec<Vec<Aabb>>, // model space bounds around each mesh in each pose
+    joint_buffer: animation::JointBuffer,
+    joints_dirty: bool, // joint_matrices or the mesh changed since the last upload
     depth_texture: texture::Texture,
+    ssao: ssao::Ssao,
+    deferred: Option<deferred::DeferredRenderer>,
+    hdr: hdr::HdrPipeline,
+    post: post::PostProcessStack,
 }
 
 impl State {
+    const CAMERA_SPEED: f32 = 5.0; // units per second
+    const CLICK_TOLERANCE: f32 = 4.0; // pixels the cursor may move between press and release of a click
+
     // Creating some of the wgpu types requires async code
     pub async fn new(window: Window) -> Self {
+        Self::with_settings(window, Settings::default()).await
+    }
+
+    pub async fn with_settings(window: Window, settings: Settings) -> Self {
 
         // region: --- SETUP
         let size = window.inner_size();
@@ -91,54 +199,35 @@ impl State {
         surface.configure(&device, &config);
         // endregion: --- SETUP
 
-        // region: --- TEXTURES
-        // tutorial 3; not strictly needed to work
+        // region: --- MATERIAL
+        // tutorial 3; the happy tree is the base color of a PBR material
         let diffuse_bytes = include_bytes!("happy-tree.png"); // CHANGED!
         let diffuse_texture = texture::Texture::from_bytes(&device, &queue, diffuse_bytes, "happy-tree.png").unwrap();
 
-        let texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        // This should match the filterable field of the
-                        // corresponding Texture entry above.
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
-                label: Some("texture_bind_group_layout"),
-            });
-
-        let diffuse_bind_group = device.create_bind_group(
-            &wgpu::BindGroupDescriptor {
-                layout: &texture_bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                    }
-                ],
-                label: Some("diffuse_bind_group"),
-            }
+        let material_bind_group_layout = material::Material::bind_group_layout(&device);
+        let material = material::Material::new(
+            &device,
+            &queue,
+            &material_bind_group_layout,
+            "happy-tree",
+            material::MaterialFactors {
+                metallic: 0.0,
+                roughness: 0.5,
+                ..Default::default()
+            },
+            material::MaterialTextures {
+                base_color: Some(diffuse_texture),
+                ..Default::default()
+            },
         );
-
-        // endregion: --- TEXTURES
+        // how the material is saved in scene files; res/ has a copy of the texture
+        let material_source = SceneMaterial {
+            name: material.name.clone(),
+            factors: material.factors,
+            base_color_texture: Some("happy-tree.png".to_string()),
+            ..Default::default()
+        };
+        // endregion: --- MATERIAL
 
         // region: --- CAMERA
         let camera = camera::Camera {
@@ -150,11 +239,16 @@ impl State {
             // which way is "up"
             up: glam::Vec3::new(0.0, 1.0, 0.0),
             aspect: config.width as f32 / config.height as f32,
-            fovy: 45.0,
-            znear: 0.1,
-            zfar: 100.0,
+            projection: camera::Projection::Perspective {
+                fovy: 45.0,
+                znear: 0.1,
+                zfar: if settings.infinite_far_plane { f32::INFINITY } else { 100.0 },
+            },
+            depth_mode: settings.depth_mode,
         };
 
+        let camera_controller = camera::CameraController::Orbit(camera::OrbitController::new(Self::CAMERA_SPEED, &camera));
+
         let mut camera_uniform = camera::CameraUniform::new();
         camera_uniform.update_view_proj(&camera);
 
@@ -170,28 +264,125 @@ impl State {
             entries: &[
                 wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT, // fragment needs the eye position
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
                         min_binding_size: None,
                     },
                     count: None,
-                }
+                },
+                // skinned meshes' joint matrices, see animation::JointBuffer
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // every instance, which draws pick by index
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
             label: Some("camera_bind_group_layout"),
         });
-        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &camera_bind_group_layout,
+        let joint_buffer = animation::JointBuffer::new(&device);
+        // endregion: --- CAMERA
+
+        // region: --- LIGHTING
+        // one directional light plus image-based lighting from a prefiltered environment
+        let environment = ibl::Environment::new(&device, &queue);
+        let light_uniform = light::LightUniform::new(
+            glam::Vec3::new(-0.5, -1.0, -0.3), // same direction as the sun in the environment
+            glam::Vec3::new(1.0, 0.95, 0.9),
+            3.0,
+        );
+        let light_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Light Buffer"),
+                contents: bytemuck::cast_slice(&[light_uniform]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let point_lights = Vec::new();
+        let point_lights_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Point Lights Buffer"),
+                contents: bytemuck::cast_slice(&[light::PointLightsUniform::new(&point_lights)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let cube_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::Cube,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let lighting_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[
-                wgpu::BindGroupEntry {
+                wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    resource: camera_buffer.as_entire_binding(),
-                }
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                cube_entry(1), // irradiance
+                cube_entry(2), // prefiltered
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
-            label: Some("camera_bind_group"),
+            label: Some("lighting_bind_group_layout"),
         });
-        // endregion: --- CAMERA
+        let lighting_bind_group = create_lighting_bind_group(&device, &lighting_bind_group_layout, &light_buffer, &point_lights_buffer, &environment);
+        // endregion: --- LIGHTING
+
+        // region: --- MESH
+        // mesh 0, which the instances draw; more can be added
+        let meshes = vec![mesh::GpuMesh::new(&device, Mesh::pentagon(), Vec::new(), "Pentagon")];
+        // endregion: --- MESH
 
         // region: --- INSTANCES
         // instances to display and their relative positions
@@ -212,27 +403,49 @@ impl State {
 
                 model::Instance {
                     position, rotation,
+                    ..Default::default()
                 }
             })
         }).collect::<Vec<_>>();
         let instance_data = instances.iter().map(model::Instance::to_raw).collect::<Vec<_>>();
-        let instance_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Instance Buffer"),
-                contents: bytemuck::cast_slice(&instance_data),
-                usage: wgpu::BufferUsages::VERTEX,
-            }
-        );
+        // the vertex stage reads instances by the indices in a visible buffer, as does GPU culling
+        let mut instance_buffer = instance_buffer::InstanceBuffer::new(&device, "Instance Buffer", wgpu::BufferUsages::STORAGE, instance_data.len());
+        instance_buffer.write(&device, &queue, &instance_data);
+        let camera_bind_group = create_camera_bind_group(&device, &camera_bind_group_layout, &camera_buffer, &joint_buffer, &instance_buffer);
+        let bvh = Bvh::new(instance_data.iter().map(|instance| meshes[instance.mesh()].bounds.transform(&instance.model_matrix())).collect());
+        // rewritten with the indices of the visible ones every update
+        let visible_buffer = instance_buffer::InstanceBuffer::new(&device, "Visible Instance Buffer", wgpu::BufferUsages::VERTEX, instance_data.len());
+        let gpu_culling = match settings.culling {
+            CullingMode::Cpu => None,
+            CullingMode::Gpu => Some(culling::GpuCulling::new(
+                &device,
+                &queue,
+                config.width,
+                config.height,
+                &meshes,
+            )),
+        };
         // endregion: --- INSTANCES
 
+        // region: --- SSAO
+        // ambient occlusion is computed from a depth prepass before the scene is shaded
+        let ssao = ssao::Ssao::new(&device, config.width, config.height, ssao::SsaoSettings::default());
+        // endregion: --- SSAO
+
         // region: --- SHADER AND PIPELINE
-        let shader = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
+        // shading functions shared with the deferred lighting passes come first
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shader.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(format!("{}\n{}", include_str!("pbr.wgsl"), include_str!("shader.wgsl")).into()),
+        });
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
                 bind_group_layouts: &[
-                    &texture_bind_group_layout, // add texture to pipeline at group 0
+                    &material_bind_group_layout, // add material to pipeline at group 0
                     &camera_bind_group_layout, // add camera to pipeline at group 1
+                    &lighting_bind_group_layout, // add light and environment to pipeline at group 2
+                    ssao.output_layout(), // add ambient occlusion to pipeline at group 3
                 ], // inform pipeline of layout of bind groups; can be empty array
                 push_constant_ranges: &[],
             });
@@ -244,14 +457,14 @@ impl State {
                 entry_point: "vertex", // function in shader that is entry point for vertex shader
                 buffers: &[
                     model::Vertex::desc(), // description of vertex buffer and description on how to handle the raw [u8]
-                    model::InstanceRaw::desc(), // description of instances of this vertex buffer
+                    model::InstanceRaw::index_desc(), // description of instances of this vertex buffer
                 ],
             },
             fragment: Some(wgpu::FragmentState { // optional; needed to store color on surface
                 module: &shader,
                 entry_point: "fragment", // function that is entry point for fragment shader
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format, // use surface config format
+                    format: hdr::HdrPipeline::FORMAT, // scene is rendered in HDR, then tonemapped to the surface format
                     blend: Some(wgpu::BlendState::REPLACE), // just overwrite color
                     write_mask: wgpu::ColorWrites::ALL, // write to all colors:rgba,
                 })],
@@ -271,7 +484,7 @@ impl State {
             depth_stencil: Some(wgpu::DepthStencilState {
                 format: texture::Texture::DEPTH_FORMAT,
                 depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
+                depth_compare: settings.depth_mode.compare_equal(), // equal passes where the depth prepass already wrote
                 stencil: wgpu::StencilState::default(),
                 bias: wgpu::DepthBiasState::default(),
             }),
@@ -282,34 +495,86 @@ impl State {
             },
             multiview: None, // array texture stuff
         });
+
+        // same vertex stage without a fragment stage, only fills the depth buffer for SSAO
+        let depth_prepass_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Depth Prepass Pipeline Layout"),
+                bind_group_layouts: &[
+                    &material_bind_group_layout,
+                    &camera_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+        let depth_prepass_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Depth Prepass Pipeline"),
+            layout: Some(&depth_prepass_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vertex",
+                buffers: &[
+                    model::Vertex::desc(),
+                    model::InstanceRaw::index_desc(),
+                ],
+            },
+            fragment: None,
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: settings.depth_mode.compare(),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let deferred = match settings.render_path {
+            RenderPath::Forward => None,
+            RenderPath::Deferred => Some(deferred::DeferredRenderer::new(
+                &device,
+                config.width,
+                config.height,
+                settings.depth_mode,
+                &shader,
+                deferred::SceneLayouts {
+                    material: &material_bind_group_layout,
+                    camera: &camera_bind_group_layout,
+                    lighting: &lighting_bind_group_layout,
+                    ssao: ssao.output_layout(),
+                },
+            )),
+        };
+        let id_buffer = settings.id_buffer_picking.then(|| picking::IdBuffer::new(
+            &device,
+            config.width,
+            config.height,
+            settings.depth_mode,
+            &shader,
+            &material_bind_group_layout,
+            &camera_bind_group_layout,
+        ));
         // endregion: --- SHADER AND PIPELINE
 
         // region: --- DEPTH
-        let depth_texture = texture::Texture::create_depth_texture(&device, &config, "depth_texture");
+        let depth_texture = texture::Texture::create_depth_texture(&device, &config, settings.depth_mode, "depth_texture");
         // endregion: --- DEPTH
 
-        // region: --- BUFFERS
-        // tutorial 2; not strictly needed to work
-        let vertex_buffer = device.create_buffer_init( // expects a &[u8] -> convert vertices
-                                                       &wgpu::util::BufferInitDescriptor {
-                                                           label: Some("Vertex Buffer"),
-                                                           contents: bytemuck::cast_slice(model::VERTICES),
-                                                           usage: wgpu::BufferUsages::VERTEX,
-                                                       }
-        );
+        // region: --- HDR
+        let hdr = hdr::HdrPipeline::new(&device, &config);
+        // endregion: --- HDR
 
-        let index_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Index Buffer"),
-                contents: bytemuck::cast_slice(model::INDICES),
-                usage: wgpu::BufferUsages::INDEX,
-            }
-        );
-        // endregion: --- BUFFERS
+        // region: --- POST PROCESSING
+        let post = post::PostProcessStack::with_default_effects(&device, &queue, &config);
+        // endregion: --- POST PROCESSING
 
         // region: --- MODELS
         // let obj_model =
-        //     resources::load_model("cube.obj", &device, &queue, &texture_bind_group_layout)
+        //     resources::load_model("cube.obj", &device, &queue, &material_bind_group_layout)
         //         .await
         //         .unwrap();
         // endregion: --- MODELS
@@ -323,19 +588,59 @@ impl State {
             size,
             bg_color: Color::BLACK,
             render_pipeline,
-            diffuse_bind_group,
-            diffuse_texture,
+            depth_prepass_pipeline,
+            material,
+            material_layout: material_bind_group_layout,
+            material_source,
+            light_uniform,
+            light_buffer,
+            lighting_bind_group,
+            lighting_layout: lighting_bind_group_layout,
+            point_lights,
+            entity_lights: Vec::new(),
+            point_lights_buffer,
             camera,
             camera_uniform,
             camera_buffer,
             camera_bind_group,
-            camera_controller: camera::CameraController::new(0.2),
+            camera_layout: camera_bind_group_layout,
+            camera_controller,
+            camera_smoothing: 0.0,
+            camera_path: None,
+            camera_path_time: 0.0,
             instances,
+            instance_data,
+            scene_graph: SceneGraph::new(),
+            scene_nodes: Vec::new(),
+            entities: Vec::new(),
+            world: World::new(),
+            systems: Vec::new(),
             instance_buffer,
-            vertex_buffer, // later additions in tutorials from here
-            index_buffer,
-            num_indices: model::INDICES.len() as u32,
+            visible_buffer,
+            lod_ranges: Vec::new(),
+            bvh,
+            bvh_dirty: false,
+            frustum_culling: true,
+            culling_stats: CullingStats::default(),
+            gpu_culling,
+            cursor_position: None,
+            click_position: None,
+            selected_instance: None,
+            id_buffer,
+            meshes,
+            mesh_sources: vec![Some(MeshSource::Builtin)],
+            mesh_lods: 0,
+            meshes_dirty: false,
+            skeleton: None,
+            joint_matrices: Vec::new(),
+            pose_bounds: Vec::new(),
+            joint_buffer,
+            joints_dirty: false,
             depth_texture,
+            ssao,
+            deferred,
+            hdr,
+            post,
         }
     }
 
@@ -348,9 +653,22 @@ impl State {
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
+            self.camera.aspect = new_size.width as f32 / new_size.height as f32;
             // both need to go after config width and height to have the same and not crash
             self.surface.configure(&self.device, &self.config);
-            self.depth_texture = texture::Texture::create_depth_texture(&self.device, &self.config, "depth_texture");
+            self.depth_texture = texture::Texture::create_depth_texture(&self.device, &self.config, self.camera.depth_mode, "depth_texture");
+            self.ssao.resize(&self.device, new_size.width, new_size.height);
+            if let Some(deferred) = &mut self.deferred {
+                deferred.resize(&self.device, new_size.width, new_size.height);
+            }
+            if let Some(gpu_culling) = &mut self.gpu_culling {
+                gpu_culling.resize(&self.device, new_size.width, new_size.height);
+            }
+            if let Some(id_buffer) = &mut self.id_buffer {
+                id_buffer.resize(&self.device, new_size.width, new_size.height);
+            }
+            self.hdr.resize(&self.device, new_size.width, new_size.height);
+            self.post.resize(&self.device, new_size.width, new_size.height);
         }
     }
 
@@ -358,67 +676,1091 @@ impl State {
         self.camera_controller.process_events(event);
         match event {
             WindowEvent::CursorMoved { position, .. } => {
-                self.bg_color = wgpu::Color {
-                    r: position.x as f64 / self.size.width as f64,
-                    g: position.y as f64 / self.size.height as f64,
-                    b: 1.0,
-                    a: 1.0,
+                // the background stays as set_background or a scene file left it
+                self.cursor_position = Some(glam::Vec2::new(position.x as f32, position.y as f32));
+                true
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor_position = None;
+                false
+            }
+            // a left click that didn't drag the camera selects what is under the cursor
+            WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => {
+                match state {
+                    ElementState::Pressed => self.click_position = self.cursor_position,
+                    ElementState::Released => {
+                        let dragged = match (self.click_position.take(), self.cursor_position) {
+                            (Some(pressed), Some(released)) => pressed.distance(released) > Self::CLICK_TOLERANCE,
+                            _ => true,
+                        };
+                        if !dragged {
+                            self.selected_instance = self.pick_at_cursor().map(|hit| hit.instance);
+                        }
+                    }
+                }
+                true
+            }
+            // Tab switches between orbiting and flying
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::Tab),
+                    ..
+                },
+                ..
+            } => {
+                let mode = match self.camera_mode() {
+                    CameraMode::Orbit => CameraMode::Fly,
+                    CameraMode::Fly => CameraMode::Orbit,
                 };
+                self.set_camera_mode(mode);
+                true
+            }
+            // F frames the whole scene
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::F),
+                    ..
+                },
+                ..
+            } => {
+                if let Some(bounds) = self.scene_bounds() {
+                    self.frame_bounds(bounds);
+                }
                 true
             }
             _ => false
         }
     }
 
-    pub fn update(&mut self) {
-        self.camera_controller.update_camera(&mut self.camera);
+    pub fn device_input(&mut self, event: &DeviceEvent) -> bool {
+        self.camera_controller.process_device_events(event)
+    }
+
+    pub fn camera_mode(&self) -> CameraMode {
+        self.camera_controller.mode()
+    }
+
+    // flying grabs and hides the cursor so the mouse can turn freely
+    pub fn set_camera_mode(&mut self, mode: CameraMode) {
+        if mode == self.camera_mode() {
+            return;
+        }
+        self.reset_camera_controller(mode);
+        self.grab_cursor(mode == CameraMode::Fly);
+    }
+
+    // a fresh controller that starts from where the camera is now
+    fn reset_camera_controller(&mut self, mode: CameraMode) {
+        self.camera_controller = match mode {
+            CameraMode::Orbit => camera::CameraController::Orbit(camera::OrbitController::new(Self::CAMERA_SPEED, &self.camera)),
+            CameraMode::Fly => camera::CameraController::Fly(camera::FlyController::new(Self::CAMERA_SPEED, 0.003, &self.camera)),
+        };
+        self.camera_controller.set_smoothing(self.camera_smoothing);
+    }
+
+    // moves the camera along the path from its first keyframe, ignoring camera input and the world's
+    // CameraComponents until the path ends; looping paths play until stop_camera_path
+    pub fn play_camera_path(&mut self, path: CameraPath) {
+        self.camera_path_time = path.keyframes().first().map_or(0.0, |keyframe| keyframe.time);
+        self.camera_path = Some(path);
+    }
+
+    // hands the camera back to the controller where the path left it
+    pub fn stop_camera_path(&mut self) {
+        if self.camera_path.take().is_some() {
+            self.reset_camera_controller(self.camera_mode());
+        }
+    }
+
+    pub fn is_playing_camera_path(&self) -> bool {
+        self.camera_path.is_some()
+    }
+
+    // the camera as it is now, for recording a path
+    pub fn camera_keyframe(&self, time: f32) -> CameraKeyframe {
+        CameraKeyframe::from_camera(time, &self.camera)
+    }
+
+    pub fn projection(&self) -> Projection {
+        self.camera.projection
+    }
+
+    // the depth mode stays the one from Settings; it is applied on top of the projection, and
+    // InfinitePerspectiveReverseZ falls back to an infinite Perspective without reverse-Z
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.camera.projection = projection.for_depth_mode(self.camera.depth_mode);
+    }
+
+    pub fn depth_mode(&self) -> DepthMode {
+        self.camera.depth_mode
+    }
+
+    // seconds for the camera to speed up to and slow down from key movement; 0 turns smoothing off
+    pub fn set_camera_smoothing(&mut self, smoothing: f32) {
+        self.camera_smoothing = smoothing;
+        self.camera_controller.set_smoothing(smoothing);
+    }
+
+    // orbits around the center of the bounds at a distance where all of it is in view
+    pub fn frame_bounds(&mut self, bounds: Aabb) {
+        self.set_camera_mode(CameraMode::Orbit);
+        if let camera::CameraController::Orbit(controller) = &mut self.camera_controller {
+            controller.frame(&mut self.camera, &bounds);
+        }
+    }
+
+    // bounds of every instance; None without instances
+    pub fn scene_bounds(&self) -> Option<Aabb> {
+        (0..self.instance_data.len())
+            .map(|index| self.instance_bounds(index))
+            .reduce(|a, b| a.union(&b))
+    }
+
+    // world space bounds of an instance, counted like Hit::instance; panics if index is out of bounds
+    pub fn instance_bounds(&self, index: usize) -> Aabb {
+        let instance = &self.instance_data[index];
+        self.model_bounds(instance.mesh(), instance.pose()).transform(&instance.model_matrix())
+    }
+
+    // model space bounds of a mesh in a pose, see set_pose
+    fn model_bounds(&self, mesh: usize, pose: u32) -> Aabb {
+        let poses = self.pose_bounds.get(mesh).map_or(&[][..], Vec::as_slice);
+        match poses.len() {
+            0 => self.meshes[mesh].bounds,
+            count => poses[(pose as usize).min(count - 1)],
+        }
+    }
+
+    // the tree over every instance's bounds that culling and picking search, as of the last update;
+    // items are counted like Hit::instance, e.g. for collision tests with Bvh::query_aabb
+    pub fn spatial_index(&self) -> &Bvh {
+        &self.bvh
+    }
+
+    // the instances at least partly inside the frustum as of the last update, counted like
+    // Hit::instance and in that order
+    pub fn instances_in_frustum(&self, frustum: &Frustum) -> Vec<usize> {
+        let mut instances = Vec::new();
+        self.bvh.query_frustum(frustum, |index| instances.push(index));
+        instances.sort_unstable();
+        instances
+    }
+
+    // up to count instances by the distance from point to their bounds as of the last update, nearest
+    // first, see Bvh::query_nearest
+    pub fn nearest_instances(&self, point: glam::Vec3, count: usize) -> Vec<(usize, f32)> {
+        self.bvh.query_nearest(point, count)
+    }
+
+    fn grab_cursor(&self, grab: bool) {
+        use winit::window::CursorGrabMode;
+        let result = if grab {
+            // not every platform can lock the cursor in place
+            self.window.set_cursor_grab(CursorGrabMode::Locked)
+                .or_else(|_| self.window.set_cursor_grab(CursorGrabMode::Confined))
+        } else {
+            self.window.set_cursor_grab(CursorGrabMode::None)
+        };
+        if let Err(e) = result {
+            eprintln!("Cursor grab failed: {:?}", e);
+        }
+        self.window.set_cursor_visible(!grab);
+    }
+
+    // the clear color behind the scene; scene files save it
+    pub fn background(&self) -> Color {
+        self.bg_color
+    }
+
+    pub fn set_background(&mut self, color: Color) {
+        self.bg_color = color;
+    }
+
+    pub fn set_light(&mut self, direction: glam::Vec3, color: glam::Vec3, intensity: f32) {
+        self.light_uniform = light::LightUniform::new(direction, color, intensity);
+        self.queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[self.light_uniform]));
+    }
+
+    // image-based lighting from an HDR image in place of the procedural sky; the directional light
+    // stays as it is, so point it along the image's sun with set_light. Fails if the pixels don't
+    // fill the image's size
+    pub fn set_environment(&mut self, image: EnvironmentImage) -> Result<(), texture::TextureError> {
+        let source = image.to_texture(&self.device, &self.queue)?;
+        let environment = ibl::Environment::from_source(&self.device, &self.queue, image.source(&source));
+        self.lighting_bind_group = create_lighting_bind_group(&self.device, &self.lighting_layout, &self.light_buffer, &self.point_lights_buffer, &environment);
+        Ok(())
+    }
+
+    // the forward path uses the first light::MAX_FORWARD_POINT_LIGHTS of them, then the world's
+    pub fn set_point_lights(&mut self, lights: Vec<PointLight>) {
+        self.point_lights = lights;
+        self.upload_point_lights();
+    }
+
+    fn upload_point_lights(&mut self) {
+        let lights = [self.point_lights.as_slice(), self.entity_lights.as_slice()].concat();
+        self.queue.write_buffer(&self.point_lights_buffer, 0, bytemuck::cast_slice(&[light::PointLightsUniform::new(&lights)]));
+        if let Some(deferred) = &mut self.deferred {
+            deferred.set_point_lights(&self.device, &self.queue, &lights);
+        }
+    }
+
+    pub fn point_lights(&self) -> &[PointLight] {
+        &self.point_lights
+    }
+
+    // exposure in stops, applied before tonemapping
+    pub fn set_exposure(&mut self, exposure: f32) {
+        self.hdr.set_exposure(&self.queue, exposure);
+    }
+
+    pub fn ssao_settings(&self) -> SsaoSettings {
+        self.ssao.settings()
+    }
+
+    pub fn set_ssao_settings(&mut self, settings: SsaoSettings) {
+        self.ssao.set_settings(&self.device, &self.queue, settings);
+    }
+
+    pub fn set_tonemapper(&mut self, tonemapper: Tonemapper) {
+        self.hdr.set_tonemapper(&self.queue, tonemapper);
+    }
+
+    // effects run in order; toggle, reorder or add custom ones through the stack
+    pub fn post_effects_mut(&mut self) -> &mut PostProcessStack {
+        &mut self.post
+    }
+
+    // LUT strip PNG for the color grading effect; also enables it
+    pub fn set_color_grading_lut(&mut self, bytes: &[u8]) -> Result<(), texture::TextureError> {
+        if let Some(color_grading) = self.post.get_mut::<post::ColorGrading>() {
+            color_grading.set_lut_strip(&self.device, &self.queue, bytes)?;
+            self.post.set_enabled(post::ColorGrading::NAME, true);
+        }
+        Ok(())
+    }
+
+    // dt is the time since the previous update
+    pub fn update(&mut self, dt: std::time::Duration) {
+        match &self.camera_path {
+            Some(path) => {
+                self.camera_path_time += dt.as_secs_f32();
+                if let Some(keyframe) = path.sample(self.camera_path_time, self.camera.up) {
+                    self.camera.eye = keyframe.eye;
+                    self.camera.target = keyframe.target;
+                    self.camera.projection.set_fovy(keyframe.fovy);
+                }
+                if !path.looping && self.camera_path_time >= path.duration() {
+                    self.stop_camera_path();
+                }
+            }
+            None => self.camera_controller.update_camera(&mut self.camera, dt),
+        }
+        for system in &mut self.systems {
+            system(&mut self.world, dt);
+        }
+        self.extract_camera();
         self.camera_uniform.update_view_proj(&self.camera);
         self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.camera_uniform]));
+        self.ssao.update(&self.queue, &self.camera);
+        self.extract_instances();
+        if self.world.transforms.is_changed() || self.world.lights.is_changed() {
+            self.extract_lights();
+        }
+        self.world.clear_changed();
+        let generation = self.instance_buffer.generation();
+        self.instance_buffer.upload(&self.device, &self.queue, &self.instance_data);
+        let mut grown = self.instance_buffer.generation() != generation;
+        if self.joints_dirty {
+            grown |= self.upload_joints();
+            self.joints_dirty = false;
+        }
+        if grown {
+            self.camera_bind_group = create_camera_bind_group(&self.device, &self.camera_layout, &self.camera_buffer, &self.joint_buffer, &self.instance_buffer);
+        }
+        if self.bvh_dirty {
+            let bounds = (0..self.instance_data.len()).map(|index| self.instance_bounds(index)).collect();
+            self.bvh.update(bounds);
+            self.bvh_dirty = false;
+        }
+        match &mut self.gpu_culling {
+            Some(gpu_culling) => gpu_culling.update(&self.queue, &self.camera, self.frustum_culling, self.instance_data.len()),
+            None => self.cull_instances(),
+        }
     }
 
-    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?; // texture on the surface we will draw to
-        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default()); // view description; default
-        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Render Encoder"),
+    // hands the vertex shader every pose's joint matrices and fits bounds around each pose; true if
+    // the joint buffer grew and needs binding again
+    fn upload_joints(&mut self) -> bool {
+        let joint_count = self.skeleton.as_ref().map_or(0, |skeleton| skeleton.joints().len());
+        let grown = self.joint_buffer.write(&self.device, &self.queue, joint_count, &self.joint_matrices);
+        // a skinned vertex lies between where its joints would each move it, so the mesh bounds moved
+        // by every joint hold it; unweighted vertices stay inside the mesh bounds
+        self.pose_bounds = self.meshes.iter()
+            .map(|mesh| self.joint_matrices.iter()
+                .map(|matrices| matrices.iter().fold(mesh.bounds, |pose, matrix| pose.union(&mesh.bounds.transform(matrix))))
+                .collect())
+            .collect();
+        if let Some(gpu_culling) = &mut self.gpu_culling {
+            for (index, (mesh, poses)) in self.meshes.iter().zip(&self.pose_bounds).enumerate() {
+                let all = poses.iter().fold(mesh.bounds, |all, pose| all.union(pose));
+                gpu_culling.set_bounds(index, &all);
+            }
+        }
+        grown
+    }
+
+    // uploads the indices of only the instances in view, grouped by mesh and then by the level of
+    // detail their size on screen calls for, so everything after draws them in ranges
+    fn cull_instances(&mut self) {
+        let view_proj = self.camera.build_view_projection_matrix();
+        let projection_scale = self.camera.build_projection_matrix().y_axis.y;
+        let frustum = self.frustum_culling.then(|| Frustum::from_view_proj(&view_proj));
+        // the tree rejects whole groups by their boxes; the spheres catch some boxes near the corners
+        let visible = match &frustum {
+            Some(frustum) => self.instances_in_frustum(frustum),
+            None => (0..self.instance_data.len()).collect(),
+        };
+        let mut batches = self.meshes.iter().map(|mesh| vec![Vec::new(); mesh.lods.len()]).collect::<Vec<_>>();
+        for index in visible {
+            let instance = &self.instance_data[index];
+            let mesh = &self.meshes[instance.mesh()];
+            // the sphere fits the mesh in its bind pose only
+            if let (Some(frustum), None) = (&frustum, &self.skeleton) {
+                if !frustum.intersects_sphere(&mesh.sphere.transform(&instance.model_matrix())) {
+                    continue;
+                }
+            }
+            let bounds = self.bvh.bounds()[index];
+            let lod = mesh.select_lod(culling::screen_size(&view_proj, projection_scale, &bounds));
+            batches[instance.mesh()][lod].push(index as u32);
+        }
+
+        let mut stats = CullingStats::default();
+        self.lod_ranges.clear();
+        for lods in &batches {
+            let mut ranges = Vec::new();
+            for (lod, instances) in lods.iter().enumerate() {
+                ranges.push(stats.drawn as u32..(stats.drawn + instances.len()) as u32);
+                stats.lods[lod] += instances.len();
+                stats.drawn += instances.len();
+            }
+            self.lod_ranges.push(ranges);
+        }
+        stats.culled = self.instance_data.len() - stats.drawn;
+        self.visible_buffer.write(&self.device, &self.queue, &batches.concat().concat());
+        self.culling_stats = stats;
+    }
+
+    pub fn instances(&self) -> &[Instance] {
+        &self.instances
+    }
+
+    // these instances need uploading again and the tree around their bounds refitting
+    fn mark_instances_dirty(&mut self, range: std::ops::Range<usize>) {
+        self.instance_buffer.mark_dirty(range);
+        self.bvh_dirty = true;
+    }
+
+    // the index of the new instance; it is drawn and can be picked from the next update. Panics if
+    // its mesh wasn't added
+    pub fn add_instance(&mut self, instance: Instance) -> usize {
+        assert!(instance.mesh < self.meshes.len(), "the instance's mesh wasn't added");
+        let index = self.instances.len();
+        self.instances.push(instance);
+        // the scene graph's instances move up one
+        self.instance_data.insert(index, instance.to_raw());
+        self.mark_instances_dirty(index..self.instance_data.len());
+        self.selected_instance = self.selected_instance.map(|selected| if selected >= index { selected + 1 } else { selected });
+        index
+    }
+
+    // moves the last instance into the gap like Vec::swap_remove, so only one index changes;
+    // panics if index is out of bounds
+    pub fn remove_instance(&mut self, index: usize) -> Instance {
+        let last = self.instances.len() - 1;
+        let instance = self.instances.swap_remove(index);
+        self.instance_data.swap(index, last);
+        self.instance_data.remove(last); // the scene graph's instances move down one
+        if index < last {
+            self.mark_instances_dirty(index..index + 1);
+        }
+        if last < self.instance_data.len() {
+            self.mark_instances_dirty(last..self.instance_data.len());
+        }
+        // the selection follows the instance that moved
+        self.selected_instance = match self.selected_instance {
+            Some(selected) if selected == index => None,
+            Some(selected) if selected == last => Some(index),
+            Some(selected) if selected > last => Some(selected - 1),
+            selected => selected,
+        };
+        instance
+    }
+
+    // panics if index is out of bounds or the instance's mesh wasn't added
+    pub fn update_instance(&mut self, index: usize, instance: Instance) {
+        assert!(instance.mesh < self.meshes.len(), "the instance's mesh wasn't added");
+        self.instances[index] = instance;
+        self.instance_data[index] = instance.to_raw();
+        self.mark_instances_dirty(index..index + 1);
+    }
+
+    pub fn clear_instances(&mut self) {
+        self.instance_data.drain(..self.instances.len());
+        self.mark_instances_dirty(0..self.instance_data.len());
+        self.instances.clear();
+        self.selected_instance = None;
+    }
+
+    // mesh 0, which instances draw by default
+    pub fn mesh(&self) -> &Mesh {
+        &self.meshes[0].mesh
+    }
+
+    // mesh 0 and the ones added since; instances, scene graph nodes and MeshRenderers pick one by index
+    pub fn mesh_count(&self) -> usize {
+        self.meshes.len()
+    }
+
+    // replaces mesh 0, without levels of detail; panics if it has no vertices
+    pub fn set_mesh(&mut self, mesh: Mesh) {
+        self.set_mesh_lods(mesh, Vec::new());
+    }
+
+    // like set_mesh, with simpler meshes for instances that are small on screen, e.g. from
+    // Mesh::generate_lods or other assets; panics with mesh::GpuMesh::MAX_LODS or more of them
+    pub fn set_mesh_lods(&mut self, mesh: Mesh, lods: Vec<Lod>) {
+        self.put_mesh(0, mesh::GpuMesh::new(&self.device, mesh, lods, "Mesh"), None);
+    }
+
+    // like set_mesh, but adds the mesh and returns its index
+    pub fn add_mesh(&mut self, mesh: Mesh) -> usize {
+        self.add_mesh_lods(mesh, Vec::new())
+    }
+
+    // like set_mesh_lods, but adds the mesh and returns its index
+    pub fn add_mesh_lods(&mut self, mesh: Mesh, lods: Vec<Lod>) -> usize {
+        self.put_mesh(self.meshes.len(), mesh::GpuMesh::new(&self.device, mesh, lods, "Mesh"), None)
+    }
+
+    // places a mesh at index, or adds it with the next index; drawing, culling and picking follow
+    // from the next update
+    fn put_mesh(&mut self, index: usize, mesh: mesh::GpuMesh, source: Option<MeshSource>) -> usize {
+        if index == self.meshes.len() {
+            self.meshes.push(mesh);
+            self.mesh_sources.push(source);
+        } else {
+            self.meshes[index] = mesh;
+            self.mesh_sources[index] = source;
+        }
+        self.mark_meshes_dirty();
+        index
+    }
+
+    // a mesh scene files can save, with the levels of detail generate_lods asked for
+    fn put_saved_mesh(&mut self, index: usize, mesh: Mesh, source: MeshSource) -> usize {
+        let lods = mesh.generate_lods(self.mesh_lods);
+        self.put_mesh(index, mesh::GpuMesh::new(&self.device, mesh, lods, "Mesh"), Some(source))
+    }
+
+    fn mark_meshes_dirty(&mut self) {
+        self.meshes_dirty = true; // nodes and entities of a new mesh can be drawn now
+        self.bvh_dirty = true;
+        self.joints_dirty = true; // for the pose bounds
+        if let Some(gpu_culling) = &mut self.gpu_culling {
+            gpu_culling.set_meshes(&self.device, &self.queue, &self.meshes);
+        }
+    }
+
+    // the skeleton the meshes' Vertex::joints refer to, with every instance in its rest pose until
+    // set_pose; None draws the mesh as it is. Scene files don't save it
+    pub fn set_skeleton(&mut self, skeleton: Option<Skeleton>) {
+        self.joint_matrices = skeleton.iter()
+            .map(|skeleton| skeleton.joint_matrices(&skeleton.rest_pose()))
+            .collect();
+        self.skeleton = skeleton;
+        self.joints_dirty = true;
+        self.bvh_dirty = true;
+    }
+
+    pub fn skeleton(&self) -> Option<&Skeleton> {
+        self.skeleton.as_ref()
+    }
+
+    // instances with Instance::pose == index are drawn in this pose from the next update; poses up
+    // to index that were never set are the rest pose, and instances past the last pose use the last.
+    // Panics without a skeleton or if the pose has fewer joints than it
+    pub fn set_pose(&mut self, index: usize, pose: &Pose) {
+        let skeleton = self.skeleton.as_ref().expect("set_pose needs a skeleton");
+        if index >= self.joint_matrices.len() {
+            let rest = skeleton.joint_matrices(&skeleton.rest_pose());
+            self.joint_matrices.resize(index + 1, rest);
+        }
+        self.joint_matrices[index] = skeleton.joint_matrices(pose);
+        self.joints_dirty = true;
+        self.bvh_dirty = true;
+    }
+
+    // 0 without a skeleton
+    pub fn pose_count(&self) -> usize {
+        self.joint_matrices.len()
+    }
+
+    // replaces the levels of detail of every mesh with up to count simplified from it, see
+    // Mesh::generate_lods; primitives and files loaded later get as many, and scene files can save
+    // them. Panics with mesh::GpuMesh::MAX_LODS or more
+    pub fn generate_lods(&mut self, count: usize) {
+        for mesh in &mut self.meshes {
+            let lods = mesh.mesh.generate_lods(count);
+            *mesh = mesh::GpuMesh::new(&self.device, mesh.mesh.clone(), lods, "Mesh");
+        }
+        self.mesh_lods = count;
+        self.mark_meshes_dirty();
+    }
+
+    // like set_mesh, but scene files can save it
+    pub fn set_primitive(&mut self, primitive: Primitive) {
+        self.put_saved_mesh(0, primitive.mesh(), MeshSource::Primitive(primitive));
+    }
+
+    // like add_mesh, but scene files can save it
+    pub fn add_primitive(&mut self, primitive: Primitive) -> usize {
+        self.put_saved_mesh(self.meshes.len(), primitive.mesh(), MeshSource::Primitive(primitive))
+    }
+
+    // an OBJ file from res/ as mesh 0, see resources::load_mesh; scene files can save it
+    pub async fn load_mesh(&mut self, file_name: &str) -> Result<(), ResourceError> {
+        let mesh = resources::load_mesh(file_name).await?;
+        self.put_saved_mesh(0, mesh, MeshSource::Asset(file_name.to_string()));
+        Ok(())
+    }
+
+    // like load_mesh, but adds the mesh and returns its index
+    pub async fn add_mesh_asset(&mut self, file_name: &str) -> Result<usize, ResourceError> {
+        let mesh = resources::load_mesh(file_name).await?;
+        Ok(self.put_saved_mesh(self.meshes.len(), mesh, MeshSource::Asset(file_name.to_string())))
+    }
+
+    // nodes with a NodeMesh are drawn after the instances, grouped by mesh, and Hit::instance counts
+    // them in the same order; nodes of a mesh past mesh_count are left out until it is added
+    pub fn scene_graph(&self) -> &SceneGraph {
+        &self.scene_graph
+    }
+
+    pub fn scene_graph_mut(&mut self) -> &mut SceneGraph {
+        &mut self.scene_graph
+    }
+
+    // the background, camera, lights, material, meshes and instances as they are now; write it out
+    // with to_string. Fails for a mesh given to set_mesh or add_mesh, which has no source to save
+    pub fn scene_file(&self) -> Result<SceneFile, SceneFileError> {
+        let meshes = self.mesh_sources.iter().cloned().collect::<Option<Vec<_>>>().ok_or(SceneFileError::UnsavedMesh)?;
+        let direction = glam::Vec4::from(self.light_uniform.direction).truncate();
+        let color = glam::Vec4::from(self.light_uniform.color);
+        Ok(SceneFile {
+            background: [self.bg_color.r, self.bg_color.g, self.bg_color.b, self.bg_color.a],
+            camera: SceneCamera {
+                eye: self.camera.eye,
+                target: self.camera.target,
+                up: self.camera.up,
+                projection: self.camera.projection,
+            },
+            light: SceneLight {
+                direction,
+                color: color.truncate(),
+                intensity: color.w,
+            },
+            point_lights: self.point_lights.clone(),
+            material: self.material_source.clone(),
+            meshes,
+            lods: self.mesh_lods,
+            instances: self.instances.iter().map(SceneInstance::new).collect(),
+        })
+    }
+
+    // replaces what scene_file saves, loading meshes and textures from res/; nothing changes if it
+    // fails. Meshes past the scene's are removed, but mesh 0 stays if it has none
+    pub async fn load_scene_file(&mut self, scene: &SceneFile) -> Result<(), SceneFileError> {
+        scene.validate()?;
+        // the meshes that aren't already loaded at their index
+        let mut meshes = Vec::new();
+        for (index, source) in scene.meshes.iter().enumerate() {
+            if self.mesh_sources.get(index).and_then(Option::as_ref) != Some(source) {
+                meshes.push((index, source, source.load().await?));
+            }
+        }
+        let textures_changed = (
+            &scene.material.base_color_texture,
+            &scene.material.metallic_roughness_texture,
+            &scene.material.occlusion_texture,
+            &scene.material.emissive_texture,
+        ) != (
+            &self.material_source.base_color_texture,
+            &self.material_source.metallic_roughness_texture,
+            &self.material_source.occlusion_texture,
+            &self.material_source.emissive_texture,
+        );
+        if textures_changed {
+            let (srgb, linear) = (wgpu::TextureFormat::Rgba8UnormSrgb, wgpu::TextureFormat::Rgba8Unorm);
+            let textures = material::MaterialTextures {
+                base_color: self.load_scene_texture(&scene.material.base_color_texture, srgb).await?,
+                metallic_roughness: self.load_scene_texture(&scene.material.metallic_roughness_texture, linear).await?,
+                occlusion: self.load_scene_texture(&scene.material.occlusion_texture, linear).await?,
+                emissive: self.load_scene_texture(&scene.material.emissive_texture, srgb).await?,
+            };
+            self.material = material::Material::new(
+                &self.device,
+                &self.queue,
+                &self.material_layout,
+                &scene.material.name,
+                scene.material.factors,
+                textures,
+            );
+        } else {
+            self.material.set_factors(&self.queue, scene.material.factors);
+        }
+        self.material_source = scene.material.clone();
+
+        let [r, g, b, a] = scene.background;
+        self.bg_color = Color { r, g, b, a };
+        self.camera.eye = scene.camera.eye;
+        self.camera.target = scene.camera.target;
+        self.camera.up = scene.camera.up;
+        self.set_projection(scene.camera.projection);
+        self.reset_camera_controller(self.camera_mode());
+        self.set_light(scene.light.direction, scene.light.color, scene.light.intensity);
+        self.set_point_lights(scene.point_lights.clone());
+        for (index, source, mesh) in meshes {
+            self.put_saved_mesh(index, mesh, source.clone());
+        }
+        if scene.meshes.len().max(1) < self.meshes.len() {
+            self.meshes.truncate(scene.meshes.len().max(1));
+            self.mesh_sources.truncate(self.meshes.len());
+            self.mark_meshes_dirty();
+        }
+        if scene.lods != self.mesh_lods {
+            self.generate_lods(scene.lods);
+        }
+        self.clear_instances();
+        for instance in &scene.instances {
+            self.add_instance(instance.to_instance());
+        }
+        // drops nodes and entities of the removed meshes before anything reads their instances
+        self.extract_instances();
+        Ok(())
+    }
+
+    // reads a scene file from res/, e.g. one saved from scene_file().to_string()
+    pub async fn load_scene(&mut self, file_name: &str) -> Result<(), SceneFileError> {
+        let text = resources::load_string(file_name).await
+            .map_err(|error| SceneFileError::Resource(file_name.to_string(), error))?;
+        self.load_scene_file(&text.parse()?).await
+    }
+
+    async fn load_scene_texture(&self, path: &Option<String>, format: wgpu::TextureFormat) -> Result<Option<texture::Texture>, SceneFileError> {
+        let Some(path) = path else {
+            return Ok(None);
+        };
+        resources::load_texture(path, format, &self.device, &self.queue).await
+            .map(Some)
+            .map_err(|error| SceneFileError::Resource(path.clone(), error))
+    }
+
+    // the scene graph node behind an index past the instances, e.g. from Hit::instance
+    pub fn scene_node(&self, index: usize) -> Option<NodeId> {
+        self.scene_nodes.get(index.checked_sub(self.instances.len())?).copied()
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    pub fn add_system(&mut self, system: impl FnMut(&mut World, std::time::Duration) + 'static) {
+        self.systems.push(Box::new(system));
+    }
+
+    // the entity behind an index past the scene graph's instances, e.g. from Hit::instance
+    pub fn entity(&self, index: usize) -> Option<Entity> {
+        self.entities.get(index.checked_sub(self.instances.len() + self.scene_nodes.len())?).copied()
+    }
+
+    // the first CameraComponent with a Transform moves the camera when cameras or transforms changed,
+    // and input and set_projection carry on from there until the next change; a playing camera path
+    // comes first. The camera keeps its up vector
+    fn extract_camera(&mut self) {
+        if self.camera_path.is_some() || !(self.world.cameras.is_changed() || self.world.transforms.is_changed()) {
+            return;
+        }
+        let camera = self.world.cameras.iter()
+            .find_map(|(entity, camera)| Some((self.world.transforms.get(entity)?, camera)));
+        if let Some((transform, camera)) = camera {
+            self.camera.eye = transform.translation;
+            self.camera.target = transform.translation + transform.rotation * glam::Vec3::NEG_Z;
+            self.set_projection(camera.projection);
+            // input carries on from here
+            self.reset_camera_controller(self.camera_mode());
+        }
+    }
+
+    // rebuilds the instances after the flat ones from the scene graph and the world's MeshRenderers,
+    // whichever changed, or both once the meshes changed. Both are grouped by mesh, and those of
+    // meshes that weren't added are left out
+    fn extract_instances(&mut self) {
+        let scene_changed = self.scene_graph.is_changed() || self.meshes_dirty;
+        if !scene_changed && !self.world.transforms.is_changed() && !self.world.mesh_renderers.is_changed() {
+            return;
+        }
+        let mut start = self.instances.len() + self.scene_nodes.len();
+        if scene_changed {
+            start = self.instances.len();
+            self.instance_data.truncate(start);
+            self.scene_nodes.clear();
+            let mut missing = 0;
+            for (mesh, batch) in self.scene_graph.flatten() {
+                if mesh < self.meshes.len() {
+                    self.instance_data.extend(batch.instances);
+                    self.scene_nodes.extend(batch.nodes);
+                } else {
+                    missing += batch.nodes.len();
+                }
+            }
+            if missing > 0 {
+                eprintln!("{missing} scene graph nodes use meshes that weren't added and aren't drawn");
+            }
+            self.meshes_dirty = false;
+        }
+        self.instance_data.truncate(self.instances.len() + self.scene_nodes.len());
+        self.entities.clear();
+        let mut renderers = self.world.mesh_renderers.iter()
+            .filter_map(|(entity, renderer)| Some((entity, renderer, self.world.transforms.get(entity)?)))
+            .collect::<Vec<_>>();
+        // a batch per mesh, keeping the order within each
+        renderers.sort_by_key(|(_, renderer, _)| renderer.mesh);
+        let mut missing = 0;
+        for (entity, renderer, transform) in renderers {
+            if renderer.mesh >= self.meshes.len() {
+                missing += 1;
+                continue;
+            }
+            let instance = Instance {
+                position: transform.translation,
+                rotation: transform.rotation,
+                scale: transform.scale,
+                color: renderer.color,
+                custom: renderer.custom,
+                pose: renderer.pose,
+                mesh: renderer.mesh,
+            };
+            self.instance_data.push(instance.to_raw());
+            self.entities.push(entity);
+        }
+        if missing > 0 {
+            eprintln!("{missing} MeshRenderers use meshes that weren't added and aren't drawn");
+        }
+        self.mark_instances_dirty(start..self.instance_data.len());
+        // the order is stable unless nodes or entities were added or removed
+        self.selected_instance = self.selected_instance.filter(|&selected| selected < self.instance_data.len());
+    }
+
+    fn extract_lights(&mut self) {
+        let lights = self.world.lights.iter()
+            .filter_map(|(entity, light)| Some(PointLight {
+                position: self.world.transforms.get(entity)?.translation,
+                color: light.color,
+                intensity: light.intensity,
+                radius: light.radius,
+            }))
+            .collect::<Vec<_>>();
+        if lights != self.entity_lights {
+            self.entity_lights = lights;
+            self.upload_point_lights();
+        }
+    }
+
+    // on by default; turning it off draws every instance, e.g. to compare performance
+    pub fn set_frustum_culling(&mut self, enabled: bool) {
+        self.frustum_culling = enabled;
+    }
+
+    // world space ray through the cursor; None while the cursor is outside the window
+    pub fn cursor_ray(&self) -> Option<Ray> {
+        let size = glam::Vec2::new(self.size.width as f32, self.size.height as f32);
+        self.cursor_position.map(|cursor| self.camera.screen_ray(cursor, size))
+    }
+
+    // the closest instance the ray passes through as of the last update; the tree of boxes first, then
+    // the triangles of its mesh
+    pub fn pick(&self, ray: &Ray) -> Option<Hit> {
+        // each mesh skinned once per pose, as instances in it are hit
+        let posed = self.meshes.iter()
+            .map(|_| self.joint_matrices.iter().map(|_| std::cell::OnceCell::new()).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let closest = self.bvh.query_ray(ray, |index| {
+            // in model space the mesh vertices can be used as they are
+            let instance = &self.instance_data[index];
+            let local_ray = ray.transform(&instance.model_matrix().inverse());
+            let source = &self.meshes[instance.mesh()].mesh;
+            let poses = &posed[instance.mesh()];
+            let mesh = match poses.len() {
+                0 => source,
+                count => {
+                    let pose = (instance.pose() as usize).min(count - 1);
+                    poses[pose].get_or_init(|| source.skinned(&self.joint_matrices[pose]))
+                }
+            };
+            mesh.triangles()
+                .filter_map(|[a, b, c]| local_ray.intersect_triangle(a, b, c))
+                .reduce(f32::min)
         });
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(self.bg_color),
-                        store: true,
-                    },
-                })],
+        closest.map(|(instance, distance)| Hit {
+            instance,
+            distance: distance * ray.direction.length(),
+            point: ray.at(distance),
+        })
+    }
+
+    pub fn pick_at_cursor(&self) -> Option<Hit> {
+        self.pick(&self.cursor_ray()?)
+    }
+
+    // the instance picked by the last click, if it hit one
+    pub fn selected_instance(&self) -> Option<usize> {
+        self.selected_instance
+    }
+
+    // asks which instance covers a pixel, answered by poll_id_pick a frame or two later;
+    // false without Settings::id_buffer_picking
+    pub fn request_id_pick(&mut self, pixel: glam::UVec2) -> bool {
+        match &mut self.id_buffer {
+            Some(id_buffer) => {
+                id_buffer.request(pixel);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // the answer to the last request_id_pick once it is ready; doesn't block
+    pub fn poll_id_pick(&mut self) -> Option<IdPick> {
+        self.id_buffer.as_mut()?.poll(&self.device)
+    }
+
+    // with CullingMode::Gpu, also skip instances hidden behind last frame's depth; off by default
+    pub fn set_occlusion_culling(&mut self, enabled: bool) {
+        if let Some(gpu_culling) = &mut self.gpu_culling {
+            gpu_culling.set_occlusion_culling(enabled);
+        }
+    }
+
+    // instances drawn and skipped by the last update, with CullingMode::Cpu
+    pub fn culling_stats(&self) -> CullingStats {
+        self.culling_stats
+    }
+
+    // material and camera at groups 0 and 1, then the instances of each mesh with their level of detail
+    fn draw_scene<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_bind_group(0, &self.material.bind_group, &[]); // tutorial 3
+        render_pass.set_bind_group(1, &self.camera_bind_group, &[]); // tutorial 4
+        if self.gpu_culling.is_none() {
+            render_pass.set_vertex_buffer(1, self.visible_buffer.buffer().slice(..)); // tutorial 5
+        }
+        for (index, mesh) in self.meshes.iter().enumerate() {
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..)); // tutorial 2
+            render_pass.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format); // tutorial 2
+            if let Some(gpu_culling) = &self.gpu_culling {
+                // the compute pass decided how many instances there are
+                gpu_culling.draw(render_pass, index);
+                continue;
+            }
+            // a mesh added since the last update has no instances yet
+            let lod_ranges = self.lod_ranges.get(index).into_iter().flatten();
+            for (lod, instances) in mesh.lods.iter().zip(lod_ranges) {
+                if !instances.is_empty() {
+                    // ids of vertices of instances -> @builtin(vertex_index)
+                    let indices = lod.first_index..lod.first_index + lod.num_indices;
+                    render_pass.draw_indexed(indices, lod.base_vertex, instances.clone()); // DRAW CALL
+                }
+            }
+        }
+    }
+
+    fn render_forward(&self, encoder: &mut wgpu::CommandEncoder) {
+        if self.ssao.enabled() {
+            let mut depth_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Depth Prepass"),
+                color_attachments: &[],
                 depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
                     view: &self.depth_texture.view,
                     depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
+                        load: wgpu::LoadOp::Clear(self.camera.depth_mode.far_depth()),
                         store: true,
                     }),
                     stencil_ops: None,
                 }),
             });
+            depth_pass.set_pipeline(&self.depth_prepass_pipeline);
+            self.draw_scene(&mut depth_pass);
+        }
+        self.ssao.process(&self.device, encoder, &self.depth_texture);
+
+        // keep the prepass depth if there was one
+        let depth_load = if self.ssao.enabled() { wgpu::LoadOp::Load } else { wgpu::LoadOp::Clear(self.camera.depth_mode.far_depth()) };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: self.hdr.view(),
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(self.bg_color),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: depth_load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
 
-            // use the pipeline
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]); // tutorial 3
-            render_pass.set_bind_group(1, &self.camera_bind_group, &[]); // tutorial 4
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..)); // tutorial 2
-            render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..)); // tutorial 5
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16); // tutorial 2
-            // ids of vertices of instances -> @builtin(vertex_index)
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..self.instances.len() as _); // DRAW CALL
+        // use the pipeline
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(2, &self.lighting_bind_group, &[]);
+        render_pass.set_bind_group(3, self.ssao.output_bind_group(), &[]);
+        self.draw_scene(&mut render_pass);
+    }
 
+    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        let output = self.surface.get_current_texture()?; // texture on the surface we will draw to
+        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default()); // view description; default
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Render Encoder"),
+        });
+        if let Some(gpu_culling) = &mut self.gpu_culling {
+            gpu_culling.cull(&self.device, &mut encoder, &self.instance_buffer);
+        }
+        if let Some(deferred) = &self.deferred {
+            {
+                let mut geometry_pass = deferred.begin_geometry_pass(&mut encoder, self.hdr.view(), &self.depth_texture.view, self.bg_color);
+                self.draw_scene(&mut geometry_pass);
+            }
+            self.ssao.process(&self.device, &mut encoder, &self.depth_texture);
+            deferred.lighting_pass(
+                &self.device,
+                &mut encoder,
+                self.hdr.view(),
+                &self.depth_texture,
+                deferred::LightingResources {
+                    camera: &self.camera_bind_group,
+                    lighting: &self.lighting_bind_group,
+                    ssao: self.ssao.output_bind_group(),
+                },
+            );
+        } else {
+            self.render_forward(&mut encoder);
+        }
+        if let Some(gpu_culling) = &mut self.gpu_culling {
+            gpu_culling.build_hi_z(&self.device, &mut encoder, &self.depth_texture);
+        }
+        // the same draws as the scene, so each mesh only covers its own instances
+        if let Some(pixel) = self.id_buffer.as_mut().and_then(picking::IdBuffer::take_request) {
+            if let Some(id_buffer) = &self.id_buffer {
+                let mut id_pass = id_buffer.begin_pass(&mut encoder);
+                self.draw_scene(&mut id_pass);
+            }
+            if let Some(id_buffer) = &mut self.id_buffer {
+                id_buffer.read_texel(&mut encoder, pixel);
+            }
         }
 
+        // post-process and tonemap the HDR scene into the surface texture
+        self.post.process(&self.device, &self.queue, &mut encoder, &self.hdr, &view);
+
         // submit will accept anything that implements IntoIter
         self.queue.submit(std::iter::once(encoder.finish()));
+        if let Some(id_buffer) = &mut self.id_buffer {
+            id_buffer.after_submit();
+        }
         output.present();
 
         Ok(())
     }
 }
+
+// the camera uniform, the joint matrices and the instances, which the joint and instance buffers
+// replace when they grow
+fn create_camera_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    camera_buffer: &wgpu::Buffer,
+    joint_buffer: &animation::JointBuffer,
+    instance_buffer: &instance_buffer::InstanceBuffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: joint_buffer.buffer().as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: instance_buffer.buffer().as_entire_binding(),
+            },
+        ],
+        label: Some("camera_bind_group"),
+    })
+}
+
+// the directional and point lights with the environment's image-based lighting
+fn create_lighting_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    light_buffer: &wgpu::Buffer,
+    point_lights_buffer: &wgpu::Buffer,
+    environment: &ibl::Environment,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: light_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&environment.irradiance_map.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&environment.prefiltered_map.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(&environment.brdf_lut.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::Sampler(&environment.prefiltered_map.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: point_lights_buffer.as_entire_binding(),
+            },
+        ],
+        label: Some("lighting_bind_group"),
+    })
+}
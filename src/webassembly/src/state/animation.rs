@@ -0,0 +1,31 @@
+use crate::state::scene::Transform;
+
+mod clip;
+mod skeleton;
+mod skinning;
+
+pub use clip::{AnimationClip, Channel, Interpolation, Keyframes};
+pub use skeleton::{Joint, Skeleton};
+pub use skinning::JointBuffer;
+
+// a local transform for each of a skeleton's joints, relative to its parent; start from
+// Skeleton::rest_pose, sample clips into it, blend and hand it to State::set_pose
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pose {
+    pub joints: Vec<Transform>,
+}
+
+impl Pose {
+    // weight 0 is this pose and 1 the other; rotations take the shorter way around. Joints only one
+    // of them has are left out
+    pub fn blend(&self, other: &Pose, weight: f32) -> Pose {
+        let joints = self.joints.iter().zip(&other.joints)
+            .map(|(a, b)| Transform {
+                translation: a.translation.lerp(b.translation, weight),
+                rotation: a.rotation.slerp(b.rotation, weight),
+                scale: a.scale.lerp(b.scale, weight),
+            })
+            .collect();
+        Pose { joints }
+    }
+}
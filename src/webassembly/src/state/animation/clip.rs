@@ -0,0 +1,233 @@
+use super::Pose;
+
+// how a channel gets from one keyframe to the next, as in glTF
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Interpolation {
+    #[default]
+    Linear, // spherical for rotations
+    Step, // holds each keyframe until the next
+    CubicSpline, // Hermite, with an in-tangent, the value and an out-tangent per keyframe
+}
+
+// one value per keyframe, or three with Interpolation::CubicSpline
+#[derive(Debug, Clone, PartialEq)]
+pub enum Keyframes {
+    Translation(Vec<glam::Vec3>),
+    Rotation(Vec<glam::Quat>),
+    Scale(Vec<glam::Vec3>),
+}
+
+// animates one part of one joint's local transform
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+    pub joint: usize,
+    pub times: Vec<f32>, // in seconds, increasing
+    pub keyframes: Keyframes,
+    pub interpolation: Interpolation,
+}
+
+impl Channel {
+    // values go through Vec4 so translations, rotations and scales share the interpolation; lerp is
+    // the linear one. The first and last keyframes hold before and after the channel
+    fn sample_vec4(&self, time: f32, value: impl Fn(usize) -> glam::Vec4, lerp: impl Fn(glam::Vec4, glam::Vec4, f32) -> glam::Vec4) -> glam::Vec4 {
+        let cubic = self.interpolation == Interpolation::CubicSpline;
+        let key = |keyframe: usize| if cubic { value(keyframe * 3 + 1) } else { value(keyframe) };
+        let next = self.times.partition_point(|&keyframe| keyframe <= time);
+        if next == 0 {
+            return key(0);
+        }
+        if next == self.times.len() {
+            return key(next - 1);
+        }
+        let previous = next - 1;
+        let duration = self.times[next] - self.times[previous];
+        let t = (time - self.times[previous]) / duration;
+        match self.interpolation {
+            Interpolation::Step => key(previous),
+            Interpolation::Linear => lerp(key(previous), key(next), t),
+            Interpolation::CubicSpline => {
+                // tangents are per second, so they scale with the time between keyframes
+                let out_tangent = value(previous * 3 + 2) * duration;
+                let in_tangent = value(next * 3) * duration;
+                let (t2, t3) = (t * t, t * t * t);
+                key(previous) * (2.0 * t3 - 3.0 * t2 + 1.0)
+                    + out_tangent * (t3 - 2.0 * t2 + t)
+                    + key(next) * (-2.0 * t3 + 3.0 * t2)
+                    + in_tangent * (t3 - t2)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationClip {
+    pub name: String,
+    pub channels: Vec<Channel>,
+}
+
+impl AnimationClip {
+    pub fn new(name: &str, channels: Vec<Channel>) -> Self {
+        Self {
+            name: name.to_string(),
+            channels,
+        }
+    }
+
+    // until the last keyframe of any channel
+    pub fn duration(&self) -> f32 {
+        self.channels.iter().filter_map(|channel| channel.times.last().copied()).fold(0.0, f32::max)
+    }
+
+    // writes the channels' values at time, in seconds from the start, into pose; what no channel
+    // animates keeps its value. For a looping clip pass time.rem_euclid(duration())
+    pub fn sample(&self, time: f32, pose: &mut Pose) {
+        for channel in self.channels.iter().filter(|channel| !channel.times.is_empty()) {
+            let Some(joint) = pose.joints.get_mut(channel.joint) else {
+                continue;
+            };
+            match &channel.keyframes {
+                Keyframes::Translation(values) => {
+                    joint.translation = channel.sample_vec4(time, |index| values[index].extend(0.0), glam::Vec4::lerp).truncate();
+                }
+                Keyframes::Rotation(values) => {
+                    let slerp = |a: glam::Vec4, b: glam::Vec4, t: f32| glam::Quat::from_vec4(a).slerp(glam::Quat::from_vec4(b), t).into();
+                    joint.rotation = glam::Quat::from_vec4(channel.sample_vec4(time, |index| values[index].into(), slerp)).normalize();
+                }
+                Keyframes::Scale(values) => {
+                    joint.scale = channel.sample_vec4(time, |index| values[index].extend(0.0), glam::Vec4::lerp).truncate();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::scene::Transform;
+
+    fn translation(times: Vec<f32>, values: Vec<glam::Vec3>, interpolation: Interpolation) -> AnimationClip {
+        AnimationClip::new("test", vec![Channel {
+            joint: 0,
+            times,
+            keyframes: Keyframes::Translation(values),
+            interpolation,
+        }])
+    }
+
+    fn sample(clip: &AnimationClip, time: f32) -> Transform {
+        let mut pose = Pose { joints: vec![Transform::default()] };
+        clip.sample(time, &mut pose);
+        pose.joints[0]
+    }
+
+    fn assert_near(a: glam::Vec3, b: glam::Vec3) {
+        assert!(a.abs_diff_eq(b, 1e-5), "{a} != {b}");
+    }
+
+    #[test]
+    fn step_holds_each_keyframe_until_the_next() {
+        let clip = translation(vec![0.0, 1.0, 2.0], vec![glam::Vec3::X, glam::Vec3::Y, glam::Vec3::Z], Interpolation::Step);
+        assert_eq!(sample(&clip, 0.0).translation, glam::Vec3::X);
+        assert_eq!(sample(&clip, 0.99).translation, glam::Vec3::X);
+        assert_eq!(sample(&clip, 1.0).translation, glam::Vec3::Y);
+        assert_eq!(sample(&clip, 1.5).translation, glam::Vec3::Y);
+        assert_eq!(sample(&clip, 2.0).translation, glam::Vec3::Z);
+    }
+
+    #[test]
+    fn linear_interpolates_between_keyframes() {
+        let clip = translation(vec![1.0, 3.0], vec![glam::Vec3::ZERO, glam::Vec3::new(4.0, -2.0, 0.0)], Interpolation::Linear);
+        assert_near(sample(&clip, 1.5).translation, glam::Vec3::new(1.0, -0.5, 0.0));
+        assert_near(sample(&clip, 2.0).translation, glam::Vec3::new(2.0, -1.0, 0.0));
+
+        let quarter_turn = glam::Quat::from_rotation_y(std::f32::consts::FRAC_PI_2);
+        let clip = AnimationClip::new("test", vec![Channel {
+            joint: 0,
+            times: vec![0.0, 1.0],
+            keyframes: Keyframes::Rotation(vec![glam::Quat::IDENTITY, quarter_turn]),
+            interpolation: Interpolation::Linear,
+        }]);
+        let rotation = sample(&clip, 0.5).rotation;
+        assert!(rotation.abs_diff_eq(glam::Quat::from_rotation_y(std::f32::consts::FRAC_PI_4), 1e-5), "{rotation}");
+    }
+
+    #[test]
+    fn cubic_spline_scales_tangents_by_the_time_between_keyframes() {
+        // in-tangent, value, out-tangent per keyframe; the out-tangent is 1 unit per second
+        let values = vec![glam::Vec3::ZERO, glam::Vec3::ZERO, glam::Vec3::X, glam::Vec3::ZERO, glam::Vec3::X, glam::Vec3::ZERO];
+        let clip = translation(vec![0.0, 2.0], values.clone(), Interpolation::CubicSpline);
+        // halfway: 0.5 of each value and 0.125 of the out-tangent times the 2 seconds
+        assert_near(sample(&clip, 1.0).translation, glam::Vec3::new(0.75, 0.0, 0.0));
+        assert_near(sample(&clip, 0.0).translation, glam::Vec3::ZERO);
+        assert_near(sample(&clip, 2.0).translation, glam::Vec3::X);
+
+        // over half the time the same tangent bends the curve half as much
+        let clip = translation(vec![0.0, 1.0], values, Interpolation::CubicSpline);
+        assert_near(sample(&clip, 0.5).translation, glam::Vec3::new(0.625, 0.0, 0.0));
+    }
+
+    #[test]
+    fn sampling_clamps_to_the_first_and_last_keyframes() {
+        for interpolation in [Interpolation::Step, Interpolation::Linear] {
+            let clip = translation(vec![1.0, 2.0], vec![glam::Vec3::X, glam::Vec3::Y], interpolation);
+            assert_eq!(sample(&clip, -5.0).translation, glam::Vec3::X);
+            assert_eq!(sample(&clip, 0.5).translation, glam::Vec3::X);
+            assert_eq!(sample(&clip, 2.5).translation, glam::Vec3::Y);
+        }
+        // the values, not the tangents around them
+        let values = vec![glam::Vec3::Z, glam::Vec3::X, glam::Vec3::Z, glam::Vec3::Z, glam::Vec3::Y, glam::Vec3::Z];
+        let clip = translation(vec![1.0, 2.0], values, Interpolation::CubicSpline);
+        assert_eq!(sample(&clip, 0.0).translation, glam::Vec3::X);
+        assert_eq!(sample(&clip, 3.0).translation, glam::Vec3::Y);
+
+        let clip = translation(vec![1.0], vec![glam::Vec3::X], Interpolation::Linear);
+        assert_eq!(sample(&clip, 0.0).translation, glam::Vec3::X);
+        assert_eq!(sample(&clip, 2.0).translation, glam::Vec3::X);
+        assert_eq!(clip.duration(), 1.0);
+    }
+
+    #[test]
+    fn channels_leave_other_joints_and_parts_alone() {
+        let mut clip = translation(vec![0.0, 1.0], vec![glam::Vec3::ZERO, glam::Vec3::X], Interpolation::Linear);
+        clip.channels[0].joint = 1;
+        clip.channels.push(Channel {
+            joint: 5, // past the pose's joints
+            times: vec![0.0],
+            keyframes: Keyframes::Scale(vec![glam::Vec3::splat(2.0)]),
+            interpolation: Interpolation::Linear,
+        });
+        let rotation = glam::Quat::from_rotation_x(1.0);
+        let mut pose = Pose { joints: vec![Transform::default(), Transform { rotation, ..Default::default() }] };
+        clip.sample(0.5, &mut pose);
+        assert_eq!(pose.joints[0], Transform::default());
+        assert_near(pose.joints[1].translation, glam::Vec3::new(0.5, 0.0, 0.0));
+        assert_eq!((pose.joints[1].rotation, pose.joints[1].scale), (rotation, glam::Vec3::ONE));
+    }
+
+    #[test]
+    fn blend_weighs_each_part_and_drops_unmatched_joints() {
+        let a = Pose { joints: vec![Transform::default(), Transform::default()] };
+        let b = Pose {
+            joints: vec![Transform {
+                translation: glam::Vec3::new(2.0, 0.0, -4.0),
+                rotation: glam::Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
+                scale: glam::Vec3::splat(3.0),
+            }],
+        };
+        assert_eq!(a.blend(&b, 0.0).joints, vec![Transform::default()]);
+        let joint = a.blend(&b, 1.0).joints[0];
+        assert_near(joint.translation, b.joints[0].translation);
+        assert!(joint.rotation.abs_diff_eq(b.joints[0].rotation, 1e-5));
+
+        let joint = a.blend(&b, 0.25).joints[0];
+        assert_near(joint.translation, glam::Vec3::new(0.5, 0.0, -1.0));
+        assert_near(joint.scale, glam::Vec3::splat(1.5));
+        assert!(joint.rotation.abs_diff_eq(glam::Quat::from_rotation_z(std::f32::consts::FRAC_PI_8), 1e-5));
+
+        // the shorter way round: -q is the same rotation as q
+        let c = Pose { joints: vec![Transform { rotation: -glam::Quat::from_rotation_z(0.5), ..Default::default() }] };
+        let joint = a.blend(&c, 0.5).joints[0];
+        assert!(joint.rotation.angle_between(glam::Quat::from_rotation_z(0.25)) < 1e-3, "{}", joint.rotation);
+    }
+}
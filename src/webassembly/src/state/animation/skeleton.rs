@@ -0,0 +1,85 @@
+use super::Pose;
+use crate::state::scene::Transform;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Joint {
+    pub name: String,
+    pub parent: Option<usize>, // comes before the joint in Skeleton::joints
+    pub rest: Transform, // relative to the parent, what poses start from
+    pub inverse_bind: glam::Mat4, // from model space into the joint's space when the mesh was bound to it
+}
+
+impl Joint {
+    // with an identity inverse bind matrix, see Skeleton::bind_rest_pose
+    pub fn new(name: &str, parent: Option<usize>, rest: Transform) -> Self {
+        Self {
+            name: name.to_string(),
+            parent,
+            rest,
+            inverse_bind: glam::Mat4::IDENTITY,
+        }
+    }
+}
+
+// joints a skinned mesh follows; Vertex::joints index into them
+#[derive(Debug, Clone, PartialEq)]
+pub struct Skeleton {
+    joints: Vec<Joint>,
+}
+
+impl Skeleton {
+    // panics if a joint's parent doesn't come before it
+    pub fn new(joints: Vec<Joint>) -> Self {
+        for (index, joint) in joints.iter().enumerate() {
+            if let Some(parent) = joint.parent {
+                assert!(parent < index, "joint {} comes before its parent {parent}", joint.name);
+            }
+        }
+        Self { joints }
+    }
+
+    pub fn joints(&self) -> &[Joint] {
+        &self.joints
+    }
+
+    // the first joint with the name
+    pub fn joint(&self, name: &str) -> Option<usize> {
+        self.joints.iter().position(|joint| joint.name == name)
+    }
+
+    pub fn rest_pose(&self) -> Pose {
+        Pose {
+            joints: self.joints.iter().map(|joint| joint.rest).collect(),
+        }
+    }
+
+    // sets the inverse bind matrices for a mesh modelled around the rest pose, so the rest pose
+    // leaves it where it is
+    pub fn bind_rest_pose(&mut self) {
+        let globals = self.global_matrices(&self.rest_pose());
+        for (joint, global) in self.joints.iter_mut().zip(globals) {
+            joint.inverse_bind = global.inverse();
+        }
+    }
+
+    // every joint's transform in model space; panics if the pose has fewer joints
+    pub fn global_matrices(&self, pose: &Pose) -> Vec<glam::Mat4> {
+        let mut globals: Vec<glam::Mat4> = Vec::with_capacity(self.joints.len());
+        for (joint, local) in self.joints.iter().zip(&pose.joints[..self.joints.len()]) {
+            let local = local.to_matrix();
+            globals.push(match joint.parent {
+                Some(parent) => globals[parent] * local,
+                None => local,
+            });
+        }
+        globals
+    }
+
+    // what the vertex shader moves a vertex by for each joint, from the bind pose into the pose
+    pub fn joint_matrices(&self, pose: &Pose) -> Vec<glam::Mat4> {
+        self.global_matrices(pose).iter()
+            .zip(&self.joints)
+            .map(|(global, joint)| *global * joint.inverse_bind)
+            .collect()
+    }
+}
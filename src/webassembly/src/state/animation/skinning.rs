@@ -0,0 +1,48 @@
+// the joint matrices of every pose for the vertex shader, in one storage buffer after a header
+// with how many joints and poses there are; a pose's matrices start at pose * joint count
+pub struct JointBuffer {
+    buffer: wgpu::Buffer,
+    capacity: usize, // in matrices
+}
+
+impl JointBuffer {
+    const HEADER_SIZE: usize = 16; // joint and pose counts, padded to a matrix's alignment
+
+    // room for one matrix, the least the shader's binding takes
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            buffer: Self::create_buffer(device, 1),
+            capacity: 1,
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Joint Buffer"),
+            size: (Self::HEADER_SIZE + capacity * std::mem::size_of::<glam::Mat4>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    // poses each hold joint_count matrices; no poses leaves every vertex unskinned. True when the
+    // buffer had to grow, so bind groups with it need making again
+    pub fn write(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, joint_count: usize, poses: &[Vec<glam::Mat4>]) -> bool {
+        let matrices = poses.iter().flatten().map(glam::Mat4::to_cols_array_2d).collect::<Vec<_>>();
+        let grown = matrices.len() > self.capacity;
+        if grown {
+            self.capacity = matrices.len().max(self.capacity * 2);
+            self.buffer = Self::create_buffer(device, self.capacity);
+        }
+        let joint_count = if poses.is_empty() { 0 } else { joint_count as u32 };
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[joint_count, poses.len() as u32, 0, 0]));
+        if !matrices.is_empty() {
+            queue.write_buffer(&self.buffer, Self::HEADER_SIZE as wgpu::BufferAddress, bytemuck::cast_slice(&matrices));
+        }
+        grown
+    }
+}
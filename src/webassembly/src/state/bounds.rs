@@ -0,0 +1,112 @@
+// Bounding volumes for framing the camera, culling, picking and the instance Bvh
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: glam::Vec3,
+    pub max: glam::Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: glam::Vec3, max: glam::Vec3) -> Self {
+        Self { min, max }
+    }
+
+    // None for no points
+    pub fn from_points(points: impl IntoIterator<Item = glam::Vec3>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(Self::new(first, first), |aabb, point| Self::new(aabb.min.min(point), aabb.max.max(point))))
+    }
+
+    pub fn center(&self) -> glam::Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    // half the size along each axis
+    pub fn extents(&self) -> glam::Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    // touching counts
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.cmple(other.max).all() && other.min.cmple(self.max).all()
+    }
+
+    // 0 inside the box
+    pub fn distance_to_point(&self, point: glam::Vec3) -> f32 {
+        (self.min - point).max(point - self.max).max(glam::Vec3::ZERO).length()
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let size = self.max - self.min;
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    // box around the transformed corners, so it stays axis aligned
+    pub fn transform(&self, matrix: &glam::Mat4) -> Self {
+        let corners = (0..8).map(|i| {
+            let corner = glam::Vec3::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            );
+            matrix.transform_point3(corner)
+        });
+        Self::from_points(corners).unwrap()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BoundingSphere {
+    pub center: glam::Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    // Ritter's sphere: starts between two points far apart, then grows to cover the rest; usually
+    // a few percent bigger than the smallest sphere. None for no points
+    pub fn from_points(points: &[glam::Vec3]) -> Option<Self> {
+        let &first = points.first()?;
+        let furthest = |from: glam::Vec3| points.iter().copied().max_by(|a, b| a.distance_squared(from).total_cmp(&b.distance_squared(from))).unwrap();
+        let a = furthest(first);
+        let b = furthest(a);
+        let mut sphere = Self {
+            center: (a + b) * 0.5,
+            radius: a.distance(b) * 0.5,
+        };
+        for &point in points {
+            let distance = point.distance(sphere.center);
+            if distance > sphere.radius {
+                // the smallest sphere around the old one and the point
+                let radius = (sphere.radius + distance) * 0.5;
+                sphere.center += (point - sphere.center) * ((radius - sphere.radius) / distance);
+                sphere.radius = radius;
+            }
+        }
+        Some(sphere)
+    }
+
+    // scaled by the largest axis scale, so it still covers a non-uniformly scaled volume
+    pub fn transform(&self, matrix: &glam::Mat4) -> Self {
+        let scale = matrix.x_axis.truncate().length()
+            .max(matrix.y_axis.truncate().length())
+            .max(matrix.z_axis.truncate().length());
+        Self {
+            center: matrix.transform_point3(self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+impl From<Aabb> for BoundingSphere {
+    fn from(aabb: Aabb) -> Self {
+        Self {
+            center: aabb.center(),
+            radius: aabb.extents().length(),
+        }
+    }
+}
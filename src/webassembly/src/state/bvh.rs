@@ -0,0 +1,437 @@
+use crate::state::bounds::Aabb;
+use crate::state::culling::Frustum;
+use crate::state::picking::Ray;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+// a bounding volume hierarchy over boxes, e.g. the world space bounds of State's instances, to find
+// the ones in a frustum, overlapping a box, along a ray or near a point without testing every one;
+// items are the boxes' indices
+pub struct Bvh {
+    bounds: Vec<Aabb>, // of each item
+    nodes: Vec<BvhNode>, // the root first; children always come after their parent
+    items: Vec<u32>, // in the order leaves refer to them
+    built_area: f32, // of all nodes together when last built, to tell when refitting made it loose
+}
+
+#[derive(Debug, Copy, Clone)]
+struct BvhNode {
+    bounds: Aabb,
+    first: u32, // a leaf's first entry in items, or an inner node's left child with the right one after it
+    count: u32, // items in a leaf; 0 for inner nodes
+}
+
+// what query_nearest has yet to look at; items sort before nodes at the same distance
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Entry {
+    Item(u32),
+    Node(u32),
+}
+
+impl Bvh {
+    const LEAF_SIZE: usize = 4;
+
+    pub fn new(bounds: Vec<Aabb>) -> Self {
+        let mut bvh = Self {
+            bounds,
+            nodes: Vec::new(),
+            items: Vec::new(),
+            built_area: 0.0,
+        };
+        bvh.build();
+        bvh
+    }
+
+    pub fn len(&self) -> usize {
+        self.bounds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bounds.is_empty()
+    }
+
+    pub fn bounds(&self) -> &[Aabb] {
+        &self.bounds
+    }
+
+    // replaces the boxes; with as many as before the tree is refit around them, unless that made it
+    // twice as loose as a fresh build, which happens when items moved far
+    pub fn update(&mut self, bounds: Vec<Aabb>) {
+        let rebuild = bounds.len() != self.bounds.len();
+        self.bounds = bounds;
+        if rebuild {
+            self.build();
+            return;
+        }
+        self.refit();
+        if self.area() > 2.0 * self.built_area {
+            self.build();
+        }
+    }
+
+    // splits at the median along the axis the boxes' centers spread most on, until leaves are small
+    fn build(&mut self) {
+        self.items = (0..self.bounds.len() as u32).collect();
+        self.nodes.clear();
+        if !self.bounds.is_empty() {
+            self.nodes.push(BvhNode {
+                bounds: self.bounds[0],
+                first: 0,
+                count: self.items.len() as u32,
+            });
+        }
+        let centers = self.bounds.iter().map(Aabb::center).collect::<Vec<_>>();
+        let mut stack = if self.nodes.is_empty() { Vec::new() } else { vec![0] };
+        while let Some(node) = stack.pop() {
+            let BvhNode { first, count, .. } = self.nodes[node];
+            let items = &mut self.items[first as usize..(first + count) as usize];
+            self.nodes[node].bounds = items.iter()
+                .map(|&item| self.bounds[item as usize])
+                .reduce(|a, b| a.union(&b))
+                .unwrap();
+            if items.len() <= Self::LEAF_SIZE {
+                continue;
+            }
+            let spread = Aabb::from_points(items.iter().map(|&item| centers[item as usize])).unwrap();
+            let size = spread.max - spread.min;
+            let axis = if size.x >= size.y && size.x >= size.z { 0 } else if size.y >= size.z { 1 } else { 2 };
+            let middle = items.len() / 2;
+            items.select_nth_unstable_by(middle, |&a, &b| centers[a as usize][axis].total_cmp(&centers[b as usize][axis]));
+
+            let left = self.nodes.len() as u32;
+            let bounds = self.nodes[node].bounds; // until the children fit their own
+            self.nodes.push(BvhNode { bounds, first, count: middle as u32 });
+            self.nodes.push(BvhNode { bounds, first: first + middle as u32, count: count - middle as u32 });
+            self.nodes[node].first = left;
+            self.nodes[node].count = 0;
+            stack.push(left as usize);
+            stack.push(left as usize + 1);
+        }
+        self.built_area = self.area();
+    }
+
+    // children come after their parents, so going backwards every node sees its children refit
+    fn refit(&mut self) {
+        for node in (0..self.nodes.len()).rev() {
+            let BvhNode { first, count, .. } = self.nodes[node];
+            self.nodes[node].bounds = if count == 0 {
+                self.nodes[first as usize].bounds.union(&self.nodes[first as usize + 1].bounds)
+            } else {
+                self.leaf(&self.nodes[node]).iter()
+                    .map(|&item| self.bounds[item as usize])
+                    .reduce(|a, b| a.union(&b))
+                    .unwrap()
+            };
+        }
+    }
+
+    fn area(&self) -> f32 {
+        self.nodes.iter().map(|node| node.bounds.surface_area()).sum()
+    }
+
+    fn leaf(&self, node: &BvhNode) -> &[u32] {
+        &self.items[node.first as usize..(node.first + node.count) as usize]
+    }
+
+    // calls visit with every item whose box is at least partly in the frustum, in no particular order;
+    // nothing under a node entirely inside is tested again
+    pub fn query_frustum(&self, frustum: &Frustum, mut visit: impl FnMut(usize)) {
+        let mut stack = Vec::new();
+        if !self.nodes.is_empty() {
+            stack.push((0, false));
+        }
+        while let Some((node, inside)) = stack.pop() {
+            let node = &self.nodes[node];
+            if !inside && !frustum.intersects_aabb(&node.bounds) {
+                continue;
+            }
+            let inside = inside || frustum.contains_aabb(&node.bounds);
+            if node.count == 0 {
+                stack.push((node.first as usize, inside));
+                stack.push((node.first as usize + 1, inside));
+                continue;
+            }
+            for &item in self.leaf(node) {
+                if inside || frustum.intersects_aabb(&self.bounds[item as usize]) {
+                    visit(item as usize);
+                }
+            }
+        }
+    }
+
+    // calls visit with every item whose box overlaps aabb, in no particular order
+    pub fn query_aabb(&self, aabb: &Aabb, mut visit: impl FnMut(usize)) {
+        let mut stack = Vec::new();
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            if !node.bounds.intersects(aabb) {
+                continue;
+            }
+            if node.count == 0 {
+                stack.extend([node.first as usize, node.first as usize + 1]);
+                continue;
+            }
+            for &item in self.leaf(node) {
+                if self.bounds[item as usize].intersects(aabb) {
+                    visit(item as usize);
+                }
+            }
+        }
+    }
+
+    // the closest item along the ray and where it is hit, in multiples of ray.direction. hit is
+    // called with the items whose boxes the ray passes through, nearer boxes first, and says where
+    // the item itself is hit, if it is; boxes behind the closest hit so far are skipped
+    pub fn query_ray(&self, ray: &Ray, mut hit: impl FnMut(usize) -> Option<f32>) -> Option<(usize, f32)> {
+        let mut closest: Option<(usize, f32)> = None;
+        let mut stack = Vec::new();
+        if let Some(distance) = self.nodes.first().and_then(|root| ray.intersect_aabb(&root.bounds)) {
+            stack.push((0, distance));
+        }
+        while let Some((node, distance)) = stack.pop() {
+            if closest.is_some_and(|(_, closest)| distance >= closest) {
+                continue;
+            }
+            let node = &self.nodes[node];
+            if node.count == 0 {
+                let child = |child: usize| Some((child, ray.intersect_aabb(&self.nodes[child].bounds)?));
+                let (left, right) = (child(node.first as usize), child(node.first as usize + 1));
+                // the nearer child goes on top
+                let (near, far) = match (left, right) {
+                    (Some(left), Some(right)) if right.1 < left.1 => (Some(right), Some(left)),
+                    children => children,
+                };
+                stack.extend(far);
+                stack.extend(near);
+                continue;
+            }
+            for &item in self.leaf(node) {
+                let Some(box_distance) = ray.intersect_aabb(&self.bounds[item as usize]) else {
+                    continue;
+                };
+                if closest.is_some_and(|(_, closest)| box_distance >= closest) {
+                    continue;
+                }
+                if let Some(distance) = hit(item as usize) {
+                    if !closest.is_some_and(|(_, closest)| distance >= closest) {
+                        closest = Some((item as usize, distance));
+                    }
+                }
+            }
+        }
+        closest
+    }
+
+    // up to count items by the distance from point to their boxes, nearest first; 0 for boxes around
+    // the point
+    pub fn query_nearest(&self, point: glam::Vec3, count: usize) -> Vec<(usize, f32)> {
+        let mut nearest = Vec::with_capacity(count.min(self.len()));
+        // nodes and items come out by distance, so once an item does nothing left can be nearer.
+        // Distances are never negative, where the bits of an f32 sort like the number
+        let mut queue = BinaryHeap::new();
+        if count > 0 && !self.nodes.is_empty() {
+            queue.push(Reverse((self.nodes[0].bounds.distance_to_point(point).to_bits(), Entry::Node(0))));
+        }
+        while let Some(Reverse((distance, entry))) = queue.pop() {
+            match entry {
+                Entry::Item(item) => {
+                    nearest.push((item as usize, f32::from_bits(distance)));
+                    if nearest.len() == count {
+                        break;
+                    }
+                }
+                Entry::Node(node) => {
+                    let node = &self.nodes[node as usize];
+                    if node.count == 0 {
+                        for child in [node.first, node.first + 1] {
+                            let distance = self.nodes[child as usize].bounds.distance_to_point(point);
+                            queue.push(Reverse((distance.to_bits(), Entry::Node(child))));
+                        }
+                        continue;
+                    }
+                    for &item in self.leaf(node) {
+                        let distance = self.bounds[item as usize].distance_to_point(point);
+                        queue.push(Reverse((distance.to_bits(), Entry::Item(item))));
+                    }
+                }
+            }
+        }
+        nearest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // xorshift, so the boxes are the same every run
+    struct Random(u32);
+
+    impl Random {
+        fn next(&mut self) -> f32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            (self.0 >> 8) as f32 / (1 << 24) as f32
+        }
+
+        fn range(&mut self, min: f32, max: f32) -> f32 {
+            min + (max - min) * self.next()
+        }
+
+        fn vec3(&mut self, min: f32, max: f32) -> glam::Vec3 {
+            glam::Vec3::new(self.range(min, max), self.range(min, max), self.range(min, max))
+        }
+
+        fn boxes(&mut self, count: usize) -> Vec<Aabb> {
+            (0..count)
+                .map(|_| {
+                    let center = self.vec3(-50.0, 50.0);
+                    let extents = self.vec3(0.1, 3.0);
+                    Aabb::new(center - extents, center + extents)
+                })
+                .collect()
+        }
+    }
+
+    fn sorted(mut items: Vec<usize>) -> Vec<usize> {
+        items.sort_unstable();
+        items
+    }
+
+    #[test]
+    fn query_aabb_and_frustum_match_brute_force() {
+        let mut random = Random(1);
+        let bounds = random.boxes(500);
+        let bvh = Bvh::new(bounds.clone());
+        for _ in 0..50 {
+            let center = random.vec3(-50.0, 50.0);
+            let aabb = Aabb::new(center - random.vec3(1.0, 20.0), center + random.vec3(1.0, 20.0));
+            let mut found = Vec::new();
+            bvh.query_aabb(&aabb, |item| found.push(item));
+            let expected = (0..bounds.len()).filter(|&item| bounds[item].intersects(&aabb)).collect::<Vec<_>>();
+            assert_eq!(sorted(found), expected);
+
+            let view = glam::Mat4::look_at_rh(random.vec3(-60.0, 60.0), random.vec3(-10.0, 10.0), glam::Vec3::Y);
+            let projection = glam::Mat4::perspective_rh(random.range(0.3, 1.5), 1.5, 0.1, random.range(20.0, 150.0));
+            let frustum = Frustum::from_view_proj(&(projection * view));
+            let mut found = Vec::new();
+            bvh.query_frustum(&frustum, |item| found.push(item));
+            let expected = (0..bounds.len()).filter(|&item| frustum.intersects_aabb(&bounds[item])).collect::<Vec<_>>();
+            assert_eq!(sorted(found), expected);
+        }
+    }
+
+    #[test]
+    fn query_ray_finds_the_closest_hit_and_skips_boxes_behind_it() {
+        let mut random = Random(2);
+        let bounds = random.boxes(500);
+        let bvh = Bvh::new(bounds.clone());
+        // odd items miss whatever their box says, so hit has to be asked
+        let hit_distance = |item: usize, ray: &Ray| ray.intersect_aabb(&bounds[item]).filter(|_| item & 1 == 0);
+        let (mut calls, mut boxes_hit) = (0, 0);
+        for _ in 0..200 {
+            let origin = random.vec3(-70.0, 70.0);
+            let ray = Ray::new(origin, random.vec3(-10.0, 10.0) - origin);
+
+            let mut closest_so_far = f32::INFINITY;
+            let found = bvh.query_ray(&ray, |item| {
+                let box_distance = ray.intersect_aabb(&bounds[item]);
+                assert!(box_distance.is_some_and(|distance| distance < closest_so_far), "item {item} is behind a hit");
+                calls += 1;
+                let distance = hit_distance(item, &ray);
+                closest_so_far = closest_so_far.min(distance.unwrap_or(f32::INFINITY));
+                distance
+            });
+
+            let expected = (0..bounds.len())
+                .filter_map(|item| Some((item, hit_distance(item, &ray)?)))
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            assert_eq!(found.map(|(_, distance)| distance), expected.map(|(_, distance)| distance));
+            if let Some((item, distance)) = found {
+                assert_eq!(hit_distance(item, &ray), Some(distance));
+            }
+            boxes_hit += bounds.iter().filter(|aabb| ray.intersect_aabb(aabb).is_some()).count();
+        }
+        assert!(calls < boxes_hit, "{calls} calls for {boxes_hit} boxes on the rays");
+    }
+
+    #[test]
+    fn query_nearest_orders_by_distance() {
+        let mut random = Random(3);
+        let bounds = random.boxes(500);
+        let bvh = Bvh::new(bounds.clone());
+        for _ in 0..50 {
+            // some points fall inside boxes, where distances are 0
+            let point = random.vec3(-60.0, 60.0);
+            let count = random.range(1.0, 40.0) as usize;
+            let found = bvh.query_nearest(point, count);
+
+            let mut expected = bounds.iter().map(|aabb| aabb.distance_to_point(point)).collect::<Vec<_>>();
+            expected.sort_unstable_by(f32::total_cmp);
+            expected.truncate(count);
+            assert_eq!(found.iter().map(|&(_, distance)| distance).collect::<Vec<_>>(), expected);
+            for &(item, distance) in &found {
+                assert_eq!(bounds[item].distance_to_point(point), distance);
+            }
+            let mut items = sorted(found.iter().map(|&(item, _)| item).collect());
+            items.dedup();
+            assert_eq!(items.len(), found.len());
+        }
+        assert!(bvh.query_nearest(glam::Vec3::ZERO, 0).is_empty());
+        assert_eq!(bvh.query_nearest(glam::Vec3::ZERO, 1000).len(), bounds.len());
+    }
+
+    #[test]
+    fn update_refits_small_moves_and_rebuilds_after_large_ones() {
+        let mut random = Random(4);
+        let bounds = random.boxes(200);
+        let mut bvh = Bvh::new(bounds.clone());
+        let (built_area, items) = (bvh.built_area, bvh.items.clone());
+
+        // nudged boxes keep the tree
+        let nudged = bounds.iter()
+            .map(|aabb| {
+                let offset = random.vec3(-0.5, 0.5);
+                Aabb::new(aabb.min + offset, aabb.max + offset)
+            })
+            .collect::<Vec<_>>();
+        bvh.update(nudged.clone());
+        assert_eq!((bvh.built_area, &bvh.items), (built_area, &items));
+        assert!(bvh.area() <= 2.0 * built_area);
+        for (node, parent) in bvh.nodes.iter().zip(0..) {
+            if node.count == 0 {
+                for child in [node.first, node.first + 1] {
+                    let child = bvh.nodes[child as usize].bounds;
+                    assert_eq!(bvh.nodes[parent].bounds.union(&child), bvh.nodes[parent].bounds);
+                }
+            } else {
+                for &item in bvh.leaf(node) {
+                    assert_eq!(node.bounds.union(&nudged[item as usize]), node.bounds);
+                }
+            }
+        }
+
+        // shuffled boxes would leave a refit tree loose
+        let mut shuffled = nudged.clone();
+        for i in (1..shuffled.len()).rev() {
+            shuffled.swap(i, random.range(0.0, i as f32 + 1.0) as usize);
+        }
+        bvh.update(shuffled.clone());
+        assert_ne!(bvh.built_area, built_area);
+        assert_eq!(bvh.area(), bvh.built_area);
+
+        // a different count always rebuilds
+        let more = random.boxes(300);
+        bvh.update(more.clone());
+        assert_eq!(bvh.len(), 300);
+        assert_eq!(bvh.area(), bvh.built_area);
+        let everything = Aabb::new(glam::Vec3::splat(-100.0), glam::Vec3::splat(100.0));
+        let mut found = Vec::new();
+        bvh.query_aabb(&everything, |item| found.push(item));
+        assert_eq!(sorted(found), (0..more.len()).collect::<Vec<_>>());
+    }
+}
@@ -1,31 +1,131 @@
-use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
+use super::picking::Ray;
+use super::texture::DepthMode;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use winit::event::{DeviceEvent, WindowEvent};
 
+mod fly;
+mod orbit;
+mod path;
+
+pub use fly::FlyController;
+pub use orbit::OrbitController;
+pub use path::{CameraKeyframe, CameraPath, CameraPathError};
+
+// maps OpenGL's -1..1 depth to wgpu's 0..1; from_cols_array is column major, so each row here is a column
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: glam::Mat4 = glam::Mat4::from_cols_array(&[
     1.0, 0.0, 0.0, 0.0,
     0.0, 1.0, 0.0, 0.0,
-    0.0, 0.0, 0.5, 0.5,
-    0.0, 0.0, 0.0, 1.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+]);
+
+// maps depth d to 1 - d
+#[rustfmt::skip]
+pub const REVERSE_Z_MATRIX: glam::Mat4 = glam::Mat4::from_cols_array(&[
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, -1.0, 0.0,
+    0.0, 0.0, 1.0, 1.0,
 ]);
 
 // This is so we can store this in a buffer
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
+    // eye position for lighting; vec4 because uniforms need 16 byte alignment
+    pub view_position: [f32; 4],
     // We can't use cgmath with bytemuck directly so we'll have
     // to convert the Matrix4 into a 4x4 f32 array
     pub view_proj: [[f32; 4]; 4],
+    // clip space back to world space, for reconstructing positions from depth
+    pub inv_view_proj: [[f32; 4]; 4],
 }
 
 impl CameraUniform {
     pub fn new() -> Self {
         Self {
+            view_position: [0.0; 4],
             view_proj: glam::Mat4::IDENTITY.to_cols_array_2d(),
+            inv_view_proj: glam::Mat4::IDENTITY.to_cols_array_2d(),
         }
     }
 
     pub fn update_view_proj(&mut self, camera: &Camera) {
-        self.view_proj = camera.build_view_projection_matrix().to_cols_array_2d();
+        // the shaders tell the background apart by its depth
+        self.view_position = camera.eye.extend(camera.depth_mode.far_depth()).to_array();
+        let view_proj = camera.build_view_projection_matrix();
+        self.view_proj = view_proj.to_cols_array_2d();
+        self.inv_view_proj = view_proj.inverse().to_cols_array_2d();
+    }
+}
+
+// how view space is mapped to clip space; independent of where the camera is
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Projection {
+    // fovy in degrees; zfar can be f32::INFINITY for no far plane
+    Perspective { fovy: f32, znear: f32, zfar: f32 },
+    // height is the visible height in units; the width follows from the aspect
+    Orthographic { height: f32, znear: f32, zfar: f32 },
+    // no far plane and depth going from 1 at znear to 0 at infinity whatever the depth mode;
+    // only draws with DepthMode::ReverseZ, see for_depth_mode
+    InfinitePerspectiveReverseZ { fovy: f32, znear: f32 },
+    // view space to wgpu clip space as is; neither the aspect nor the depth mode is applied
+    Custom(glam::Mat4),
+}
+
+impl Projection {
+    pub fn matrix(&self, aspect: f32, depth_mode: DepthMode) -> glam::Mat4 {
+        let reverse = |matrix: glam::Mat4| match depth_mode {
+            DepthMode::Standard => matrix,
+            DepthMode::ReverseZ => REVERSE_Z_MATRIX * matrix,
+        };
+        match *self {
+            Projection::Perspective { fovy, znear, zfar } if zfar.is_infinite() => match depth_mode {
+                DepthMode::Standard => glam::Mat4::perspective_infinite_rh(fovy.to_radians(), aspect, znear),
+                DepthMode::ReverseZ => glam::Mat4::perspective_infinite_reverse_rh(fovy.to_radians(), aspect, znear),
+            },
+            Projection::Perspective { fovy, znear, zfar } => {
+                reverse(OPENGL_TO_WGPU_MATRIX * glam::Mat4::perspective_rh_gl(fovy.to_radians(), aspect, znear, zfar))
+            }
+            Projection::Orthographic { height, znear, zfar } => {
+                let half_height = height * 0.5;
+                let half_width = half_height * aspect;
+                reverse(OPENGL_TO_WGPU_MATRIX * glam::Mat4::orthographic_rh_gl(-half_width, half_width, -half_height, half_height, znear, zfar))
+            }
+            // glam builds this one for a 0..1 depth range already
+            Projection::InfinitePerspectiveReverseZ { fovy, znear } => {
+                glam::Mat4::perspective_infinite_reverse_rh(fovy.to_radians(), aspect, znear)
+            }
+            Projection::Custom(matrix) => matrix,
+        }
+    }
+
+    // the projection as it can draw with the depth mode: InfinitePerspectiveReverseZ with standard
+    // depth becomes the standard infinite perspective, everything else stays as is
+    pub fn for_depth_mode(self, depth_mode: DepthMode) -> Self {
+        match (self, depth_mode) {
+            (Projection::InfinitePerspectiveReverseZ { fovy, znear }, DepthMode::Standard) => {
+                Projection::Perspective { fovy, znear, zfar: f32::INFINITY }
+            }
+            (projection, _) => projection,
+        }
+    }
+
+    // changes the field of view of projections that have one
+    pub fn set_fovy(&mut self, new_fovy: f32) {
+        if let Projection::Perspective { fovy, .. } | Projection::InfinitePerspectiveReverseZ { fovy, .. } = self {
+            *fovy = new_fovy;
+        }
+    }
+
+    // vertical field of view in degrees, for projections that have one
+    pub fn fovy(&self) -> Option<f32> {
+        match *self {
+            Projection::Perspective { fovy, .. } | Projection::InfinitePerspectiveReverseZ { fovy, .. } => Some(fovy),
+            Projection::Orthographic { .. } | Projection::Custom(_) => None,
+        }
     }
 }
 
@@ -33,106 +133,93 @@ pub struct Camera {
     pub eye: glam::Vec3,
     pub target: glam::Vec3,
     pub up: glam::Vec3,
-    pub aspect: f32,
-    pub fovy: f32,
-    pub znear: f32,
-    pub zfar: f32,
+    pub aspect: f32, // width / height, kept up to date by State::resize
+    pub projection: Projection,
+    pub depth_mode: DepthMode, // must match how the depth buffer is cleared and compared, see State::with_settings
 }
 
 impl Camera {
-    fn build_view_projection_matrix(&self) -> glam::Mat4  {
+    pub fn build_view_projection_matrix(&self) -> glam::Mat4  {
         // 1.
         let view = glam::Mat4 ::look_at_rh(self.eye, self.target, self.up);
         // 2.
-        let proj = glam::Mat4::perspective_rh_gl(self.fovy.to_radians(), self.aspect, self.znear, self.zfar);
+        let proj = self.build_projection_matrix();
 
         // 3.
-        return OPENGL_TO_WGPU_MATRIX * proj * view;
+        return proj * view;
+    }
+
+    // view space to wgpu clip space
+    pub fn build_projection_matrix(&self) -> glam::Mat4 {
+        self.projection.matrix(self.aspect, self.depth_mode)
+    }
+
+    // world space ray through a pixel, starting at the near plane; cursor and size in pixels
+    pub fn screen_ray(&self, cursor: glam::Vec2, size: glam::Vec2) -> Ray {
+        let ndc = glam::Vec2::new(cursor.x / size.x * 2.0 - 1.0, 1.0 - cursor.y / size.y * 2.0);
+        let inv_view_proj = self.build_view_projection_matrix().inverse();
+        // halfway into the depth range instead of the far plane, which may be at infinity
+        let near = inv_view_proj.project_point3(ndc.extend(1.0 - self.depth_mode.far_depth()));
+        let middle = inv_view_proj.project_point3(ndc.extend(0.5));
+        Ray::new(near, (middle - near).normalize())
     }
 }
 
-pub struct CameraController {
-    pub speed: f32,
-    pub is_forward_pressed: bool,
-    pub is_backward_pressed: bool,
-    pub is_left_pressed: bool,
-    pub is_right_pressed: bool,
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CameraMode {
+    Orbit, // keys move the eye around target
+    Fly, // first person; mouse looks around, keys move along the view
+}
+
+pub enum CameraController {
+    Orbit(OrbitController),
+    Fly(FlyController),
 }
 
 impl CameraController {
-    pub fn new(speed: f32) -> Self {
-        Self {
-            speed,
-            is_forward_pressed: false,
-            is_backward_pressed: false,
-            is_left_pressed: false,
-            is_right_pressed: false,
+    pub fn mode(&self) -> CameraMode {
+        match self {
+            CameraController::Orbit(_) => CameraMode::Orbit,
+            CameraController::Fly(_) => CameraMode::Fly,
         }
     }
 
     pub fn process_events(&mut self, event: &WindowEvent) -> bool {
-        match event {
-            WindowEvent::KeyboardInput {
-                input: KeyboardInput {
-                    state,
-                    virtual_keycode: Some(keycode),
-                    ..
-                },
-                ..
-            } => {
-                let is_pressed = *state == ElementState::Pressed;
-                match keycode {
-                    VirtualKeyCode::W | VirtualKeyCode::Up => {
-                        self.is_forward_pressed = is_pressed;
-                        true
-                    }
-                    VirtualKeyCode::A | VirtualKeyCode::Left => {
-                        self.is_left_pressed = is_pressed;
-                        true
-                    }
-                    VirtualKeyCode::S | VirtualKeyCode::Down => {
-                        self.is_backward_pressed = is_pressed;
-                        true
-                    }
-                    VirtualKeyCode::D | VirtualKeyCode::Right => {
-                        self.is_right_pressed = is_pressed;
-                        true
-                    }
-                    _ => false,
-                }
-            }
-            _ => false,
+        match self {
+            CameraController::Orbit(controller) => controller.process_events(event),
+            CameraController::Fly(controller) => controller.process_events(event),
         }
     }
 
-    pub fn update_camera(&self, camera: &mut Camera) {
-        let forward = camera.target - camera.eye;
-        let forward_norm = forward.normalize();
-        let forward_mag = forward.length();
-
-        // Prevents glitching when camera gets too close to the
-        // center of the scene.
-        if self.is_forward_pressed && forward_mag > self.speed {
-            camera.eye += forward_norm * self.speed;
-        }
-        if self.is_backward_pressed {
-            camera.eye -= forward_norm * self.speed;
+    // raw device input, e.g. mouse motion that isn't limited by the window edges
+    pub fn process_device_events(&mut self, event: &DeviceEvent) -> bool {
+        match self {
+            CameraController::Orbit(_) => false,
+            CameraController::Fly(controller) => controller.process_device_events(event),
         }
+    }
 
-        let right = forward_norm.cross(camera.up);
-
-        // Redo radius calc in case the forward/backward is pressed.
-        let forward = camera.target - camera.eye;
-        let forward_mag = forward.length();
-
-        if self.is_right_pressed {
-            // Rescale the distance between the target and eye so
-            // that it doesn't change. The eye therefore still
-            // lies on the circle made by the target and eye.
-            camera.eye = camera.target - (forward + right * self.speed).normalize() * forward_mag;
+    pub fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
+        match self {
+            CameraController::Orbit(controller) => controller.update_camera(camera, dt),
+            CameraController::Fly(controller) => controller.update_camera(camera, dt),
         }
-        if self.is_left_pressed {
-            camera.eye = camera.target - (forward - right * self.speed).normalize() * forward_mag;
+    }
+
+    // seconds for key movement to reach and lose its full speed; 0 starts and stops at once
+    pub fn set_smoothing(&mut self, smoothing: f32) {
+        match self {
+            CameraController::Orbit(controller) => controller.smoothing = smoothing,
+            CameraController::Fly(controller) => controller.smoothing = smoothing,
         }
     }
 }
+
+// how far to move towards a target velocity this frame, independent of the frame rate
+fn smoothing_factor(smoothing: f32, dt: f32) -> f32 {
+    if smoothing <= 0.0 {
+        1.0
+    } else {
+        1.0 - (-dt / smoothing).exp()
+    }
+}
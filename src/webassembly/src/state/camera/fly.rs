@@ -0,0 +1,150 @@
+use super::Camera;
+use std::time::Duration;
+use winit::event::{DeviceEvent, ElementState, KeyboardInput, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+// first person camera: mouse motion turns, WASD moves along the view, Space/Shift up and down
+pub struct FlyController {
+    pub speed: f32, // units per second
+    pub sensitivity: f32, // radians per pixel of mouse motion
+    pub smoothing: f32, // seconds to speed up and slow down, see CameraController::set_smoothing
+    velocity: glam::Vec3,
+    yaw: f32, // around the up axis, 0 looks along -z
+    pitch: f32,
+    rotate_horizontal: f32, // mouse motion since the last update
+    rotate_vertical: f32,
+    is_forward_pressed: bool,
+    is_backward_pressed: bool,
+    is_left_pressed: bool,
+    is_right_pressed: bool,
+    is_up_pressed: bool,
+    is_down_pressed: bool,
+}
+
+impl FlyController {
+    const MAX_PITCH: f32 = 89.0 * std::f32::consts::PI / 180.0; // straight up or down flips the view
+    const SPEED_STEP: f32 = 1.1; // speed factor per scroll line
+
+    // starts looking the way the camera currently does
+    pub fn new(speed: f32, sensitivity: f32, camera: &Camera) -> Self {
+        let forward = (camera.target - camera.eye).normalize_or_zero();
+        Self {
+            speed,
+            sensitivity,
+            smoothing: 0.0,
+            velocity: glam::Vec3::ZERO,
+            yaw: forward.x.atan2(-forward.z),
+            pitch: forward.y.clamp(-1.0, 1.0).asin().clamp(-Self::MAX_PITCH, Self::MAX_PITCH),
+            rotate_horizontal: 0.0,
+            rotate_vertical: 0.0,
+            is_forward_pressed: false,
+            is_backward_pressed: false,
+            is_left_pressed: false,
+            is_right_pressed: false,
+            is_up_pressed: false,
+            is_down_pressed: false,
+        }
+    }
+
+    pub fn process_events(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state,
+                    virtual_keycode: Some(keycode),
+                    ..
+                },
+                ..
+            } => {
+                let is_pressed = *state == ElementState::Pressed;
+                match keycode {
+                    VirtualKeyCode::W | VirtualKeyCode::Up => {
+                        self.is_forward_pressed = is_pressed;
+                        true
+                    }
+                    VirtualKeyCode::A | VirtualKeyCode::Left => {
+                        self.is_left_pressed = is_pressed;
+                        true
+                    }
+                    VirtualKeyCode::S | VirtualKeyCode::Down => {
+                        self.is_backward_pressed = is_pressed;
+                        true
+                    }
+                    VirtualKeyCode::D | VirtualKeyCode::Right => {
+                        self.is_right_pressed = is_pressed;
+                        true
+                    }
+                    VirtualKeyCode::Space => {
+                        self.is_up_pressed = is_pressed;
+                        true
+                    }
+                    VirtualKeyCode::LShift | VirtualKeyCode::RShift => {
+                        self.is_down_pressed = is_pressed;
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            // scrolling changes how fast the camera flies
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 50.0,
+                };
+                self.speed *= Self::SPEED_STEP.powf(lines);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn process_device_events(&mut self, event: &DeviceEvent) -> bool {
+        match event {
+            DeviceEvent::MouseMotion { delta: (dx, dy) } => {
+                self.rotate_horizontal += *dx as f32;
+                self.rotate_vertical += *dy as f32;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
+        let dt = dt.as_secs_f32();
+        // mouse motion is already a distance, so turning doesn't scale with dt
+        self.yaw += self.rotate_horizontal * self.sensitivity;
+        self.pitch = (self.pitch - self.rotate_vertical * self.sensitivity).clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+        self.rotate_horizontal = 0.0;
+        self.rotate_vertical = 0.0;
+
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        let forward = glam::Vec3::new(sin_yaw * cos_pitch, sin_pitch, -cos_yaw * cos_pitch);
+        let right = forward.cross(camera.up).normalize();
+
+        let mut direction = glam::Vec3::ZERO;
+        if self.is_forward_pressed {
+            direction += forward;
+        }
+        if self.is_backward_pressed {
+            direction -= forward;
+        }
+        if self.is_right_pressed {
+            direction += right;
+        }
+        if self.is_left_pressed {
+            direction -= right;
+        }
+        if self.is_up_pressed {
+            direction += camera.up;
+        }
+        if self.is_down_pressed {
+            direction -= camera.up;
+        }
+        let target_velocity = direction.normalize_or_zero() * self.speed;
+        self.velocity = self.velocity.lerp(target_velocity, super::smoothing_factor(self.smoothing, dt));
+        camera.eye += self.velocity * dt;
+
+        // keep the target one unit ahead so the orbit controller has something to circle
+        camera.target = camera.eye + forward;
+    }
+}
@@ -0,0 +1,201 @@
+use super::{Camera, Projection};
+use std::time::Duration;
+use crate::state::bounds;
+use winit::event::{ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+// keeps the eye on a sphere around the target: left drag (or A/D) rotates, scrolling (or W/S)
+// zooms and middle drag pans the target
+pub struct OrbitController {
+    pub speed: f32, // key zoom and key rotation along the orbit, in units per second
+    pub sensitivity: f32, // radians per pixel of dragging
+    pub smoothing: f32, // seconds for keys to speed up and slow down, see CameraController::set_smoothing
+    key_velocity: glam::Vec2, // yaw in radians and distance in units per second from keys
+    pub min_distance: f32,
+    pub max_distance: f32,
+    yaw: f32, // around the up axis, 0 puts the eye on +z of the target
+    pitch: f32, // above the target's horizon
+    distance: f32,
+    is_rotating: bool,
+    is_panning: bool,
+    last_cursor: Option<glam::Vec2>,
+    rotate_delta: glam::Vec2, // cursor motion since the last update
+    pan_delta: glam::Vec2,
+    zoom_lines: f32,
+    is_forward_pressed: bool,
+    is_backward_pressed: bool,
+    is_left_pressed: bool,
+    is_right_pressed: bool,
+}
+
+impl OrbitController {
+    const MAX_PITCH: f32 = 89.0 * std::f32::consts::PI / 180.0; // over the pole flips the view
+    const ZOOM_STEP: f32 = 0.9; // distance factor per scroll line
+    const PAN_SENSITIVITY: f32 = 0.002; // target motion per pixel, relative to the distance
+
+    // starts from where the camera currently is
+    pub fn new(speed: f32, camera: &Camera) -> Self {
+        let offset = camera.eye - camera.target;
+        let distance = offset.length().max(0.001);
+        Self {
+            speed,
+            sensitivity: 0.005,
+            smoothing: 0.0,
+            key_velocity: glam::Vec2::ZERO,
+            min_distance: 0.5,
+            max_distance: 100.0,
+            yaw: offset.x.atan2(offset.z),
+            pitch: (offset.y / distance).clamp(-1.0, 1.0).asin().clamp(-Self::MAX_PITCH, Self::MAX_PITCH),
+            distance,
+            is_rotating: false,
+            is_panning: false,
+            last_cursor: None,
+            rotate_delta: glam::Vec2::ZERO,
+            pan_delta: glam::Vec2::ZERO,
+            zoom_lines: 0.0,
+            is_forward_pressed: false,
+            is_backward_pressed: false,
+            is_left_pressed: false,
+            is_right_pressed: false,
+        }
+    }
+
+    pub fn process_events(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state,
+                    virtual_keycode: Some(keycode),
+                    ..
+                },
+                ..
+            } => {
+                let is_pressed = *state == ElementState::Pressed;
+                match keycode {
+                    VirtualKeyCode::W | VirtualKeyCode::Up => {
+                        self.is_forward_pressed = is_pressed;
+                        true
+                    }
+                    VirtualKeyCode::A | VirtualKeyCode::Left => {
+                        self.is_left_pressed = is_pressed;
+                        true
+                    }
+                    VirtualKeyCode::S | VirtualKeyCode::Down => {
+                        self.is_backward_pressed = is_pressed;
+                        true
+                    }
+                    VirtualKeyCode::D | VirtualKeyCode::Right => {
+                        self.is_right_pressed = is_pressed;
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let is_pressed = *state == ElementState::Pressed;
+                match button {
+                    MouseButton::Left => {
+                        self.is_rotating = is_pressed;
+                        true
+                    }
+                    MouseButton::Middle => {
+                        self.is_panning = is_pressed;
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let cursor = glam::Vec2::new(position.x as f32, position.y as f32);
+                let delta = self.last_cursor.map_or(glam::Vec2::ZERO, |last| cursor - last);
+                self.last_cursor = Some(cursor);
+                if self.is_rotating {
+                    self.rotate_delta += delta;
+                }
+                if self.is_panning {
+                    self.pan_delta += delta;
+                }
+                self.is_rotating || self.is_panning
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.last_cursor = None;
+                false
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.zoom_lines += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 50.0,
+                };
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // moves the target to the center of the bounds and the eye back far enough to see all of it;
+    // orthographic projections are resized to fit instead, custom ones only get the new target
+    pub fn frame(&mut self, camera: &mut Camera, bounds: &bounds::Aabb) {
+        let sphere = bounds::BoundingSphere::from(*bounds);
+        match &mut camera.projection {
+            Projection::Perspective { fovy, .. } | Projection::InfinitePerspectiveReverseZ { fovy, .. } => {
+                // the narrower of the vertical and horizontal field of view
+                let half_fovy = fovy.to_radians() * 0.5;
+                let half_fov = half_fovy.min((half_fovy.tan() * camera.aspect).atan());
+                self.distance = (sphere.radius / half_fov.sin()).clamp(self.min_distance, self.max_distance);
+            }
+            Projection::Orthographic { height, .. } => {
+                *height = 2.0 * sphere.radius * (1.0 / camera.aspect).max(1.0);
+                self.distance = (2.0 * sphere.radius).clamp(self.min_distance, self.max_distance);
+            }
+            Projection::Custom(_) => {}
+        }
+        camera.target = sphere.center;
+        self.place_eye(camera);
+    }
+
+    pub fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
+        let dt = dt.as_secs_f32();
+
+        // keys orbit at speed units per second along the circle and zoom at speed units per second
+        let mut target_velocity = glam::Vec2::ZERO;
+        if self.is_right_pressed {
+            target_velocity.x += self.speed / self.distance;
+        }
+        if self.is_left_pressed {
+            target_velocity.x -= self.speed / self.distance;
+        }
+        if self.is_forward_pressed {
+            target_velocity.y -= self.speed;
+        }
+        if self.is_backward_pressed {
+            target_velocity.y += self.speed;
+        }
+        self.key_velocity = self.key_velocity.lerp(target_velocity, super::smoothing_factor(self.smoothing, dt));
+
+        // dragging and scrolling are distances already and don't scale with dt
+        self.yaw += self.key_velocity.x * dt - self.rotate_delta.x * self.sensitivity;
+        self.pitch = (self.pitch + self.rotate_delta.y * self.sensitivity).clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+        let distance = self.distance * Self::ZOOM_STEP.powf(self.zoom_lines) + self.key_velocity.y * dt;
+        self.distance = distance.clamp(self.min_distance, self.max_distance);
+
+        // pan in the view plane, faster when further away
+        if self.pan_delta != glam::Vec2::ZERO {
+            let forward = (camera.target - camera.eye).normalize();
+            let right = forward.cross(camera.up).normalize();
+            let up = right.cross(forward);
+            let scale = self.distance * Self::PAN_SENSITIVITY;
+            camera.target += (-right * self.pan_delta.x + up * self.pan_delta.y) * scale;
+        }
+
+        self.rotate_delta = glam::Vec2::ZERO;
+        self.pan_delta = glam::Vec2::ZERO;
+        self.zoom_lines = 0.0;
+        self.place_eye(camera);
+    }
+
+    fn place_eye(&self, camera: &mut Camera) {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        let offset = glam::Vec3::new(sin_yaw * cos_pitch, sin_pitch, cos_yaw * cos_pitch);
+        camera.eye = camera.target + offset * self.distance;
+    }
+}
@@ -0,0 +1,173 @@
+use super::Camera;
+use std::fmt;
+use std::str::FromStr;
+
+// where the camera is at a point in time
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CameraKeyframe {
+    pub time: f32, // seconds from the start of the path
+    pub eye: glam::Vec3,
+    pub target: glam::Vec3,
+    pub fovy: f32, // degrees; ignored by projections without a field of view
+}
+
+impl CameraKeyframe {
+    // the camera as it is now
+    pub fn from_camera(time: f32, camera: &Camera) -> Self {
+        Self {
+            time,
+            eye: camera.eye,
+            target: camera.target,
+            fovy: camera.projection.fovy().unwrap_or(45.0),
+        }
+    }
+
+    // which way the camera looks, with the up vector as the camera's y axis
+    fn orientation(&self, up: glam::Vec3) -> glam::Quat {
+        let forward = (self.target - self.eye).normalize_or_zero();
+        let right = forward.cross(up).normalize_or_zero();
+        if right == glam::Vec3::ZERO {
+            // looking straight up or down
+            return glam::Quat::from_rotation_arc(glam::Vec3::NEG_Z, forward);
+        }
+        glam::Quat::from_mat3(&glam::Mat3::from_cols(right, right.cross(forward), -forward))
+    }
+
+    fn is_finite(&self) -> bool {
+        self.time.is_finite() && self.eye.is_finite() && self.target.is_finite() && self.fovy.is_finite()
+    }
+}
+
+#[derive(Debug)]
+pub enum CameraPathError {
+    InvalidLine(usize), // 1-based line number of a keyframe that isn't 8 finite numbers
+    NonFinite,          // a keyframe with a NaN or infinite value
+}
+
+impl fmt::Display for CameraPathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CameraPathError::InvalidLine(line) => write!(f, "line {line}: expected time, eye xyz, target xyz and fovy"),
+            CameraPathError::NonFinite => write!(f, "keyframe values must be finite"),
+        }
+    }
+}
+
+// keyframes played back in order: Catmull-Rom through the eye positions, slerp between the view
+// directions. Saved as text with one keyframe per line, `time eye.x eye.y eye.z target.x target.y
+// target.z fovy`, an optional `loop` line and `#` comments
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CameraPath {
+    keyframes: Vec<CameraKeyframe>, // sorted by time
+    pub looping: bool,
+}
+
+impl CameraPath {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // keeps the keyframes sorted; one at the same time as an existing one goes after it. NaN or
+    // infinite values are refused, as sample couldn't place them in time
+    pub fn add(&mut self, keyframe: CameraKeyframe) -> Result<(), CameraPathError> {
+        if !keyframe.is_finite() {
+            return Err(CameraPathError::NonFinite);
+        }
+        let index = self.keyframes.partition_point(|other| other.time <= keyframe.time);
+        self.keyframes.insert(index, keyframe);
+        Ok(())
+    }
+
+    pub fn keyframes(&self) -> &[CameraKeyframe] {
+        &self.keyframes
+    }
+
+    // time of the last keyframe
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    // the camera at a time, held at the ends or wrapped around when looping; None without keyframes
+    pub fn sample(&self, time: f32, up: glam::Vec3) -> Option<CameraKeyframe> {
+        let first = self.keyframes.first()?;
+        let duration = self.duration();
+        let time = if self.looping && duration > first.time {
+            first.time + (time - first.time).rem_euclid(duration - first.time)
+        } else {
+            time.clamp(first.time, duration)
+        };
+
+        // the segment from keyframe i to i + 1 and its neighbours, repeating the ends
+        let i = self.keyframes.partition_point(|keyframe| keyframe.time <= time).clamp(1, self.keyframes.len()) - 1;
+        let key = |index: usize| &self.keyframes[index.min(self.keyframes.len() - 1)];
+        let (k0, k1, k2, k3) = (key(i.saturating_sub(1)), key(i), key(i + 1), key(i + 2));
+        let span = k2.time - k1.time;
+        let t = if span > 0.0 { (time - k1.time) / span } else { 0.0 };
+
+        let eye = catmull_rom(k0.eye, k1.eye, k2.eye, k3.eye, t);
+        let orientation = k1.orientation(up).slerp(k2.orientation(up), t);
+        let distance = k1.eye.distance(k1.target) + (k2.eye.distance(k2.target) - k1.eye.distance(k1.target)) * t;
+        Some(CameraKeyframe {
+            time,
+            eye,
+            target: eye + orientation * glam::Vec3::NEG_Z * distance,
+            fovy: k1.fovy + (k2.fovy - k1.fovy) * t,
+        })
+    }
+}
+
+// uniform Catmull-Rom spline between p1 and p2
+fn catmull_rom(p0: glam::Vec3, p1: glam::Vec3, p2: glam::Vec3, p3: glam::Vec3, t: f32) -> glam::Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * (2.0 * p1 + (p2 - p0) * t + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2 + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+impl fmt::Display for CameraPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# time eye.x eye.y eye.z target.x target.y target.z fovy")?;
+        if self.looping {
+            writeln!(f, "loop")?;
+        }
+        for k in &self.keyframes {
+            writeln!(
+                f,
+                "{} {} {} {} {} {} {} {}",
+                k.time, k.eye.x, k.eye.y, k.eye.z, k.target.x, k.target.y, k.target.z, k.fovy,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for CameraPath {
+    type Err = CameraPathError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut path = CameraPath::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line == "loop" {
+                path.looping = true;
+                continue;
+            }
+            let values = line.split_whitespace().map(str::parse::<f32>).collect::<Result<Vec<_>, _>>()
+                .ok()
+                .and_then(|values| <[f32; 8]>::try_from(values).ok());
+            let Some([time, ex, ey, ez, tx, ty, tz, fovy]) = values else {
+                return Err(CameraPathError::InvalidLine(number + 1));
+            };
+            path.add(CameraKeyframe {
+                time,
+                eye: glam::Vec3::new(ex, ey, ez),
+                target: glam::Vec3::new(tx, ty, tz),
+                fovy,
+            })
+            .map_err(|_| CameraPathError::InvalidLine(number + 1))?;
+        }
+        Ok(path)
+    }
+}
@@ -0,0 +1,83 @@
+use crate::state::bounds::{Aabb, BoundingSphere};
+use crate::state::mesh::GpuMesh;
+
+mod gpu;
+
+pub use gpu::GpuCulling;
+
+// where instances are culled
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum CullingMode {
+    // on the CPU before uploading the visible instances; fine for hundreds of instances
+    #[default]
+    Cpu,
+    // in a compute pass feeding an indirect draw, for tens of thousands; can also cull occluded
+    // instances against a Hi-Z pyramid, see State::set_occlusion_culling
+    Gpu,
+}
+
+// the six planes of what a view-projection matrix can see; a point p is inside a plane
+// when dot(plane.xyz, p) + plane.w >= 0
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Frustum {
+    pub planes: [glam::Vec4; 6], // left, right, bottom, top, near, far
+}
+
+impl Frustum {
+    // Gribb/Hartmann plane extraction for wgpu's 0..1 clip space depth; works for reverse-Z too
+    pub fn from_view_proj(view_proj: &glam::Mat4) -> Self {
+        let row = |i| view_proj.row(i);
+        let planes = [
+            row(3) + row(0),
+            row(3) - row(0),
+            row(3) + row(1),
+            row(3) - row(1),
+            row(2),
+            row(3) - row(2),
+        ];
+        Self {
+            planes: planes.map(|plane| {
+                // an infinite far plane has no normal and keeps everything inside
+                let length = plane.truncate().length();
+                if length > f32::EPSILON { plane / length } else { plane }
+            }),
+        }
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes.iter().all(|plane| plane.truncate().dot(sphere.center) + plane.w >= -sphere.radius)
+    }
+
+    // tests the corner furthest along each plane's normal; may keep boxes just outside a frustum corner
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            let corner = glam::Vec3::select(normal.cmpge(glam::Vec3::ZERO), aabb.max, aabb.min);
+            normal.dot(corner) + plane.w >= 0.0
+        })
+    }
+
+    // whether the whole box is inside, so nothing in it needs testing
+    pub fn contains_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            let corner = glam::Vec3::select(normal.cmpge(glam::Vec3::ZERO), aabb.min, aabb.max);
+            normal.dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
+// how much of the viewport height the sphere around a world space box covers, for picking levels of
+// detail; projection_scale is the projection matrix's y scale. Boxes around the camera count as huge
+pub fn screen_size(view_proj: &glam::Mat4, projection_scale: f32, bounds: &Aabb) -> f32 {
+    let w = (*view_proj * bounds.center().extend(1.0)).w;
+    bounds.extents().length() * projection_scale / w.max(f32::EPSILON)
+}
+
+// what the last CPU culling pass did with the instances; GPU culling keeps its counts on the GPU
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct CullingStats {
+    pub drawn: usize,
+    pub culled: usize,
+    pub lods: [usize; GpuMesh::MAX_LODS], // how many of the drawn instances use each level of detail
+}
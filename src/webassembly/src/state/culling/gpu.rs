@@ -0,0 +1,446 @@
+use crate::state::{bounds::Aabb, camera, instance_buffer::InstanceBuffer, mesh::GpuMesh, texture};
+use super::Frustum;
+use wgpu::util::DeviceExt;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CullUniform {
+    view_proj: [[f32; 4]; 4],
+    planes: [[f32; 4]; 6],
+    instance_count: u32,
+    hi_z: u32,
+    far_depth: f32,
+    pyramid_levels: u32,
+    mesh_count: u32,
+    projection_scale: f32, // the projection's y scale, for screen sizes
+    _padding: [u32; 2],
+}
+
+// what culling needs of one of State's meshes
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CullMesh {
+    bounds_min: [f32; 4],
+    bounds_max: [f32; 4],
+    lod_screen_sizes: [[f32; 4]; 2], // GpuLod::screen_size, GpuMesh::MAX_LODS of them
+    lod_count: u32,
+    first_draw: u32, // of the mesh's levels in the draw args
+    _padding: [u32; 2],
+}
+
+// culling in a compute pass: the indices of the visible instances of the scene's instance buffer are
+// appended to the visible buffer, in a region per level of detail of their mesh, and counted in a
+// DrawIndexedIndirect per region, ready for draw
+pub struct GpuCulling {
+    uniform: CullUniform,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    meshes: Vec<CullMesh>,
+    mesh_buffer: wgpu::Buffer,
+    meshes_dirty: bool, // meshes changed since the last upload
+    draw_count: usize, // levels of detail of every mesh together
+    visible_buffer: InstanceBuffer<u32>, // only ever written by the compute pass
+    indirect_buffer: wgpu::Buffer, // draw_count draw args
+    indirect_reset_buffer: wgpu::Buffer, // the draw args with no instances, copied over every frame
+    cull_layout: wgpu::BindGroupLayout,
+    // rebuilt when the instance buffers grow or the pyramid is resized, keyed by buffer generations,
+    // and when set_meshes replaces the mesh buffers
+    cull_bind_group: Option<((u32, u32), wgpu::BindGroup)>,
+    cull_pipeline: wgpu::ComputePipeline,
+    // Hi-Z: the farthest depth of the last frame at half resolution and below, one level per mip
+    pyramid: wgpu::Texture,
+    pyramid_levels: Vec<wgpu::TextureView>,
+    reduce_layout: wgpu::BindGroupLayout,
+    reduce_pipeline: wgpu::ComputePipeline,
+    occlusion_culling: bool,
+    pyramid_valid: bool, // false until a frame was drawn at this size and depth mode with occlusion culling on
+}
+
+impl GpuCulling {
+    const CULL_WORKGROUP_SIZE: u32 = 64;
+    const REDUCE_WORKGROUP_SIZE: u32 = 8;
+    const PYRAMID_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+    const DRAW_ARGS_SIZE: wgpu::BufferAddress = std::mem::size_of::<wgpu::util::DrawIndexedIndirect>() as wgpu::BufferAddress;
+
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        meshes: &[GpuMesh],
+    ) -> Self {
+        let uniform = CullUniform {
+            view_proj: glam::Mat4::IDENTITY.to_cols_array_2d(),
+            planes: [[0.0; 4]; 6],
+            instance_count: 0,
+            hi_z: 0,
+            far_depth: 1.0,
+            pyramid_levels: 1, // set with the pyramid in update
+            mesh_count: 0, // set in set_meshes
+            projection_scale: 1.0,
+            _padding: [0; 2],
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Cull Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let visible_buffer = InstanceBuffer::new(
+            device,
+            "Cull Visible Instance Buffer",
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+            0,
+        );
+        let (mesh_buffer, indirect_buffer, indirect_reset_buffer) = Self::create_mesh_buffers(device, 0, 0); // grown in set_meshes
+
+        let compute_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty,
+            count: None,
+        };
+        let storage = |read_only| wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        };
+        // depth and pyramid levels are read with textureLoad, like the depth in ssao
+        let unfilterable = wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        };
+        let uniform_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[compute_entry(0, wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            })],
+            label: Some("cull_uniform_bind_group_layout"),
+        });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &uniform_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+            label: Some("cull_uniform_bind_group"),
+        });
+        let cull_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                compute_entry(0, storage(true)),
+                compute_entry(1, storage(false)),
+                compute_entry(2, storage(false)),
+                compute_entry(3, unfilterable),
+                compute_entry(4, storage(true)),
+            ],
+            label: Some("cull_bind_group_layout"),
+        });
+        let reduce_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                compute_entry(0, unfilterable),
+                compute_entry(1, wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    format: Self::PYRAMID_FORMAT,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                }),
+            ],
+            label: Some("hi_z_reduce_bind_group_layout"),
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Culling Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../../culling.wgsl").into()),
+        });
+        let compute_pipeline = |label, layouts: &[&wgpu::BindGroupLayout], entry_point| {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(label),
+                bind_group_layouts: layouts,
+                push_constant_ranges: &[],
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                module: &shader,
+                entry_point,
+            })
+        };
+        let cull_pipeline = compute_pipeline("Cull Pipeline", &[&uniform_layout, &cull_layout], "cs_cull");
+        let reduce_pipeline = compute_pipeline("Hi-Z Reduce Pipeline", &[&uniform_layout, &reduce_layout], "cs_reduce");
+
+        let (pyramid, pyramid_levels) = Self::create_pyramid(device, width, height);
+
+        let mut gpu_culling = Self {
+            uniform,
+            uniform_buffer,
+            uniform_bind_group,
+            meshes: Vec::new(),
+            mesh_buffer,
+            meshes_dirty: false,
+            draw_count: 0,
+            visible_buffer,
+            indirect_buffer,
+            indirect_reset_buffer,
+            cull_layout,
+            cull_bind_group: None,
+            cull_pipeline,
+            pyramid,
+            pyramid_levels,
+            reduce_layout,
+            reduce_pipeline,
+            occlusion_culling: false,
+            pyramid_valid: false,
+        };
+        gpu_culling.set_meshes(device, queue, meshes);
+        gpu_culling
+    }
+
+    // the mesh buffer and the draw args, with room for at least one of each
+    fn create_mesh_buffers(device: &wgpu::Device, mesh_count: usize, draw_count: usize) -> (wgpu::Buffer, wgpu::Buffer, wgpu::Buffer) {
+        let buffer = |label, size: usize, usage| device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: size.max(1) as wgpu::BufferAddress,
+            usage,
+            mapped_at_creation: false,
+        });
+        let draw_args_size = draw_count * std::mem::size_of::<wgpu::util::DrawIndexedIndirect>();
+        (
+            buffer("Cull Mesh Buffer", mesh_count * std::mem::size_of::<CullMesh>(), wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST),
+            buffer("Cull Indirect Buffer", draw_args_size, wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::COPY_DST),
+            buffer("Cull Indirect Reset Buffer", draw_args_size, wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST),
+        )
+    }
+
+    // half the depth resolution at level 0, down to 1x1
+    fn create_pyramid(device: &wgpu::Device, width: u32, height: u32) -> (wgpu::Texture, Vec<wgpu::TextureView>) {
+        let size = wgpu::Extent3d {
+            width: (width / 2).max(1),
+            height: (height / 2).max(1),
+            depth_or_array_layers: 1,
+        };
+        let mip_level_count = size.max_mips(wgpu::TextureDimension::D2);
+        let pyramid = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("hi_z_pyramid"),
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::PYRAMID_FORMAT,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let levels = (0..mip_level_count)
+            .map(|level| pyramid.create_view(&wgpu::TextureViewDescriptor {
+                base_mip_level: level,
+                mip_level_count: Some(1),
+                ..Default::default()
+            }))
+            .collect();
+        (pyramid, levels)
+    }
+
+    fn create_cull_bind_group(&self, device: &wgpu::Device, instances: &InstanceBuffer) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.cull_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: instances.buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.visible_buffer.buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.indirect_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&self.pyramid.create_view(&wgpu::TextureViewDescriptor::default())),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.mesh_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("cull_bind_group"),
+        })
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        (self.pyramid, self.pyramid_levels) = Self::create_pyramid(device, width, height);
+        self.cull_bind_group = None;
+        self.pyramid_valid = false;
+    }
+
+    // also skip instances hidden behind what was drawn last frame; they may pop in a frame late
+    pub fn set_occlusion_culling(&mut self, enabled: bool) {
+        // the pyramid isn't built while it's off, so it would hold an old camera's depth
+        if enabled != self.occlusion_culling {
+            self.pyramid_valid = false;
+        }
+        self.occlusion_culling = enabled;
+    }
+
+    // State's meshes, by the index instances refer to them with; the bounds reach the compute pass
+    // with the next update, and instances of meshes past the end are dropped
+    pub fn set_meshes(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, meshes: &[GpuMesh]) {
+        let mut first_draw = 0;
+        self.meshes = meshes.iter()
+            .map(|mesh| {
+                let mut screen_sizes = [0.0; GpuMesh::MAX_LODS];
+                for (screen_size, lod) in screen_sizes.iter_mut().zip(&mesh.lods) {
+                    *screen_size = lod.screen_size;
+                }
+                let cull_mesh = CullMesh {
+                    bounds_min: mesh.bounds.min.extend(1.0).to_array(),
+                    bounds_max: mesh.bounds.max.extend(1.0).to_array(),
+                    lod_screen_sizes: bytemuck::cast(screen_sizes),
+                    lod_count: mesh.lods.len() as u32,
+                    first_draw,
+                    _padding: [0; 2],
+                };
+                first_draw += cull_mesh.lod_count;
+                cull_mesh
+            })
+            .collect();
+        let draw_count = first_draw as usize;
+        if meshes.len() * std::mem::size_of::<CullMesh>() > self.mesh_buffer.size() as usize
+            || draw_count as wgpu::BufferAddress * Self::DRAW_ARGS_SIZE > self.indirect_buffer.size()
+        {
+            (self.mesh_buffer, self.indirect_buffer, self.indirect_reset_buffer) = Self::create_mesh_buffers(device, meshes.len(), draw_count);
+            self.cull_bind_group = None;
+        }
+        self.uniform.mesh_count = meshes.len() as u32;
+        self.draw_count = draw_count;
+        self.meshes_dirty = true;
+        let draw_args = meshes.iter()
+            .flat_map(|mesh| &mesh.lods)
+            .flat_map(|lod| wgpu::util::DrawIndexedIndirect {
+                vertex_count: lod.num_indices,
+                instance_count: 0,
+                base_index: lod.first_index,
+                vertex_offset: lod.base_vertex,
+                base_instance: 0,
+            }.as_bytes().to_vec())
+            .collect::<Vec<_>>();
+        queue.write_buffer(&self.indirect_reset_buffer, 0, &draw_args);
+        queue.write_buffer(&self.indirect_buffer, 0, &draw_args);
+    }
+
+    // model space bounds to test a mesh's instances by in place of the mesh's, e.g. around every pose
+    // of a skinned mesh; they reach the compute pass with the next update
+    pub fn set_bounds(&mut self, mesh: usize, bounds: &Aabb) {
+        self.meshes[mesh].bounds_min = bounds.min.extend(1.0).to_array();
+        self.meshes[mesh].bounds_max = bounds.max.extend(1.0).to_array();
+        self.meshes_dirty = true;
+    }
+
+    // with frustum_culling off every instance passes the frustum test
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &camera::Camera, frustum_culling: bool, instance_count: usize) {
+        self.uniform.instance_count = instance_count as u32;
+        self.uniform.projection_scale = camera.build_projection_matrix().y_axis.y;
+        let view_proj = camera.build_view_projection_matrix();
+        self.uniform.view_proj = view_proj.to_cols_array_2d();
+        self.uniform.planes = if frustum_culling {
+            Frustum::from_view_proj(&view_proj).planes.map(|plane| plane.to_array())
+        } else {
+            [[0.0, 0.0, 0.0, 1.0]; 6]
+        };
+        // a pyramid built with the other depth convention would cull the wrong way round
+        if camera.depth_mode.far_depth() != self.uniform.far_depth {
+            self.pyramid_valid = false;
+        }
+        self.uniform.hi_z = (self.occlusion_culling && self.pyramid_valid) as u32;
+        self.uniform.far_depth = camera.depth_mode.far_depth();
+        self.uniform.pyramid_levels = self.pyramid_levels.len() as u32;
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+        if self.meshes_dirty {
+            queue.write_buffer(&self.mesh_buffer, 0, bytemuck::cast_slice(&self.meshes));
+            self.meshes_dirty = false;
+        }
+    }
+
+    // fills instance_buffer and indirect_buffer for this frame from every instance in instances
+    pub fn cull(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, instances: &InstanceBuffer) {
+        // every level of every mesh may need room for every instance, at 4 bytes each
+        self.visible_buffer.reserve(device, instances.len() * self.draw_count);
+        let generations = (instances.generation(), self.visible_buffer.generation());
+        if !matches!(&self.cull_bind_group, Some((built_for, _)) if *built_for == generations) {
+            self.cull_bind_group = Some((generations, self.create_cull_bind_group(device, instances)));
+        }
+        let Some((_, cull_bind_group)) = &self.cull_bind_group else {
+            unreachable!()
+        };
+
+        encoder.copy_buffer_to_buffer(
+            &self.indirect_reset_buffer,
+            0,
+            &self.indirect_buffer,
+            0,
+            self.draw_count as wgpu::BufferAddress * Self::DRAW_ARGS_SIZE,
+        );
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Cull Pass"),
+        });
+        pass.set_pipeline(&self.cull_pipeline);
+        pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        pass.set_bind_group(1, cull_bind_group, &[]);
+        pass.dispatch_workgroups(self.uniform.instance_count.div_ceil(Self::CULL_WORKGROUP_SIZE), 1, 1);
+    }
+
+    // reduces the depth of the frame just drawn into the pyramid the next frame culls against
+    pub fn build_hi_z(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, depth: &texture::Texture) {
+        if !self.occlusion_culling {
+            return;
+        }
+        for (level, target) in self.pyramid_levels.iter().enumerate() {
+            let source = if level == 0 { &depth.view } else { &self.pyramid_levels[level - 1] };
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &self.reduce_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(source),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(target),
+                    },
+                ],
+                label: Some("hi_z_reduce_bind_group"),
+            });
+            let width = (self.pyramid.width() >> level).max(1);
+            let height = (self.pyramid.height() >> level).max(1);
+            // a pass per level so each one sees the writes of the level before
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Hi-Z Reduce Pass"),
+            });
+            pass.set_pipeline(&self.reduce_pipeline);
+            pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+            pass.set_bind_group(1, &bind_group, &[]);
+            pass.dispatch_workgroups(
+                width.div_ceil(Self::REDUCE_WORKGROUP_SIZE),
+                height.div_ceil(Self::REDUCE_WORKGROUP_SIZE),
+                1,
+            );
+        }
+        self.pyramid_valid = true;
+    }
+
+    // draws what the last cull kept of one mesh, a draw per level of detail; the mesh buffers and
+    // bind groups must already be set
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, mesh: usize) {
+        let region = self.uniform.instance_count as wgpu::BufferAddress * std::mem::size_of::<u32>() as wgpu::BufferAddress;
+        if region == 0 {
+            return;
+        }
+        let mesh = &self.meshes[mesh];
+        for draw in mesh.first_draw as wgpu::BufferAddress..(mesh.first_draw + mesh.lod_count) as wgpu::BufferAddress {
+            // the indirect args can't offset the first instance everywhere, so the buffer is offset instead
+            render_pass.set_vertex_buffer(1, self.visible_buffer.buffer().slice(draw * region..(draw + 1) * region));
+            render_pass.draw_indexed_indirect(&self.indirect_buffer, draw * Self::DRAW_ARGS_SIZE);
+        }
+    }
+}
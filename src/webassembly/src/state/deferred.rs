@@ -0,0 +1,381 @@
+use crate::state::{fullscreen, hdr, light, model, texture};
+use wgpu::util::DeviceExt;
+
+// targets written by fs_gbuffer in shader.wgsl; emissive goes straight into the HDR target
+pub struct GBuffer {
+    pub albedo: texture::Texture, // occlusion in a
+    pub normal: texture::Texture,
+    pub material: texture::Texture, // metallic, roughness
+}
+
+impl GBuffer {
+    pub const ALBEDO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+    pub const NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+    pub const MATERIAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+    fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        Self {
+            albedo: texture::Texture::create_render_target(device, width, height, Self::ALBEDO_FORMAT, "gbuffer_albedo"),
+            normal: texture::Texture::create_render_target(device, width, height, Self::NORMAL_FORMAT, "gbuffer_normal"),
+            material: texture::Texture::create_render_target(device, width, height, Self::MATERIAL_FORMAT, "gbuffer_material"),
+        }
+    }
+}
+
+// the scene's bind group layouts, in the groups shader.wgsl and deferred.wgsl use them; ssao is
+// Ssao::output_layout
+pub struct SceneLayouts<'a> {
+    pub material: &'a wgpu::BindGroupLayout,
+    pub camera: &'a wgpu::BindGroupLayout,
+    pub lighting: &'a wgpu::BindGroupLayout,
+    pub ssao: &'a wgpu::BindGroupLayout,
+}
+
+// what the lighting pass binds next to the G-buffer, made with the layouts in SceneLayouts
+pub struct LightingResources<'a> {
+    pub camera: &'a wgpu::BindGroup,
+    pub lighting: &'a wgpu::BindGroup,
+    pub ssao: &'a wgpu::BindGroup,
+}
+
+// deferred path: the scene is drawn once into a G-buffer, then ambient and directional light are
+// added in a fullscreen pass and every point light as a sphere covering its radius
+pub struct DeferredRenderer {
+    gbuffer: GBuffer,
+    gbuffer_layout: wgpu::BindGroupLayout,
+    geometry_pipeline: wgpu::RenderPipeline,
+    directional_pipeline: wgpu::RenderPipeline,
+    point_light_pipeline: wgpu::RenderPipeline,
+    volume_vertex_buffer: wgpu::Buffer,
+    volume_index_buffer: wgpu::Buffer,
+    volume_num_indices: u32,
+    light_buffer: wgpu::Buffer,
+    light_capacity: usize,
+    light_count: u32,
+    depth_mode: texture::DepthMode,
+}
+
+impl DeferredRenderer {
+    const INITIAL_LIGHT_CAPACITY: usize = 16;
+
+    // scene_shader is the module with the scene vertex stage and fs_gbuffer
+    pub fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        depth_mode: texture::DepthMode,
+        scene_shader: &wgpu::ShaderModule,
+        layouts: SceneLayouts,
+    ) -> Self {
+        let gbuffer = GBuffer::new(device, width, height);
+
+        // G-buffer and depth are read with textureLoad, one texel per pixel
+        let unfilterable_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            },
+            count: None,
+        };
+        let gbuffer_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                unfilterable_entry(0), // albedo
+                unfilterable_entry(1), // normal
+                unfilterable_entry(2), // material
+                unfilterable_entry(3), // depth
+            ],
+            label: Some("gbuffer_bind_group_layout"),
+        });
+
+        let geometry_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("G-Buffer Pipeline Layout"),
+            bind_group_layouts: &[layouts.material, layouts.camera],
+            push_constant_ranges: &[],
+        });
+        let gbuffer_target = |format| Some(wgpu::ColorTargetState {
+            format,
+            blend: Some(wgpu::BlendState::REPLACE),
+            write_mask: wgpu::ColorWrites::ALL,
+        });
+        let geometry_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("G-Buffer Pipeline"),
+            layout: Some(&geometry_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: scene_shader,
+                entry_point: "vertex",
+                buffers: &[
+                    model::Vertex::desc(),
+                    model::InstanceRaw::index_desc(),
+                ],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: scene_shader,
+                entry_point: "fs_gbuffer",
+                targets: &[
+                    gbuffer_target(GBuffer::ALBEDO_FORMAT),
+                    gbuffer_target(GBuffer::NORMAL_FORMAT),
+                    gbuffer_target(GBuffer::MATERIAL_FORMAT),
+                    gbuffer_target(hdr::HdrPipeline::FORMAT), // emissive
+                ],
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: depth_mode.compare(),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        // light is added on top of the emissive color already in the HDR target
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let additive = wgpu::BlendState { color: additive, alpha: additive };
+
+        let shader = fullscreen::create_shader(
+            device,
+            "deferred.wgsl",
+            &format!("{}\n{}", include_str!("../pbr.wgsl"), include_str!("../deferred.wgsl")),
+        );
+        let directional_pipeline = fullscreen::create_pipeline(
+            device,
+            "Deferred Directional Pipeline",
+            &[&gbuffer_layout, layouts.camera, layouts.lighting, layouts.ssao],
+            &shader,
+            "fs_directional",
+            hdr::HdrPipeline::FORMAT,
+            additive,
+        );
+
+        let point_light_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Deferred Point Light Pipeline Layout"),
+            bind_group_layouts: &[&gbuffer_layout, layouts.camera],
+            push_constant_ranges: &[],
+        });
+        let point_light_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Deferred Point Light Pipeline"),
+            layout: Some(&point_light_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_point_light",
+                buffers: &[
+                    wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &wgpu::vertex_attr_array![0 => Float32x3],
+                    },
+                    light::PointLightRaw::desc(),
+                ],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_point_light",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: hdr::HdrPipeline::FORMAT,
+                    blend: Some(additive),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                // back faces still cover the screen when the camera is inside the volume
+                cull_mode: Some(wgpu::Face::Front),
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let (positions, indices) = Self::light_volume();
+        let volume_vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Light Volume Vertex Buffer"),
+                contents: bytemuck::cast_slice(&positions),
+                usage: wgpu::BufferUsages::VERTEX,
+            }
+        );
+        let volume_index_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Light Volume Index Buffer"),
+                contents: bytemuck::cast_slice(&indices),
+                usage: wgpu::BufferUsages::INDEX,
+            }
+        );
+        let light_buffer = Self::create_light_buffer(device, Self::INITIAL_LIGHT_CAPACITY);
+
+        Self {
+            gbuffer,
+            gbuffer_layout,
+            geometry_pipeline,
+            directional_pipeline,
+            point_light_pipeline,
+            volume_vertex_buffer,
+            volume_index_buffer,
+            volume_num_indices: indices.len() as u32,
+            light_buffer,
+            light_capacity: Self::INITIAL_LIGHT_CAPACITY,
+            light_count: 0,
+            depth_mode,
+        }
+    }
+
+    // unit uv sphere, counter-clockwise seen from outside
+    fn light_volume() -> (Vec<[f32; 3]>, Vec<u16>) {
+        use std::f32::consts::PI;
+        const STACKS: u16 = 8;
+        const SEGMENTS: u16 = 16;
+        // push the vertices out so the flat faces enclose the real sphere
+        let scale = 1.0 / (PI / SEGMENTS as f32).cos().powi(2);
+
+        let mut positions = Vec::new();
+        for stack in 0..=STACKS {
+            let phi = PI * stack as f32 / STACKS as f32;
+            for segment in 0..=SEGMENTS {
+                let theta = 2.0 * PI * segment as f32 / SEGMENTS as f32;
+                positions.push([phi.sin() * theta.cos() * scale, phi.cos() * scale, phi.sin() * theta.sin() * scale]);
+            }
+        }
+        let mut indices = Vec::new();
+        for stack in 0..STACKS {
+            for segment in 0..SEGMENTS {
+                let a = stack * (SEGMENTS + 1) + segment;
+                let b = a + SEGMENTS + 1;
+                indices.extend_from_slice(&[a, a + 1, b, a + 1, b + 1, b]);
+            }
+        }
+        (positions, indices)
+    }
+
+    fn create_light_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Point Light Buffer"),
+            size: (capacity * std::mem::size_of::<light::PointLightRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.gbuffer = GBuffer::new(device, width, height);
+    }
+
+    pub fn set_point_lights(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, lights: &[light::PointLight]) {
+        if lights.len() > self.light_capacity {
+            self.light_capacity = lights.len().next_power_of_two();
+            self.light_buffer = Self::create_light_buffer(device, self.light_capacity);
+        }
+        let raw = lights.iter().map(light::PointLight::to_raw).collect::<Vec<_>>();
+        queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&raw));
+        self.light_count = lights.len() as u32;
+    }
+
+    // clears the G-buffer, depth and the HDR target; the caller sets the material and camera
+    // bind groups and draws the scene
+    pub fn begin_geometry_pass<'a>(
+        &'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        hdr: &'a wgpu::TextureView,
+        depth: &'a wgpu::TextureView,
+        background: wgpu::Color,
+    ) -> wgpu::RenderPass<'a> {
+        let target = |view, clear| Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(clear),
+                store: true,
+            },
+        });
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("G-Buffer Pass"),
+            color_attachments: &[
+                target(&self.gbuffer.albedo.view, wgpu::Color::TRANSPARENT),
+                target(&self.gbuffer.normal.view, wgpu::Color::TRANSPARENT),
+                target(&self.gbuffer.material.view, wgpu::Color::TRANSPARENT),
+                target(hdr, background),
+            ],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(self.depth_mode.far_depth()),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        pass.set_pipeline(&self.geometry_pipeline);
+        pass
+    }
+
+    // adds ambient, directional and point light into the HDR target
+    pub fn lighting_pass(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        hdr: &wgpu::TextureView,
+        depth: &texture::Texture,
+        resources: LightingResources,
+    ) {
+        let gbuffer_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.gbuffer_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&self.gbuffer.albedo.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&self.gbuffer.normal.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&self.gbuffer.material.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&depth.view),
+                },
+            ],
+            label: Some("gbuffer_bind_group"),
+        });
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Deferred Lighting Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: hdr,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        pass.set_pipeline(&self.directional_pipeline);
+        pass.set_bind_group(0, &gbuffer_bind_group, &[]);
+        pass.set_bind_group(1, resources.camera, &[]);
+        pass.set_bind_group(2, resources.lighting, &[]);
+        pass.set_bind_group(3, resources.ssao, &[]);
+        pass.draw(0..3, 0..1);
+
+        if self.light_count > 0 {
+            pass.set_pipeline(&self.point_light_pipeline);
+            pass.set_vertex_buffer(0, self.volume_vertex_buffer.slice(..));
+            pass.set_vertex_buffer(1, self.light_buffer.slice(..));
+            pass.set_index_buffer(self.volume_index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            pass.draw_indexed(0..self.volume_num_indices, 0, 0..self.light_count);
+        }
+    }
+}
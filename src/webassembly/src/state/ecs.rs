@@ -0,0 +1,186 @@
+use crate::state::camera::Projection;
+use crate::state::scene::Transform;
+
+// handle to an entity; stays invalid once the entity is despawned, even if its slot is reused
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+// draws one of State's meshes, by index, at the entity's Transform, tinted like model::Instance
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MeshRenderer {
+    pub mesh: usize,
+    pub color: glam::Vec4,
+    pub custom: glam::Vec4,
+    pub pose: u32,
+}
+
+impl MeshRenderer {
+    pub fn new(mesh: usize) -> Self {
+        Self {
+            mesh,
+            color: glam::Vec4::ONE,
+            custom: glam::Vec4::ZERO,
+            pose: 0,
+        }
+    }
+}
+
+// a point light at the entity's translation
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Light {
+    pub color: glam::Vec3, // linear rgb
+    pub intensity: f32,
+    pub radius: f32, // no light reaches beyond this distance
+}
+
+// looks along -z of the entity's rotation; named so it doesn't clash with the camera module
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CameraComponent {
+    pub projection: Projection,
+}
+
+// one kind of component, indexed by entity; changes through insert, remove and the _mut accessors
+// are flagged until State::update has extracted them
+pub struct Components<T> {
+    slots: Vec<Option<(u32, T)>>, // generation of the entity owning the component
+    changed: bool,
+}
+
+impl<T> Default for Components<T> {
+    fn default() -> Self {
+        Self {
+            slots: Vec::new(),
+            changed: false,
+        }
+    }
+}
+
+impl<T> Components<T> {
+    // replaces and returns any component the entity had; only meant for live entities
+    pub fn insert(&mut self, entity: Entity, component: T) -> Option<T> {
+        let index = entity.index as usize;
+        if index >= self.slots.len() {
+            self.slots.resize_with(index + 1, || None);
+        }
+        self.changed = true;
+        self.slots[index].replace((entity.generation, component))
+            .and_then(|(generation, old)| (generation == entity.generation).then_some(old))
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> Option<T> {
+        let slot = self.slots.get_mut(entity.index as usize)?;
+        if !matches!(slot, Some((generation, _)) if *generation == entity.generation) {
+            return None;
+        }
+        self.changed = true;
+        slot.take().map(|(_, component)| component)
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        match self.slots.get(entity.index as usize)? {
+            Some((generation, component)) if *generation == entity.generation => Some(component),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        match self.slots.get_mut(entity.index as usize)? {
+            Some((generation, component)) if *generation == entity.generation => {
+                self.changed = true;
+                Some(component)
+            }
+            _ => None,
+        }
+    }
+
+    // in entity slot order
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            let (generation, component) = slot.as_ref()?;
+            Some((Entity { index: index as u32, generation: *generation }, component))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        self.changed = true;
+        self.slots.iter_mut().enumerate().filter_map(|(index, slot)| {
+            let (generation, component) = slot.as_mut()?;
+            Some((Entity { index: index as u32, generation: *generation }, component))
+        })
+    }
+
+    pub fn is_changed(&self) -> bool {
+        self.changed
+    }
+
+    fn clear_changed(&mut self) {
+        self.changed = false;
+    }
+}
+
+// entities and their components. Rendering reads the ones with a Transform: MeshRenderers are drawn
+// after the instances and scene graph, grouped by mesh, Lights are added to the point lights, and
+// the first CameraComponent moves the view whenever cameras or transforms change, unless a camera
+// path is playing
+#[derive(Default)]
+pub struct World {
+    generations: Vec<(u32, bool)>, // per slot, and whether an entity lives there
+    free: Vec<u32>,
+    pub transforms: Components<Transform>,
+    pub mesh_renderers: Components<MeshRenderer>,
+    pub lights: Components<Light>,
+    pub cameras: Components<CameraComponent>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawn(&mut self) -> Entity {
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.generations[index as usize];
+                slot.1 = true;
+                Entity { index, generation: slot.0 }
+            }
+            None => {
+                self.generations.push((0, true));
+                Entity { index: self.generations.len() as u32 - 1, generation: 0 }
+            }
+        }
+    }
+
+    // removes the entity with all its components; false if it was already gone
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+        self.transforms.remove(entity);
+        self.mesh_renderers.remove(entity);
+        self.lights.remove(entity);
+        self.cameras.remove(entity);
+        let slot = &mut self.generations[entity.index as usize];
+        *slot = (slot.0.wrapping_add(1), false);
+        self.free.push(entity.index);
+        true
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.generations.get(entity.index as usize) == Some(&(entity.generation, true))
+    }
+
+    pub fn clear_changed(&mut self) {
+        self.transforms.clear_changed();
+        self.mesh_renderers.clear_changed();
+        self.lights.clear_changed();
+        self.cameras.clear_changed();
+    }
+}
+
+// run every State::update, in the order they were added, after the camera controller and before
+// the components are extracted for rendering
+pub type System = Box<dyn FnMut(&mut World, std::time::Duration)>;
@@ -0,0 +1,159 @@
+use crate::state::{fullscreen, texture};
+use wgpu::util::DeviceExt;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Tonemapper {
+    Aces,
+    Reinhard,
+    AgX,
+}
+
+impl Tonemapper {
+    // next tonemapper, for cycling through them with a key
+    pub fn next(self) -> Self {
+        match self {
+            Tonemapper::Aces => Tonemapper::Reinhard,
+            Tonemapper::Reinhard => Tonemapper::AgX,
+            Tonemapper::AgX => Tonemapper::Aces,
+        }
+    }
+}
+
+// This is so we can store this in a buffer; layout matches TonemapUniform in hdr.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct TonemapUniform {
+    exposure: f32,
+    tonemapper: u32,
+    _padding: [u32; 2],
+}
+
+// the scene is rendered into an HDR texture, which is tonemapped into the surface
+pub struct HdrPipeline {
+    texture: texture::Texture,
+    layout: wgpu::BindGroupLayout,
+    uniform_buffer: wgpu::Buffer,
+    pipeline: wgpu::RenderPipeline,
+    exposure: f32,
+    tonemapper: Tonemapper,
+}
+
+impl HdrPipeline {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let texture = texture::Texture::create_render_target(device, config.width, config.height, Self::FORMAT, "hdr_texture");
+        let exposure = 0.0;
+        let tonemapper = Tonemapper::Aces;
+        let uniform_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Tonemap Buffer"),
+                contents: bytemuck::cast_slice(&[Self::uniform(exposure, tonemapper)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                fullscreen::texture_entry(0),
+                fullscreen::sampler_entry(1),
+                fullscreen::uniform_entry(2),
+            ],
+            label: Some("hdr_bind_group_layout"),
+        });
+
+        let shader = fullscreen::create_shader(device, "hdr.wgsl", include_str!("../hdr.wgsl"));
+        let pipeline = fullscreen::create_pipeline(
+            device,
+            "Tonemap Pipeline",
+            &[&layout],
+            &shader,
+            "fs_tonemap",
+            config.format,
+            wgpu::BlendState::REPLACE,
+        );
+
+        Self {
+            texture,
+            layout,
+            uniform_buffer,
+            pipeline,
+            exposure,
+            tonemapper,
+        }
+    }
+
+    fn uniform(exposure: f32, tonemapper: Tonemapper) -> TonemapUniform {
+        TonemapUniform {
+            exposure,
+            tonemapper: tonemapper as u32,
+            _padding: [0; 2],
+        }
+    }
+
+    // the HDR texture has to match the surface size, so recreate it with the surface
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.texture = texture::Texture::create_render_target(device, width, height, Self::FORMAT, "hdr_texture");
+    }
+
+    // render target for the scene
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.texture.view
+    }
+
+    pub fn texture(&self) -> &texture::Texture {
+        &self.texture
+    }
+
+    pub fn exposure(&self) -> f32 {
+        self.exposure
+    }
+
+    pub fn tonemapper(&self) -> Tonemapper {
+        self.tonemapper
+    }
+
+    pub fn set_exposure(&mut self, queue: &wgpu::Queue, exposure: f32) {
+        self.exposure = exposure;
+        self.write_uniform(queue);
+    }
+
+    pub fn set_tonemapper(&mut self, queue: &wgpu::Queue, tonemapper: Tonemapper) {
+        self.tonemapper = tonemapper;
+        self.write_uniform(queue);
+    }
+
+    fn write_uniform(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[Self::uniform(self.exposure, self.tonemapper)]));
+    }
+
+    // tonemap an HDR texture (the scene, or the result of HDR post effects) into the output
+    pub fn process(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &texture::Texture,
+        output: &wgpu::TextureView,
+    ) {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&input.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&input.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("hdr_bind_group"),
+        });
+        // every pixel is overwritten
+        fullscreen::draw(encoder, "Tonemap Pass", &self.pipeline, &bind_group, output, wgpu::LoadOp::Load);
+    }
+}
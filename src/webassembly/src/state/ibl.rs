@@ -0,0 +1,327 @@
+use crate::state::{fullscreen, texture};
+
+// This is so we can store this in a buffer; layout matches FaceUniform in ibl.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct FaceUniform {
+    face: u32,
+    roughness: f32,
+    sample_count: u32,
+    source_lod: f32,
+}
+
+// radiance to light the scene with in place of the procedural sky, e.g. from Texture::from_hdr
+#[derive(Copy, Clone)]
+pub enum EnvironmentSource<'a> {
+    Cube(&'a texture::Texture),
+    Equirectangular(&'a texture::Texture), // see EnvironmentImage::Equirectangular
+}
+
+// linear HDR pixels to light the scene with, see State::set_environment; each image is row by row
+// from the top
+#[derive(Debug, Copy, Clone)]
+pub enum EnvironmentImage<'a> {
+    // longitude along x, which is -X at 0, -Z at a quarter, +X halfway and +Z at three quarters;
+    // +Y along the top row
+    Equirectangular { width: u32, height: u32, pixels: &'a [[f32; 4]] },
+    // square faces in the order +X, -X, +Y, -Y, +Z, -Z
+    Cube { size: u32, faces: [&'a [[f32; 4]]; 6] },
+}
+
+impl EnvironmentImage<'_> {
+    // uploads the image; fails if the pixels don't fill the size
+    pub fn to_texture(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<texture::Texture, texture::TextureError> {
+        let (layers, width, height) = match self {
+            EnvironmentImage::Equirectangular { width, height, pixels } => (vec![*pixels], *width, *height),
+            EnvironmentImage::Cube { size, faces } => (faces.to_vec(), *size, *size),
+        };
+        let expected = width as usize * height as usize;
+        if expected == 0 || layers.iter().any(|layer| layer.len() != expected) {
+            return Err(texture::TextureError::InvalidDimensions(width, height));
+        }
+        Ok(texture::Texture::from_hdr(device, queue, &layers, width, height, "environment_source"))
+    }
+
+    pub fn source<'a>(&self, texture: &'a texture::Texture) -> EnvironmentSource<'a> {
+        match self {
+            EnvironmentImage::Equirectangular { .. } => EnvironmentSource::Equirectangular(texture),
+            EnvironmentImage::Cube { .. } => EnvironmentSource::Cube(texture),
+        }
+    }
+}
+
+// image-based lighting inputs for the PBR shader, all generated once at startup from a radiance
+// cube with a full mip chain
+pub struct Environment {
+    pub irradiance_map: texture::Texture, // diffuse convolution
+    pub prefiltered_map: texture::Texture, // specular convolution, one roughness per mip
+    pub brdf_lut: texture::Texture, // split sum scale and bias for F0
+}
+
+// one fullscreen draw into a single face + mip level
+struct Draw<'a> {
+    pipeline: &'a wgpu::RenderPipeline,
+    bind_group: &'a wgpu::BindGroup,
+    target: wgpu::TextureView,
+    params: FaceUniform,
+}
+
+impl Environment {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+    pub const ENVIRONMENT_SIZE: u32 = 256;
+    pub const IRRADIANCE_SIZE: u32 = 32;
+    pub const PREFILTERED_SIZE: u32 = 128;
+    pub const PREFILTERED_MIP_LEVELS: u32 = 5; // 128 down to 8, roughness 0 to 1
+    pub const BRDF_LUT_SIZE: u32 = 256;
+    const SAMPLE_COUNT: u32 = 512;
+
+    // lit by the procedural sky in ibl.wgsl
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        Self::build(device, queue, None)
+    }
+
+    // lit by a cube or equirectangular texture, resampled to ENVIRONMENT_SIZE; sources with a mip
+    // chain are sampled at the level closest to each environment mip, so they don't alias
+    pub fn from_source(device: &wgpu::Device, queue: &wgpu::Queue, source: EnvironmentSource) -> Self {
+        Self::build(device, queue, Some(source))
+    }
+
+    fn build(device: &wgpu::Device, queue: &wgpu::Queue, source: Option<EnvironmentSource>) -> Self {
+        let environment_mip_levels = Self::ENVIRONMENT_SIZE.ilog2() + 1;
+        let environment_map = texture::Texture::create_cube(device, Self::ENVIRONMENT_SIZE, environment_mip_levels, Self::FORMAT, "environment_map");
+        let irradiance_map = texture::Texture::create_cube(device, Self::IRRADIANCE_SIZE, 1, Self::FORMAT, "irradiance_map");
+        let prefiltered_map = texture::Texture::create_cube(device, Self::PREFILTERED_SIZE, Self::PREFILTERED_MIP_LEVELS, Self::FORMAT, "prefiltered_map");
+        let brdf_lut = texture::Texture::create_render_target(device, Self::BRDF_LUT_SIZE, Self::BRDF_LUT_SIZE, wgpu::TextureFormat::Rg16Float, "brdf_lut");
+
+        // region: --- PIPELINES
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<FaceUniform>() as u64),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("ibl_bind_group_layout"),
+        });
+        // fs_equirectangular reads a 2D texture where the other passes read a cube
+        let equirectangular_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<FaceUniform>() as u64),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("ibl_equirectangular_bind_group_layout"),
+        });
+        let shader = fullscreen::create_shader(device, "ibl.wgsl", include_str!("../ibl.wgsl"));
+        let create_pipeline = |entry_point: &str, format: wgpu::TextureFormat| {
+            fullscreen::create_pipeline(device, entry_point, &[&bind_group_layout], &shader, entry_point, format, wgpu::BlendState::REPLACE)
+        };
+        // whatever fills the environment map
+        let radiance_pipeline = match source {
+            None => create_pipeline("fs_sky", Self::FORMAT),
+            Some(EnvironmentSource::Cube(_)) => create_pipeline("fs_cube", Self::FORMAT),
+            Some(EnvironmentSource::Equirectangular(_)) => fullscreen::create_pipeline(
+                device,
+                "fs_equirectangular",
+                &[&equirectangular_layout],
+                &shader,
+                "fs_equirectangular",
+                Self::FORMAT,
+                wgpu::BlendState::REPLACE,
+            ),
+        };
+        let irradiance_pipeline = create_pipeline("fs_irradiance", Self::FORMAT);
+        let prefilter_pipeline = create_pipeline("fs_prefilter", Self::FORMAT);
+        let brdf_lut_pipeline = create_pipeline("fs_brdf_lut", wgpu::TextureFormat::Rg16Float);
+        // endregion: --- PIPELINES
+
+        // region: --- BIND GROUPS
+        let params_stride = (std::mem::size_of::<FaceUniform>() as u32)
+            .max(device.limits().min_uniform_buffer_offset_alignment);
+        let draw_count = 6 * environment_mip_levels + 6 + 6 * Self::PREFILTERED_MIP_LEVELS + 1;
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("IBL Params Buffer"),
+            size: (params_stride * draw_count) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let params_binding = wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer: &params_buffer,
+            offset: 0,
+            size: wgpu::BufferSize::new(std::mem::size_of::<FaceUniform>() as u64),
+        });
+        let create_bind_group = |source: &texture::Texture| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: params_binding.clone(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&source.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Sampler(&source.sampler),
+                    },
+                ],
+                label: Some("ibl_bind_group"),
+            })
+        };
+        // a texture can't be sampled and rendered to in the same pass, so the sky and BRDF
+        // passes (which don't sample) bind a cube texture they don't write to
+        let environment_bind_group = create_bind_group(&environment_map);
+        let radiance_bind_group = match source {
+            None => create_bind_group(&irradiance_map),
+            Some(EnvironmentSource::Cube(source)) => create_bind_group(source),
+            Some(EnvironmentSource::Equirectangular(source)) => device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &equirectangular_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: params_binding.clone(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Sampler(&source.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::TextureView(&source.view),
+                    },
+                ],
+                label: Some("ibl_equirectangular_bind_group"),
+            }),
+        };
+        // endregion: --- BIND GROUPS
+
+        // region: --- DRAWS
+        // the source texels under one environment texel; an equirectangular image is four faces wide
+        let source_size = match source {
+            None => Self::ENVIRONMENT_SIZE as f32,
+            Some(EnvironmentSource::Cube(source)) => source.texture.width() as f32,
+            Some(EnvironmentSource::Equirectangular(source)) => source.texture.width() as f32 / 4.0,
+        };
+        let mut draws = Vec::new();
+        for mip_level in 0..environment_mip_levels {
+            let source_lod = (source_size / (Self::ENVIRONMENT_SIZE >> mip_level) as f32).log2().max(0.0);
+            for face in 0..6 {
+                draws.push(Draw {
+                    pipeline: &radiance_pipeline,
+                    bind_group: &radiance_bind_group,
+                    target: environment_map.face_view(face, mip_level),
+                    params: FaceUniform { face, roughness: 0.0, sample_count: 0, source_lod },
+                });
+            }
+        }
+        for face in 0..6 {
+            draws.push(Draw {
+                pipeline: &irradiance_pipeline,
+                bind_group: &environment_bind_group,
+                target: irradiance_map.face_view(face, 0),
+                params: FaceUniform { face, roughness: 0.0, sample_count: 0, source_lod: 0.0 },
+            });
+        }
+        for mip_level in 0..Self::PREFILTERED_MIP_LEVELS {
+            let roughness = mip_level as f32 / (Self::PREFILTERED_MIP_LEVELS - 1) as f32;
+            for face in 0..6 {
+                draws.push(Draw {
+                    pipeline: &prefilter_pipeline,
+                    bind_group: &environment_bind_group,
+                    target: prefiltered_map.face_view(face, mip_level),
+                    params: FaceUniform { face, roughness, sample_count: Self::SAMPLE_COUNT, source_lod: 0.0 },
+                });
+            }
+        }
+        draws.push(Draw {
+            pipeline: &brdf_lut_pipeline,
+            bind_group: &environment_bind_group,
+            target: brdf_lut.texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            params: FaceUniform { face: 0, roughness: 0.0, sample_count: Self::SAMPLE_COUNT, source_lod: 0.0 },
+        });
+
+        let mut params_data = vec![0u8; params_buffer.size() as usize];
+        for (i, draw) in draws.iter().enumerate() {
+            let offset = i * params_stride as usize;
+            params_data[offset..offset + std::mem::size_of::<FaceUniform>()]
+                .copy_from_slice(bytemuck::bytes_of(&draw.params));
+        }
+        queue.write_buffer(&params_buffer, 0, &params_data);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("IBL Encoder"),
+        });
+        for (i, draw) in draws.iter().enumerate() {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("IBL Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &draw.target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(draw.pipeline);
+            render_pass.set_bind_group(0, draw.bind_group, &[i as u32 * params_stride]);
+            render_pass.draw(0..3, 0..1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+        // endregion: --- DRAWS
+
+        Self {
+            irradiance_map,
+            prefiltered_map,
+            brdf_lut,
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use crate::state::model;
+use std::marker::PhantomData;
+use std::ops::Range;
+
+// a buffer of InstanceRaw, or of indices into one, that grows when it runs out of room; between
+// uploads it remembers which instances changed so only those are written again
+pub struct InstanceBuffer<T = model::InstanceRaw> {
+    label: &'static str,
+    usage: wgpu::BufferUsages,
+    buffer: wgpu::Buffer,
+    capacity: usize, // in instances
+    len: usize,
+    dirty: Option<Range<usize>>,
+    generation: u32, // changes whenever the buffer is reallocated, so bind groups know to follow
+    element: PhantomData<T>,
+}
+
+impl<T: bytemuck::Pod> InstanceBuffer<T> {
+    const MIN_CAPACITY: usize = 16;
+
+    // COPY_DST is added to usage
+    pub fn new(device: &wgpu::Device, label: &'static str, usage: wgpu::BufferUsages, capacity: usize) -> Self {
+        let usage = usage | wgpu::BufferUsages::COPY_DST;
+        let capacity = capacity.max(Self::MIN_CAPACITY);
+        Self {
+            label,
+            usage,
+            buffer: Self::create_buffer(device, label, usage, capacity),
+            capacity,
+            len: 0,
+            dirty: None,
+            generation: 0,
+            element: PhantomData,
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, label: &str, usage: wgpu::BufferUsages, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: (capacity * std::mem::size_of::<T>()) as wgpu::BufferAddress,
+            usage,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    // these instances need uploading again
+    pub fn mark_dirty(&mut self, range: Range<usize>) {
+        self.dirty = Some(match self.dirty.take() {
+            Some(dirty) => dirty.start.min(range.start)..dirty.end.max(range.end),
+            None => range,
+        });
+    }
+
+    // room for at least len instances, doubling so growing one at a time stays cheap;
+    // a new buffer starts empty, so everything is dirty afterwards
+    pub fn reserve(&mut self, device: &wgpu::Device, len: usize) {
+        if len <= self.capacity {
+            return;
+        }
+        self.capacity = len.max(self.capacity * 2);
+        self.buffer = Self::create_buffer(device, self.label, self.usage, self.capacity);
+        self.generation = self.generation.wrapping_add(1);
+        self.dirty = Some(0..len);
+    }
+
+    // brings the buffer in line with instances, which is the whole list the dirty ranges refer to
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, instances: &[T]) {
+        self.reserve(device, instances.len());
+        self.len = instances.len();
+        if let Some(dirty) = self.dirty.take() {
+            let dirty = dirty.start.min(self.len)..dirty.end.min(self.len);
+            if !dirty.is_empty() {
+                let offset = (dirty.start * std::mem::size_of::<T>()) as wgpu::BufferAddress;
+                queue.write_buffer(&self.buffer, offset, bytemuck::cast_slice(&instances[dirty]));
+            }
+        }
+    }
+
+    // replaces everything, e.g. with the instances that passed culling this frame
+    pub fn write(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, instances: &[T]) {
+        self.mark_dirty(0..instances.len());
+        self.upload(device, queue, instances);
+    }
+}
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+
+// This is so we can store this in a buffer; layout matches LightUniform in pbr.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightUniform {
+    pub direction: [f32; 4], // direction the light travels in; w is unused padding
+    pub color: [f32; 4], // linear rgb, intensity in w
+}
+
+impl LightUniform {
+    pub fn new(direction: glam::Vec3, color: glam::Vec3, intensity: f32) -> Self {
+        Self {
+            direction: direction.normalize().extend(0.0).to_array(),
+            color: color.extend(intensity).to_array(),
+        }
+    }
+}
+
+// matches the array size of PointLightsUniform in pbr.wgsl; the deferred path has no limit
+pub const MAX_FORWARD_POINT_LIGHTS: usize = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PointLight {
+    pub position: glam::Vec3,
+    pub color: glam::Vec3, // linear rgb
+    pub intensity: f32,
+    pub radius: f32, // no light reaches beyond this distance
+}
+
+impl PointLight {
+    pub fn to_raw(&self) -> PointLightRaw {
+        PointLightRaw {
+            position: self.position.extend(self.radius).to_array(),
+            color: self.color.extend(self.intensity).to_array(),
+        }
+    }
+}
+
+// layout matches PointLight in pbr.wgsl; also the per-instance data of deferred light volumes
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PointLightRaw {
+    pub position: [f32; 4], // radius in w
+    pub color: [f32; 4], // intensity in w
+}
+
+impl PointLightRaw {
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<PointLightRaw>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 5,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 6,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+// point lights for the forward shader; layout matches PointLightsUniform in pbr.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PointLightsUniform {
+    lights: [PointLightRaw; MAX_FORWARD_POINT_LIGHTS],
+    count: u32,
+    _padding: [u32; 3],
+}
+
+impl PointLightsUniform {
+    // lights past MAX_FORWARD_POINT_LIGHTS are left out
+    pub fn new(lights: &[PointLight]) -> Self {
+        let mut uniform = Self {
+            lights: [PointLightRaw { position: [0.0; 4], color: [0.0; 4] }; MAX_FORWARD_POINT_LIGHTS],
+            count: lights.len().min(MAX_FORWARD_POINT_LIGHTS) as u32,
+            _padding: [0; 3],
+        };
+        for (raw, light) in uniform.lights.iter_mut().zip(lights) {
+            *raw = light.to_raw();
+        }
+        uniform
+    }
+}
@@ -0,0 +1,190 @@
+use crate::state::texture;
+use serde::{Deserialize, Serialize};
+use wgpu::util::DeviceExt;
+
+// This is so we can store this in a buffer; layout matches MaterialUniform in shader.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+    base_color: [f32; 4],
+    emissive: [f32; 4], // w is unused padding
+    metallic: f32,
+    roughness: f32,
+    occlusion_strength: f32,
+    _padding: f32,
+}
+
+// metallic-roughness factors; each is multiplied with the matching texture in the shader
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MaterialFactors {
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub occlusion_strength: f32, // 0 ignores the occlusion texture, 1 applies it fully
+    pub emissive: [f32; 3],
+}
+
+impl Default for MaterialFactors {
+    // same defaults as glTF so assets from the content pipeline look the same
+    fn default() -> Self {
+        Self {
+            base_color: [1.0; 4],
+            metallic: 1.0,
+            roughness: 1.0,
+            occlusion_strength: 1.0,
+            emissive: [0.0; 3],
+        }
+    }
+}
+
+impl MaterialFactors {
+    fn to_uniform(self) -> MaterialUniform {
+        MaterialUniform {
+            base_color: self.base_color,
+            emissive: [self.emissive[0], self.emissive[1], self.emissive[2], 0.0],
+            metallic: self.metallic,
+            roughness: self.roughness,
+            occlusion_strength: self.occlusion_strength,
+            _padding: 0.0,
+        }
+    }
+}
+
+// textures are optional; missing ones are replaced by a 1x1 white texture so only the factor applies
+#[derive(Default)]
+pub struct MaterialTextures {
+    pub base_color: Option<texture::Texture>, // sRGB
+    pub metallic_roughness: Option<texture::Texture>, // linear; roughness in G, metallic in B (glTF layout)
+    pub occlusion: Option<texture::Texture>, // linear; occlusion in R
+    pub emissive: Option<texture::Texture>, // sRGB
+}
+
+pub struct Material {
+    pub name: String,
+    pub factors: MaterialFactors,
+    buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup, // keeps the textures and the sampler alive
+}
+
+impl Material {
+    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                texture_entry(2), // base color
+                texture_entry(3), // metallic roughness
+                texture_entry(4), // occlusion
+                texture_entry(5), // emissive
+            ],
+            label: Some("material_bind_group_layout"),
+        })
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        name: &str,
+        factors: MaterialFactors,
+        textures: MaterialTextures,
+    ) -> Self {
+        let white = |format, label| texture::Texture::from_color(device, queue, [255; 4], format, label);
+        let base_color_texture = textures.base_color
+            .unwrap_or_else(|| white(wgpu::TextureFormat::Rgba8UnormSrgb, "default base color"));
+        let metallic_roughness_texture = textures.metallic_roughness
+            .unwrap_or_else(|| white(wgpu::TextureFormat::Rgba8Unorm, "default metallic roughness"));
+        let occlusion_texture = textures.occlusion
+            .unwrap_or_else(|| white(wgpu::TextureFormat::Rgba8Unorm, "default occlusion"));
+        let emissive_texture = textures.emissive
+            .unwrap_or_else(|| white(wgpu::TextureFormat::Rgba8UnormSrgb, "default emissive"));
+
+        // one sampler for all material textures; repeat so tiling uvs work
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(name),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some(name),
+                contents: bytemuck::cast_slice(&[factors.to_uniform()]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&base_color_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&metallic_roughness_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&occlusion_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(&emissive_texture.view),
+                },
+            ],
+            label: Some(name),
+        });
+
+        Self {
+            name: name.to_string(),
+            factors,
+            buffer,
+            bind_group,
+        }
+    }
+
+    // factors can change at runtime; textures need a new material
+    pub fn set_factors(&mut self, queue: &wgpu::Queue, factors: MaterialFactors) {
+        self.factors = factors;
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[factors.to_uniform()]));
+    }
+}
@@ -0,0 +1,141 @@
+use crate::state::bounds::{Aabb, BoundingSphere};
+use crate::state::model::{self, Vertex};
+use wgpu::util::DeviceExt;
+
+mod primitives;
+mod processing;
+mod simplify;
+mod skinning;
+
+pub use primitives::Primitive;
+
+// triangles on the CPU, counter-clockwise when seen from the front; build one with the generators
+// in primitives.rs or resources::load_mesh, clean it up with processing.rs, simplify it into levels
+// of detail with simplify.rs, pose it with skinning.rs and upload it with GpuMesh::new
+#[derive(Debug, Clone, Default)]
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>) -> Self {
+        Self { vertices, indices }
+    }
+
+    // the tutorial's pentagon from model.rs
+    pub fn pentagon() -> Self {
+        Self::new(model::VERTICES.to_vec(), model::INDICES.iter().map(|&index| index as u32).collect())
+    }
+
+    // None without vertices
+    pub fn bounds(&self) -> Option<Aabb> {
+        Aabb::from_points(self.vertices.iter().map(|vertex| glam::Vec3::from(vertex.position)))
+    }
+
+    // the smaller of Ritter's sphere and the one around the bounds; None without vertices
+    pub fn bounding_sphere(&self) -> Option<BoundingSphere> {
+        let points = self.vertices.iter().map(|vertex| glam::Vec3::from(vertex.position)).collect::<Vec<_>>();
+        let ritter = BoundingSphere::from_points(&points)?;
+        let around_bounds = BoundingSphere::from(self.bounds()?);
+        Some(if ritter.radius < around_bounds.radius { ritter } else { around_bounds })
+    }
+
+    pub fn triangles(&self) -> impl Iterator<Item = [glam::Vec3; 3]> + '_ {
+        let position = |index: u32| glam::Vec3::from(self.vertices[index as usize].position);
+        self.indices.chunks_exact(3).map(move |triangle| [position(triangle[0]), position(triangle[1]), position(triangle[2])])
+    }
+}
+
+// a simpler stand-in for a mesh, used once the mesh's bounding sphere covers less than screen_size of
+// the viewport height; see Mesh::generate_lods
+#[derive(Debug, Clone)]
+pub struct Lod {
+    pub mesh: Mesh,
+    pub screen_size: f32,
+}
+
+// where a level of detail is in GpuMesh's buffers
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GpuLod {
+    pub first_index: u32,
+    pub num_indices: u32,
+    pub base_vertex: i32,
+    pub screen_size: f32, // infinite for the full mesh
+}
+
+// a mesh and its levels of detail in shared vertex and index buffers, keeping the full mesh on the
+// CPU for picking
+pub struct GpuMesh {
+    pub mesh: Mesh,
+    pub bounds: Aabb, // of the full mesh; levels of detail are drawn with it too
+    pub sphere: BoundingSphere, // likewise
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub index_format: wgpu::IndexFormat, // Uint16 unless a level has too many vertices
+    pub lods: Vec<GpuLod>, // the full mesh first, then shrinking screen sizes
+}
+
+impl GpuMesh {
+    // levels of detail including the mesh itself; the GPU culling uniform has room for this many
+    pub const MAX_LODS: usize = 8;
+
+    // panics if the mesh is empty or there are too many levels of detail
+    pub fn new(device: &wgpu::Device, mesh: Mesh, mut lods: Vec<Lod>, label: &str) -> Self {
+        assert!(lods.len() < Self::MAX_LODS, "a mesh can have at most {} levels of detail", Self::MAX_LODS - 1);
+        let bounds = mesh.bounds().expect("mesh has no vertices");
+        let sphere = mesh.bounding_sphere().expect("mesh has no vertices");
+        lods.sort_by(|a, b| b.screen_size.total_cmp(&a.screen_size));
+        let levels = std::iter::once((&mesh, f32::INFINITY))
+            .chain(lods.iter().map(|lod| (&lod.mesh, lod.screen_size)))
+            .collect::<Vec<_>>();
+        // indices stay relative to their level's vertices, so the biggest level decides the format
+        let index_format = if levels.iter().any(|(level, _)| level.index_format() == wgpu::IndexFormat::Uint32) {
+            wgpu::IndexFormat::Uint32
+        } else {
+            wgpu::IndexFormat::Uint16
+        };
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut gpu_lods = Vec::with_capacity(levels.len());
+        for (level, screen_size) in levels {
+            gpu_lods.push(GpuLod {
+                first_index: indices.len() as u32,
+                num_indices: level.indices.len() as u32,
+                base_vertex: vertices.len() as i32,
+                screen_size,
+            });
+            vertices.extend_from_slice(&level.vertices);
+            indices.extend_from_slice(&level.indices);
+        }
+        let index_bytes = match index_format {
+            wgpu::IndexFormat::Uint16 => indices.iter().flat_map(|&index| (index as u16).to_ne_bytes()).collect::<Vec<_>>(),
+            wgpu::IndexFormat::Uint32 => bytemuck::cast_slice(&indices).to_vec(),
+        };
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: &index_bytes,
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        Self {
+            mesh,
+            bounds,
+            sphere,
+            vertex_buffer,
+            index_buffer,
+            index_format,
+            lods: gpu_lods,
+        }
+    }
+
+    // the level for an instance covering screen_size of the viewport height, see culling::screen_size
+    pub fn select_lod(&self, screen_size: f32) -> usize {
+        self.lods.iter().rposition(|lod| screen_size < lod.screen_size).unwrap_or(0)
+    }
+}
@@ -0,0 +1,263 @@
+use super::Mesh;
+use crate::state::model::Vertex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+// a generated mesh and its parameters, so scene files can save one; see Primitive::mesh
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Primitive {
+    Cube { size: f32 },
+    UvSphere { radius: f32, sectors: u32, stacks: u32 },
+    Icosphere { radius: f32, subdivisions: u32 },
+    Plane { width: f32, depth: f32, x_segments: u32, z_segments: u32 },
+    Cylinder { radius: f32, height: f32, segments: u32 },
+    Cone { radius: f32, height: f32, segments: u32 },
+    Torus { radius: f32, tube_radius: f32, segments: u32, tube_segments: u32 },
+    Capsule { radius: f32, height: f32, segments: u32, rings: u32 },
+}
+
+impl Primitive {
+    pub fn mesh(&self) -> Mesh {
+        match *self {
+            Primitive::Cube { size } => Mesh::cube(size),
+            Primitive::UvSphere { radius, sectors, stacks } => Mesh::uv_sphere(radius, sectors, stacks),
+            Primitive::Icosphere { radius, subdivisions } => Mesh::icosphere(radius, subdivisions),
+            Primitive::Plane { width, depth, x_segments, z_segments } => Mesh::plane(width, depth, x_segments, z_segments),
+            Primitive::Cylinder { radius, height, segments } => Mesh::cylinder(radius, height, segments),
+            Primitive::Cone { radius, height, segments } => Mesh::cone(radius, height, segments),
+            Primitive::Torus { radius, tube_radius, segments, tube_segments } => Mesh::torus(radius, tube_radius, segments, tube_segments),
+            Primitive::Capsule { radius, height, segments, rings } => Mesh::capsule(radius, height, segments, rings),
+        }
+    }
+}
+
+// a point of a profile that lathe spins around the y axis
+struct ProfilePoint {
+    radius: f32,
+    y: f32,
+    normal: glam::Vec2, // (outwards, up)
+    v: f32,
+}
+
+impl ProfilePoint {
+    fn new(radius: f32, y: f32, normal: glam::Vec2, v: f32) -> Self {
+        Self { radius, y, normal, v }
+    }
+}
+
+// All generators center the mesh on the origin with y up. Segment counts are clamped to what still
+// makes a closed shape; curved surfaces are smooth shaded and their uv seam faces +x
+impl Mesh {
+    // each face has its own vertices, so the edges stay sharp; every face shows the whole texture
+    pub fn cube(size: f32) -> Self {
+        // normal, then the directions u and v grow in, seen from outside
+        let faces = [
+            (glam::Vec3::Z, glam::Vec3::X, glam::Vec3::NEG_Y),
+            (glam::Vec3::NEG_Z, glam::Vec3::NEG_X, glam::Vec3::NEG_Y),
+            (glam::Vec3::X, glam::Vec3::NEG_Z, glam::Vec3::NEG_Y),
+            (glam::Vec3::NEG_X, glam::Vec3::Z, glam::Vec3::NEG_Y),
+            (glam::Vec3::Y, glam::Vec3::X, glam::Vec3::Z),
+            (glam::Vec3::NEG_Y, glam::Vec3::X, glam::Vec3::NEG_Z),
+        ];
+        let mut mesh = Self::default();
+        for (normal, right, down) in faces {
+            mesh.append(&Self::grid(1, 1, |u, v| Vertex {
+                position: ((normal + (u * 2.0 - 1.0) * right + (v * 2.0 - 1.0) * down) * size * 0.5).to_array(),
+                tex_coords: [u, v],
+                normal: normal.to_array(),
+                ..Default::default()
+            }));
+        }
+        mesh
+    }
+
+    // sectors around the y axis, stacks from pole to pole
+    pub fn uv_sphere(radius: f32, sectors: u32, stacks: u32) -> Self {
+        let stacks = stacks.max(2);
+        let profile = (0..=stacks).map(|stack| {
+            let v = stack as f32 / stacks as f32;
+            // sin(PI) comes out just below zero, and the bottom pole has to be a single point
+            let normal = glam::Vec2::new((v * PI).sin().max(0.0), (v * PI).cos());
+            ProfilePoint::new(normal.x * radius, normal.y * radius, normal, v)
+        });
+        Self::lathe(profile.collect(), sectors)
+    }
+
+    // an icosahedron with each triangle split in four per subdivision, so the triangles are close to
+    // even in size; the uvs are the sphere's longitude and latitude
+    pub fn icosphere(radius: f32, subdivisions: u32) -> Self {
+        let t = (1.0 + 5.0_f32.sqrt()) / 2.0;
+        let mut positions = [
+            (-1.0, t, 0.0), (1.0, t, 0.0), (-1.0, -t, 0.0), (1.0, -t, 0.0),
+            (0.0, -1.0, t), (0.0, 1.0, t), (0.0, -1.0, -t), (0.0, 1.0, -t),
+            (t, 0.0, -1.0), (t, 0.0, 1.0), (-t, 0.0, -1.0), (-t, 0.0, 1.0),
+        ].map(|(x, y, z)| glam::Vec3::new(x, y, z).normalize()).to_vec();
+        let mut triangles: Vec<[u32; 3]> = vec![
+            [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+            [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+            [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+            [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+        ];
+        for _ in 0..subdivisions {
+            // shared edges get one midpoint, keyed by their ordered ends
+            let mut midpoints = HashMap::new();
+            let mut midpoint = |a: u32, b: u32| *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                positions.push((positions[a as usize] + positions[b as usize]).normalize());
+                positions.len() as u32 - 1
+            });
+            triangles = triangles.into_iter().flat_map(|[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            }).collect();
+        }
+
+        let uv = |position: glam::Vec3| glam::Vec2::new(
+            (-position.z).atan2(position.x).rem_euclid(TAU) / TAU,
+            position.y.clamp(-1.0, 1.0).acos() / PI,
+        );
+        let mut vertices: Vec<Vertex> = positions.iter().map(|&position| Vertex {
+            position: (position * radius).to_array(),
+            tex_coords: uv(position).to_array(),
+            normal: position.to_array(),
+            ..Default::default()
+        }).collect();
+        let mut indices = Vec::with_capacity(triangles.len() * 3);
+        // triangles across the seam would run backwards through the whole texture; they get copies
+        // of their vertices on the far side with u past 1, which the repeating sampler wraps around
+        let mut wrapped = HashMap::new();
+        for triangle in triangles {
+            let us = triangle.map(|index| vertices[index as usize].tex_coords[0]);
+            let crosses_seam = us.iter().any(|&u| u > 0.75) && us.iter().any(|&u| u < 0.25);
+            for index in triangle {
+                let vertex = vertices[index as usize];
+                if crosses_seam && vertex.tex_coords[0] < 0.5 {
+                    indices.push(*wrapped.entry(index).or_insert_with(|| {
+                        vertices.push(Vertex { tex_coords: [vertex.tex_coords[0] + 1.0, vertex.tex_coords[1]], ..vertex });
+                        vertices.len() as u32 - 1
+                    }));
+                } else {
+                    indices.push(index);
+                }
+            }
+        }
+        Self::new(vertices, indices)
+    }
+
+    // on the xz plane facing up; the texture's top is at -z
+    pub fn plane(width: f32, depth: f32, x_segments: u32, z_segments: u32) -> Self {
+        Self::grid(x_segments.max(1), z_segments.max(1), |u, v| Vertex {
+            position: [(u - 0.5) * width, 0.0, (v - 0.5) * depth],
+            tex_coords: [u, v],
+            normal: [0.0, 1.0, 0.0],
+            ..Default::default()
+        })
+    }
+
+    // with flat caps
+    pub fn cylinder(radius: f32, height: f32, segments: u32) -> Self {
+        let half = height * 0.5;
+        let (up, side, down) = (glam::Vec2::Y, glam::Vec2::X, glam::Vec2::NEG_Y);
+        Self::lathe(vec![
+            ProfilePoint::new(0.0, half, up, 0.0),
+            ProfilePoint::new(radius, half, up, 0.25),
+            ProfilePoint::new(radius, half, side, 0.25),
+            ProfilePoint::new(radius, -half, side, 0.75),
+            ProfilePoint::new(radius, -half, down, 0.75),
+            ProfilePoint::new(0.0, -half, down, 1.0),
+        ], segments)
+    }
+
+    // tip up, with a flat base
+    pub fn cone(radius: f32, height: f32, segments: u32) -> Self {
+        let half = height * 0.5;
+        let side = glam::Vec2::new(height, radius).normalize_or_zero();
+        Self::lathe(vec![
+            ProfilePoint::new(0.0, half, side, 0.0),
+            ProfilePoint::new(radius, -half, side, 0.5),
+            ProfilePoint::new(radius, -half, glam::Vec2::NEG_Y, 0.5),
+            ProfilePoint::new(0.0, -half, glam::Vec2::NEG_Y, 1.0),
+        ], segments)
+    }
+
+    // lying on the xz plane; radius is to the middle of the tube
+    pub fn torus(radius: f32, tube_radius: f32, segments: u32, tube_segments: u32) -> Self {
+        let tube_segments = tube_segments.max(3);
+        // from the top of the tube outwards and around, so the outside of the profile faces out
+        let profile = (0..=tube_segments).map(|segment| {
+            let v = segment as f32 / tube_segments as f32;
+            let angle = FRAC_PI_2 - v * TAU;
+            let normal = glam::Vec2::new(angle.cos(), angle.sin());
+            ProfilePoint::new(radius + normal.x * tube_radius, normal.y * tube_radius, normal, v)
+        });
+        Self::lathe(profile.collect(), segments)
+    }
+
+    // a cylinder with hemispheres for caps, standing on the y axis; height includes the caps and is
+    // at least 2 * radius. rings are per hemisphere
+    pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32) -> Self {
+        let rings = rings.max(1);
+        let half = (height * 0.5 - radius).max(0.0);
+        let length = PI * radius + 2.0 * half; // of the profile, so v is spread evenly along it
+        let mut profile = Vec::with_capacity(2 * rings as usize + 2);
+        for (center, first, v) in [(half, 0, 0.0), (-half, rings, 2.0 * half)] {
+            for ring in first..=first + rings {
+                let angle = ring as f32 / (2 * rings) as f32 * PI;
+                let normal = glam::Vec2::new(angle.sin().max(0.0), angle.cos()); // see uv_sphere
+                let v = (v + angle * radius) / length;
+                profile.push(ProfilePoint::new(normal.x * radius, center + normal.y * radius, normal, v));
+            }
+        }
+        Self::lathe(profile, segments)
+    }
+
+    // adds other's triangles to this mesh
+    pub fn append(&mut self, other: &Mesh) {
+        let offset = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(&other.vertices);
+        self.indices.extend(other.indices.iter().map(|index| index + offset));
+    }
+
+    // (columns + 1) * (rows + 1) vertices from u and v in 0..=1, rows growing downwards on the outside.
+    // Triangles whose corners meet, at poles and where the same point has two normals, are left out
+    fn grid(columns: u32, rows: u32, vertex: impl Fn(f32, f32) -> Vertex) -> Self {
+        let mut vertices = Vec::with_capacity(((columns + 1) * (rows + 1)) as usize);
+        for row in 0..=rows {
+            for column in 0..=columns {
+                vertices.push(vertex(column as f32 / columns as f32, row as f32 / rows as f32));
+            }
+        }
+        let mut indices = Vec::with_capacity((columns * rows * 6) as usize);
+        for row in 0..rows {
+            for column in 0..columns {
+                let top_left = row * (columns + 1) + column;
+                let bottom_left = top_left + columns + 1;
+                for triangle in [[top_left, bottom_left, top_left + 1], [top_left + 1, bottom_left, bottom_left + 1]] {
+                    let [a, b, c] = triangle.map(|index| vertices[index as usize].position);
+                    if a != b && b != c && c != a {
+                        indices.extend_from_slice(&triangle);
+                    }
+                }
+            }
+        }
+        Self::new(vertices, indices)
+    }
+
+    // spins a profile, listed from top to bottom, around the y axis in segments steps
+    fn lathe(profile: Vec<ProfilePoint>, segments: u32) -> Self {
+        let segments = segments.max(3);
+        let rows = profile.len() as u32 - 1;
+        Self::grid(segments, rows, |u, v| {
+            let point = &profile[(v * rows as f32).round() as usize];
+            let (sin, cos) = (u * TAU).sin_cos();
+            // u grows anticlockwise seen from above, so the texture isn't mirrored from outside
+            let around = |radius: f32, y: f32| [radius * cos, y, -radius * sin];
+            Vertex {
+                position: around(point.radius, point.y),
+                tex_coords: [u, point.v],
+                normal: around(point.normal.x, point.normal.y),
+                ..Default::default()
+            }
+        })
+    }
+}
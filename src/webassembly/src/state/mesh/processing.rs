@@ -0,0 +1,249 @@
+use super::Mesh;
+use crate::state::model::Vertex;
+use std::collections::HashMap;
+
+impl Mesh {
+    // averages the normals of the triangles around each position, weighted by their area, so
+    // vertices split at uv seams or hard edges get the same normal
+    pub fn compute_smooth_normals(&mut self) {
+        let key = |vertex: &Vertex| vertex.position.map(f32::to_bits);
+        let mut normals: HashMap<[u32; 3], glam::Vec3> = HashMap::new();
+        for (triangle, [a, b, c]) in self.indices.chunks_exact(3).zip(self.triangles()) {
+            // the cross product's length is twice the area
+            let normal = (b - a).cross(c - a);
+            for &index in triangle {
+                *normals.entry(key(&self.vertices[index as usize])).or_default() += normal;
+            }
+        }
+        for vertex in &mut self.vertices {
+            let normal = normals.get(&key(vertex)).copied().unwrap_or_default();
+            vertex.normal = normal.normalize_or_zero().to_array();
+        }
+    }
+
+    // gives every triangle its own three vertices with the triangle's normal; weld merges what is
+    // still shared afterwards
+    pub fn compute_flat_normals(&mut self) {
+        let mut vertices = Vec::with_capacity(self.indices.len());
+        for (triangle, [a, b, c]) in self.indices.chunks_exact(3).zip(self.triangles()) {
+            let normal = (b - a).cross(c - a).normalize_or_zero().to_array();
+            vertices.extend(triangle.iter().map(|&index| Vertex { normal, ..self.vertices[index as usize] }));
+        }
+        self.indices = (0..vertices.len() as u32).collect();
+        self.vertices = vertices;
+    }
+
+    // merges vertices whose positions, uvs, normals and skin weights are each within tolerance of the
+    // first one seen and that follow the same joints; triangles that collapse are removed. Unused
+    // vertices are dropped
+    pub fn weld(&mut self, tolerance: f32) {
+        // positions are bucketed into cells of the tolerance, so only neighbouring cells are searched
+        let cell_size = tolerance.max(1e-6);
+        let cell = |vertex: &Vertex| (glam::Vec3::from(vertex.position) / cell_size).floor().as_ivec3();
+        let close = |a: &Vertex, b: &Vertex| {
+            glam::Vec3::from(a.position).distance(glam::Vec3::from(b.position)) <= tolerance
+                && glam::Vec2::from(a.tex_coords).abs_diff_eq(glam::Vec2::from(b.tex_coords), tolerance)
+                && glam::Vec3::from(a.normal).abs_diff_eq(glam::Vec3::from(b.normal), tolerance)
+                && a.joints == b.joints
+                && glam::Vec4::from(a.weights).abs_diff_eq(glam::Vec4::from(b.weights), tolerance)
+        };
+        let mut cells: HashMap<glam::IVec3, Vec<u32>> = HashMap::new();
+        let mut vertices: Vec<Vertex> = Vec::new();
+        let mut remap = vec![u32::MAX; self.vertices.len()];
+        for &index in &self.indices {
+            if remap[index as usize] != u32::MAX {
+                continue;
+            }
+            let vertex = &self.vertices[index as usize];
+            let center = cell(vertex);
+            let mut neighbours = (-1..=1).flat_map(|x| (-1..=1).flat_map(move |y| (-1..=1).map(move |z| center + glam::IVec3::new(x, y, z))));
+            let existing = neighbours.find_map(|neighbour| {
+                cells.get(&neighbour)?.iter().copied().find(|&welded| close(&vertices[welded as usize], vertex))
+            });
+            remap[index as usize] = existing.unwrap_or_else(|| {
+                vertices.push(*vertex);
+                let welded = vertices.len() as u32 - 1;
+                cells.entry(center).or_default().push(welded);
+                welded
+            });
+        }
+        self.indices = self.indices.chunks_exact(3)
+            .map(|triangle| [0, 1, 2].map(|corner| remap[triangle[corner] as usize]))
+            .filter(|[a, b, c]| a != b && b != c && c != a)
+            .flatten()
+            .collect();
+        self.vertices = vertices;
+    }
+
+    // vertex cache, overdraw and vertex fetch in the order they depend on each other
+    pub fn optimize(&mut self) {
+        self.optimize_vertex_cache();
+        self.optimize_overdraw();
+        self.optimize_vertex_fetch();
+    }
+
+    // reorders the triangles so the GPU's post-transform cache reuses more vertices, with Tom Forsyth's
+    // linear-speed algorithm: the next triangle is the best scored one among those around cached
+    // vertices, where vertices score high while they are recently used or have few triangles left
+    pub fn optimize_vertex_cache(&mut self) {
+        const CACHE_SIZE: usize = 32;
+        let vertex_score = |cache_position: Option<usize>, remaining: usize| -> f32 {
+            if remaining == 0 {
+                return -1.0;
+            }
+            let cache_score = match cache_position {
+                None => 0.0,
+                Some(position) if position < 3 => 0.75, // the last triangle's; a bit less to avoid repeating it
+                Some(position) => (1.0 - (position - 3) as f32 / (CACHE_SIZE - 3) as f32).powf(1.5),
+            };
+            cache_score + 2.0 * (remaining as f32).powf(-0.5)
+        };
+
+        let triangle_count = self.indices.len() / 3;
+        let mut vertex_triangles = vec![Vec::new(); self.vertices.len()];
+        for (triangle, corners) in self.indices.chunks_exact(3).enumerate() {
+            for &index in corners {
+                vertex_triangles[index as usize].push(triangle);
+            }
+        }
+        let mut remaining = vertex_triangles.iter().map(Vec::len).collect::<Vec<_>>();
+        let mut scores = remaining.iter().map(|&remaining| vertex_score(None, remaining)).collect::<Vec<_>>();
+        let triangle_score = |scores: &[f32], triangle: usize| self.indices[triangle * 3..triangle * 3 + 3].iter().map(|&index| scores[index as usize]).sum::<f32>();
+        let mut triangle_scores = vec![0.0; triangle_count]; // kept up to date around the cache
+        let mut added = vec![false; triangle_count];
+        let mut cache: Vec<u32> = Vec::with_capacity(CACHE_SIZE + 3);
+        let mut indices = Vec::with_capacity(self.indices.len());
+        let mut best = None;
+        let mut next_unadded = 0; // the next start once no cached vertex has triangles left
+        for _ in 0..triangle_count {
+            let triangle = match best {
+                Some(triangle) => triangle,
+                None => {
+                    while added[next_unadded] {
+                        next_unadded += 1;
+                    }
+                    next_unadded
+                }
+            };
+            added[triangle] = true;
+            let corners = &self.indices[triangle * 3..triangle * 3 + 3];
+            indices.extend_from_slice(corners);
+            for &index in corners {
+                remaining[index as usize] -= 1;
+                vertex_triangles[index as usize].retain(|&other| other != triangle);
+                cache.retain(|&cached| cached != index);
+            }
+            // most recent first; what falls off the end is rescored as uncached
+            cache.splice(0..0, corners.iter().copied());
+            let evicted = cache.split_off(cache.len().min(CACHE_SIZE));
+            for &index in evicted.iter().chain(&cache) {
+                scores[index as usize] = vertex_score(cache.iter().position(|&cached| cached == index), remaining[index as usize]);
+            }
+            best = None;
+            for &index in evicted.iter().chain(&cache) {
+                for &other in &vertex_triangles[index as usize] {
+                    triangle_scores[other] = triangle_score(&scores, other);
+                }
+            }
+            let mut best_score = f32::NEG_INFINITY;
+            for &index in &cache {
+                for &other in &vertex_triangles[index as usize] {
+                    if triangle_scores[other] > best_score {
+                        best_score = triangle_scores[other];
+                        best = Some(other);
+                    }
+                }
+            }
+        }
+        self.indices = indices;
+    }
+
+    // reorders clusters of triangles so the ones facing outwards come first and hide what is behind
+    // them, after Sander et al.; clusters end where a triangle misses the cache on all three
+    // vertices, so the vertex cache order inside them is kept
+    pub fn optimize_overdraw(&mut self) {
+        const CACHE_SIZE: usize = 16;
+        let mut clusters: Vec<std::ops::Range<usize>> = Vec::new();
+        let mut cache = std::collections::VecDeque::with_capacity(CACHE_SIZE);
+        for (triangle, corners) in self.indices.chunks_exact(3).enumerate() {
+            let mut misses = 0;
+            for &index in corners {
+                if !cache.contains(&index) {
+                    misses += 1;
+                    if cache.len() == CACHE_SIZE {
+                        cache.pop_back();
+                    }
+                    cache.push_front(index);
+                }
+            }
+            match clusters.last_mut() {
+                Some(cluster) if misses < 3 => cluster.end = triangle + 1,
+                _ => clusters.push(triangle..triangle + 1),
+            }
+        }
+
+        let triangles = self.triangles().collect::<Vec<_>>();
+        let mesh_center = triangles.iter().flatten().copied().sum::<glam::Vec3>() / (triangles.len() * 3).max(1) as f32;
+        // how far the cluster lies out along the way it faces; higher is drawn first
+        let outwardness = |cluster: &std::ops::Range<usize>| {
+            let (mut center, mut normal, mut area) = (glam::Vec3::ZERO, glam::Vec3::ZERO, 0.0);
+            for [a, b, c] in &triangles[cluster.clone()] {
+                let cross = (*b - *a).cross(*c - *a);
+                center += (*a + *b + *c) / 3.0 * cross.length();
+                normal += cross;
+                area += cross.length();
+            }
+            if area == 0.0 {
+                return 0.0;
+            }
+            (center / area - mesh_center).dot(normal.normalize_or_zero())
+        };
+        let mut keyed = clusters.into_iter().map(|cluster| (outwardness(&cluster), cluster)).collect::<Vec<_>>();
+        keyed.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        self.indices = keyed.into_iter()
+            .flat_map(|(_, cluster)| self.indices[cluster.start * 3..cluster.end * 3].to_vec())
+            .collect();
+    }
+
+    // reorders the vertices to where the indices first use them, so vertex fetches run through memory
+    // in order; unused vertices are dropped
+    pub fn optimize_vertex_fetch(&mut self) {
+        let mut remap = vec![u32::MAX; self.vertices.len()];
+        let mut vertices = Vec::with_capacity(self.vertices.len());
+        for index in &mut self.indices {
+            let new = &mut remap[*index as usize];
+            if *new == u32::MAX {
+                vertices.push(self.vertices[*index as usize]);
+                *new = vertices.len() as u32 - 1;
+            }
+            *index = *new;
+        }
+        self.vertices = vertices;
+    }
+
+    // average vertices transformed per triangle with a FIFO cache of cache_size vertices, between 0.5
+    // for a huge regular grid and 3 with no reuse at all
+    pub fn average_cache_miss_ratio(&self, cache_size: usize) -> f32 {
+        let mut cache = std::collections::VecDeque::with_capacity(cache_size);
+        let mut misses = 0;
+        for &index in &self.indices {
+            if !cache.contains(&index) {
+                misses += 1;
+                if cache.len() == cache_size {
+                    cache.pop_back();
+                }
+                cache.push_front(index);
+            }
+        }
+        misses as f32 / (self.indices.len() / 3).max(1) as f32
+    }
+
+    // the smallest index format that reaches every vertex
+    pub fn index_format(&self) -> wgpu::IndexFormat {
+        if self.vertices.len() <= u16::MAX as usize + 1 {
+            wgpu::IndexFormat::Uint16
+        } else {
+            wgpu::IndexFormat::Uint32
+        }
+    }
+}
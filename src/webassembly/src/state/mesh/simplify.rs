@@ -0,0 +1,331 @@
+use super::{Lod, Mesh};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+// the squared distance to a set of planes as a symmetric 4x4 matrix, upper triangle row by row, and
+// the area the planes were weighted with
+#[derive(Debug, Copy, Clone, Default)]
+struct Quadric {
+    planes: [f64; 10],
+    area: f64,
+}
+
+impl Quadric {
+    // weighted by the triangle's area, so big triangles hold their shape more
+    fn from_triangle(a: glam::DVec3, b: glam::DVec3, c: glam::DVec3) -> Self {
+        let cross = (b - a).cross(c - a);
+        let area = cross.length() * 0.5;
+        let normal = cross.normalize_or_zero();
+        let [x, y, z] = normal.to_array();
+        let w = -normal.dot(a);
+        Self {
+            planes: [x * x, x * y, x * z, x * w, y * y, y * z, y * w, z * z, z * w, w * w].map(|q| q * area),
+            area,
+        }
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (q, o) in self.planes.iter_mut().zip(other.planes) {
+            *q += o;
+        }
+        self.area += other.area;
+    }
+
+    // the mean squared distance to the planes
+    fn error(&self, p: glam::DVec3) -> f64 {
+        if self.area == 0.0 {
+            return 0.0;
+        }
+        let [xx, xy, xz, xw, yy, yz, yw, zz, zw, ww] = self.planes;
+        let (x, y, z) = (p.x, p.y, p.z);
+        let error = xx * x * x + 2.0 * xy * x * y + 2.0 * xz * x * z + 2.0 * xw * x
+            + yy * y * y + 2.0 * yz * y * z + 2.0 * yw * y
+            + zz * z * z + 2.0 * zw * z
+            + ww;
+        error.max(0.0) / self.area
+    }
+}
+
+// an edge collapse waiting in the queue; stale once either vertex changed since it was scored
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Collapse {
+    error: f64,
+    from: u32,
+    to: u32,
+    versions: (u32, u32),
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.error.total_cmp(&other.error).then((self.from, self.to).cmp(&(other.from, other.to)))
+    }
+}
+
+impl Mesh {
+    // collapses the edges that change the shape least, measured with Garland and Heckbert's quadric
+    // error, until at most target_triangles are left or the next collapse would move the surface
+    // further than target_error, a fraction of the bounds' diagonal. Vertices only ever move onto a
+    // neighbour, so the result uses a subset of this mesh's vertices and stays inside its bounds.
+    // Vertices on open borders or on seams, where vertices share a position, stay in place
+    pub fn simplify(&self, target_triangles: usize, target_error: f32) -> Mesh {
+        let max_error = self.bounds().map_or(0.0, |bounds| (bounds.max - bounds.min).length() as f64 * target_error as f64);
+        let positions = self.vertices.iter().map(|vertex| glam::Vec3::from(vertex.position).as_dvec3()).collect::<Vec<_>>();
+        let mut triangles = self.indices.chunks_exact(3).map(|triangle| [triangle[0], triangle[1], triangle[2]]).collect::<Vec<_>>();
+        let mut removed = vec![false; triangles.len()];
+        let mut vertex_triangles = vec![Vec::new(); self.vertices.len()];
+        let mut quadrics = vec![Quadric::default(); self.vertices.len()];
+        for (index, triangle) in triangles.iter().enumerate() {
+            let quadric = Quadric::from_triangle(positions[triangle[0] as usize], positions[triangle[1] as usize], positions[triangle[2] as usize]);
+            for &vertex in triangle {
+                vertex_triangles[vertex as usize].push(index);
+                quadrics[vertex as usize].add(&quadric);
+            }
+        }
+
+        let mut locked = vec![false; self.vertices.len()];
+        let mut edges: HashMap<(u32, u32), u32> = HashMap::new();
+        for triangle in &triangles {
+            for corner in 0..3 {
+                let (a, b) = (triangle[corner], triangle[(corner + 1) % 3]);
+                *edges.entry((a.min(b), a.max(b))).or_default() += 1;
+            }
+        }
+        for (&(a, b), &count) in &edges {
+            if count == 1 {
+                locked[a as usize] = true;
+                locked[b as usize] = true;
+            }
+        }
+        let mut shared: HashMap<[u64; 3], u32> = HashMap::new();
+        for position in &positions {
+            *shared.entry(position.to_array().map(f64::to_bits)).or_default() += 1;
+        }
+        for (vertex, position) in positions.iter().enumerate() {
+            if shared[&position.to_array().map(f64::to_bits)] > 1 {
+                locked[vertex] = true;
+            }
+        }
+
+        let mut versions = vec![0u32; self.vertices.len()];
+        let mut queue = BinaryHeap::new();
+        let score = |quadrics: &[Quadric], versions: &[u32], from: u32, to: u32| {
+            let mut quadric = quadrics[from as usize];
+            quadric.add(&quadrics[to as usize]);
+            Reverse(Collapse {
+                error: quadric.error(positions[to as usize]),
+                from,
+                to,
+                versions: (versions[from as usize], versions[to as usize]),
+            })
+        };
+        for &(a, b) in edges.keys() {
+            if !locked[a as usize] {
+                queue.push(score(&quadrics, &versions, a, b));
+            }
+            if !locked[b as usize] {
+                queue.push(score(&quadrics, &versions, b, a));
+            }
+        }
+
+        let mut triangle_count = triangles.len();
+        while triangle_count > target_triangles {
+            let Some(Reverse(collapse)) = queue.pop() else {
+                break;
+            };
+            // the queue is sorted by error, so nothing after this one fits either
+            if collapse.error > max_error * max_error {
+                break;
+            }
+            let (from, to) = (collapse.from as usize, collapse.to as usize);
+            if collapse.versions != (versions[from], versions[to]) {
+                continue;
+            }
+            // the vertices next to both must be the corners across the edge, or the collapse would
+            // glue two sheets of triangles together
+            let neighbours = |vertex: usize| {
+                let mut neighbours = vertex_triangles[vertex].iter().flat_map(|&index| triangles[index]).collect::<Vec<_>>();
+                neighbours.sort_unstable();
+                neighbours.dedup();
+                neighbours
+            };
+            let to_neighbours = neighbours(to);
+            let shared = neighbours(from).into_iter().filter(|vertex| to_neighbours.binary_search(vertex).is_ok()).count();
+            let across = vertex_triangles[from].iter().filter(|&&index| triangles[index].contains(&collapse.to)).count();
+            // from and to are each other's neighbours and count as shared too
+            if shared != across + 2 {
+                continue;
+            }
+            // moving from onto to mustn't turn any of the triangles that stay around by too much
+            let target = positions[to];
+            let flips = vertex_triangles[from].iter().any(|&index| {
+                let triangle = triangles[index];
+                if triangle.contains(&collapse.to) {
+                    return false;
+                }
+                let corners = triangle.map(|vertex| positions[vertex as usize]);
+                let moved = triangle.map(|vertex| if vertex == collapse.from { target } else { positions[vertex as usize] });
+                let before = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+                let after = (moved[1] - moved[0]).cross(moved[2] - moved[0]);
+                before.dot(after) <= 0.25 * before.length() * after.length()
+            });
+            if flips {
+                continue;
+            }
+
+            for index in std::mem::take(&mut vertex_triangles[from]) {
+                let triangle = &mut triangles[index];
+                if triangle.contains(&collapse.to) {
+                    removed[index] = true;
+                    triangle_count -= 1;
+                    for &vertex in triangle.iter() {
+                        vertex_triangles[vertex as usize].retain(|&other| other != index);
+                    }
+                } else {
+                    for vertex in triangle.iter_mut() {
+                        if *vertex == collapse.from {
+                            *vertex = collapse.to;
+                        }
+                    }
+                    vertex_triangles[to].push(index);
+                }
+            }
+            let quadric = quadrics[from];
+            quadrics[to].add(&quadric);
+            versions[from] += 1;
+            versions[to] += 1;
+            // every edge around to changed its error, including the ones that were around from
+            let mut neighbours = vertex_triangles[to].iter().flat_map(|&index| triangles[index]).collect::<Vec<_>>();
+            neighbours.sort_unstable();
+            neighbours.dedup();
+            for neighbour in neighbours.into_iter().filter(|&neighbour| neighbour != collapse.to) {
+                for (a, b) in [(neighbour, collapse.to), (collapse.to, neighbour)] {
+                    if !locked[a as usize] {
+                        queue.push(score(&quadrics, &versions, a, b));
+                    }
+                }
+            }
+        }
+
+        let indices = triangles.iter().zip(&removed)
+            .filter(|(_, &removed)| !removed)
+            .flat_map(|(triangle, _)| *triangle)
+            .collect();
+        let mut mesh = Mesh::new(self.vertices.clone(), indices);
+        mesh.optimize();
+        mesh
+    }
+
+    // up to count simplified levels, each with about half the triangles of the one before and used
+    // once the mesh covers half as much of the screen: the first below a quarter of the viewport
+    // height, with errors up to 1% of the mesh's size, which is a few pixels there. The error
+    // allowed doubles with every level, so it stays about as many pixels. Stops early once
+    // simplifying hardly removes anything more
+    pub fn generate_lods(&self, count: usize) -> Vec<Lod> {
+        let mut lods: Vec<Lod> = Vec::with_capacity(count);
+        let (mut screen_size, mut target_error) = (0.25, 0.01);
+        let mut target_triangles = self.indices.len() / 3;
+        for _ in 0..count {
+            let previous = lods.last().map_or(self, |lod| &lod.mesh).indices.len() / 3;
+            target_triangles /= 2;
+            // always from the full mesh, so errors don't add up from level to level
+            let mesh = self.simplify(target_triangles, target_error);
+            if mesh.indices.len() / 3 > previous * 9 / 10 {
+                break;
+            }
+            lods.push(Lod { mesh, screen_size });
+            screen_size *= 0.5;
+            target_error *= 2.0;
+        }
+        lods
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::bounds::Aabb;
+
+    fn triangle_count(mesh: &Mesh) -> usize {
+        mesh.indices.len() / 3
+    }
+
+    // the simplified mesh only uses the mesh's own vertices, so it can't grow past its bounds
+    fn assert_inside(simplified: &Mesh, mesh: &Mesh) {
+        let (inner, outer) = (simplified.bounds().unwrap(), mesh.bounds().unwrap());
+        assert_eq!(inner.union(&outer), outer, "{inner:?} outside {outer:?}");
+    }
+
+    fn assert_close(a: Aabb, b: Aabb, tolerance: f32) {
+        assert!(a.min.abs_diff_eq(b.min, tolerance) && a.max.abs_diff_eq(b.max, tolerance), "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn simplify_halves_a_uv_sphere_and_torus() {
+        for mesh in [Mesh::uv_sphere(1.0, 32, 16), Mesh::torus(1.0, 0.3, 32, 16)] {
+            let full = triangle_count(&mesh);
+            let simplified = mesh.simplify(full / 2, 0.05);
+            assert!(triangle_count(&simplified) <= full / 2, "{} of {full} triangles", triangle_count(&simplified));
+            assert!(triangle_count(&simplified) > 0);
+            assert_inside(&simplified, &mesh);
+            assert_close(simplified.bounds().unwrap(), mesh.bounds().unwrap(), 0.05);
+            // every index is still in range after optimize dropped the unused vertices
+            assert!(simplified.indices.iter().all(|&index| (index as usize) < simplified.vertices.len()));
+        }
+    }
+
+    #[test]
+    fn simplify_stops_at_the_error() {
+        let mesh = Mesh::uv_sphere(1.0, 32, 16);
+        let coarse = mesh.simplify(0, 0.05);
+        let fine = mesh.simplify(0, 0.001);
+        assert!(triangle_count(&coarse) < triangle_count(&fine));
+        assert!(triangle_count(&fine) < triangle_count(&mesh));
+        // nothing may move at all
+        assert_eq!(triangle_count(&mesh.simplify(0, 0.0)), triangle_count(&mesh));
+    }
+
+    #[test]
+    fn simplify_keeps_open_borders() {
+        let mesh = Mesh::plane(2.0, 2.0, 8, 8);
+        let on_border = |position: [f32; 3]| position[0].abs() == 1.0 || position[2].abs() == 1.0;
+        let border = |mesh: &Mesh| {
+            let mut border = mesh.indices.iter()
+                .map(|&index| mesh.vertices[index as usize].position)
+                .filter(|&position| on_border(position))
+                .map(|position| position.map(f32::to_bits))
+                .collect::<Vec<_>>();
+            border.sort_unstable();
+            border.dedup();
+            border
+        };
+        // flat, so the inside can go entirely
+        let simplified = mesh.simplify(0, 0.01);
+        assert!(triangle_count(&simplified) < triangle_count(&mesh) / 2, "{} triangles", triangle_count(&simplified));
+        assert_eq!(border(&mesh).len(), 4 * 8);
+        assert_eq!(border(&simplified), border(&mesh));
+        assert_eq!(simplified.bounds(), mesh.bounds());
+    }
+
+    #[test]
+    fn generate_lods_shrink_level_by_level() {
+        for mesh in [Mesh::uv_sphere(1.0, 32, 16), Mesh::torus(1.0, 0.3, 32, 16)] {
+            let lods = mesh.generate_lods(3);
+            assert!(!lods.is_empty());
+            let mut previous = (triangle_count(&mesh), 0.5);
+            for lod in &lods {
+                assert!(triangle_count(&lod.mesh) < previous.0);
+                assert_eq!(lod.screen_size, previous.1 * 0.5);
+                assert_inside(&lod.mesh, &mesh);
+                previous = (triangle_count(&lod.mesh), lod.screen_size);
+            }
+        }
+    }
+}
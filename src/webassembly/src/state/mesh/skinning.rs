@@ -0,0 +1,104 @@
+use super::Mesh;
+use crate::state::model::Vertex;
+
+// the vertex's joint matrices by their weights, normalized, like skin_matrix in shader.wgsl; None for
+// vertices without weights. Joints past the end count as the last one
+pub fn skin_matrix(vertex: &Vertex, joint_matrices: &[glam::Mat4]) -> Option<glam::Mat4> {
+    let total = vertex.weights.iter().sum::<f32>();
+    if total == 0.0 || joint_matrices.is_empty() {
+        return None;
+    }
+    let matrix = vertex.joints.iter().zip(vertex.weights)
+        .map(|(&joint, weight)| joint_matrices[(joint as usize).min(joint_matrices.len() - 1)] * weight)
+        .fold(glam::Mat4::ZERO, |sum, matrix| sum + matrix);
+    Some(matrix * total.recip())
+}
+
+impl Mesh {
+    // the mesh as the vertex shader draws it with these joint matrices, e.g. from
+    // Skeleton::joint_matrices, for picking or bounds on the CPU
+    pub fn skinned(&self, joint_matrices: &[glam::Mat4]) -> Mesh {
+        let vertices = self.vertices.iter()
+            .map(|vertex| match skin_matrix(vertex, joint_matrices) {
+                Some(matrix) => Vertex {
+                    position: matrix.transform_point3(vertex.position.into()).to_array(),
+                    normal: matrix.transform_vector3(vertex.normal.into()).normalize_or_zero().to_array(),
+                    ..*vertex
+                },
+                None => *vertex,
+            })
+            .collect();
+        Mesh::new(vertices, self.indices.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(joints: [u16; 4], weights: [f32; 4]) -> Vertex {
+        Vertex {
+            position: [1.0, 2.0, 3.0],
+            tex_coords: [0.0; 2],
+            normal: [0.0, 1.0, 0.0],
+            joints,
+            weights,
+        }
+    }
+
+    // the same sum as skin_matrix in shader.wgsl, one joint at a time
+    fn shader_skin_matrix(vertex: &Vertex, joint_matrices: &[glam::Mat4]) -> glam::Mat4 {
+        let total = vertex.weights[0] + vertex.weights[1] + vertex.weights[2] + vertex.weights[3];
+        if total == 0.0 || joint_matrices.is_empty() {
+            return glam::Mat4::IDENTITY;
+        }
+        let mut matrix = glam::Mat4::ZERO;
+        for i in 0..4 {
+            let joint = (vertex.joints[i] as usize).min(joint_matrices.len() - 1);
+            matrix += joint_matrices[joint] * vertex.weights[i];
+        }
+        matrix * (1.0 / total)
+    }
+
+    #[test]
+    fn skin_matrix_matches_the_shader() {
+        let joint_matrices = [
+            glam::Mat4::from_translation(glam::Vec3::X),
+            glam::Mat4::from_rotation_y(1.0),
+            glam::Mat4::from_scale_rotation_translation(glam::Vec3::splat(2.0), glam::Quat::from_rotation_z(0.5), glam::Vec3::NEG_Y),
+        ];
+        let vertices = [
+            vertex([0, 0, 0, 0], [1.0, 0.0, 0.0, 0.0]),
+            vertex([0, 1, 2, 0], [0.5, 0.25, 0.25, 0.0]),
+            // weights that don't add up to 1 are normalized
+            vertex([1, 2, 0, 0], [2.0, 2.0, 0.0, 0.0]),
+            // joints past the end count as the last one
+            vertex([7, 0, 0, 0], [1.0, 0.0, 0.0, 0.0]),
+            vertex([0, 9, 0, 0], [0.5, 0.5, 0.0, 0.0]),
+        ];
+        for vertex in &vertices {
+            let matrix = skin_matrix(vertex, &joint_matrices).unwrap();
+            assert!(matrix.abs_diff_eq(shader_skin_matrix(vertex, &joint_matrices), 1e-6), "{vertex:?}");
+        }
+        assert_eq!(skin_matrix(&vertices[0], &joint_matrices), Some(joint_matrices[0]));
+        assert_eq!(skin_matrix(&vertices[3], &joint_matrices), Some(joint_matrices[2]));
+        let average = skin_matrix(&vertices[2], &joint_matrices).unwrap();
+        assert!(average.abs_diff_eq((joint_matrices[1] + joint_matrices[2]) * 0.5, 1e-6));
+    }
+
+    #[test]
+    fn vertices_without_weights_or_joints_stay_put() {
+        let joint_matrices = [glam::Mat4::from_translation(glam::Vec3::X)];
+        let unweighted = vertex([0; 4], [0.0; 4]);
+        assert_eq!(skin_matrix(&unweighted, &joint_matrices), None);
+        assert_eq!(skin_matrix(&vertex([0; 4], [1.0, 0.0, 0.0, 0.0]), &[]), None);
+        assert_eq!(shader_skin_matrix(&unweighted, &joint_matrices), glam::Mat4::IDENTITY);
+
+        let mesh = Mesh::new(vec![unweighted, vertex([0; 4], [1.0, 0.0, 0.0, 0.0])], vec![0, 1, 1]);
+        let skinned = mesh.skinned(&joint_matrices);
+        assert_eq!((skinned.vertices[0].position, skinned.vertices[0].normal), (unweighted.position, unweighted.normal));
+        assert_eq!(skinned.vertices[1].position, [2.0, 2.0, 3.0]);
+        assert_eq!(skinned.vertices[1].normal, [0.0, 1.0, 0.0]);
+        assert_eq!(skinned.indices, mesh.indices);
+    }
+}
@@ -1,8 +1,11 @@
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)] // need bytemuck to cast to &[u8] for buffer
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)] // need bytemuck to cast to &[u8] for buffer
 pub struct Vertex {                                                  // Pod = plain old data = can convert to u8
-    position: [f32; 3],
-    tex_coords: [f32; 2]
+    pub position: [f32; 3],
+    pub tex_coords: [f32; 2],
+    pub normal: [f32; 3],
+    pub joints: [u16; 4], // into the skeleton's joints, see animation::Skeleton
+    pub weights: [f32; 4], // of each joint; all zero for vertices that don't follow the skeleton
 }
 
 impl Vertex {
@@ -20,18 +23,33 @@ impl Vertex {
                     offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress, // offset as in jump over the previous elements
                     shader_location: 1, // corresponds to @location(1) some_name: vec3<f32>
                     format: wgpu::VertexFormat::Float32x2,
-                }
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Uint16x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: (std::mem::size_of::<[f32; 8]>() + std::mem::size_of::<[u16; 4]>()) as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
             ]
         }
     }
 }
 
 pub const VERTICES: &[Vertex] = &[
-    Vertex { position: [-0.0868241, 0.49240386, 0.0], tex_coords: [0.4131759, 0.00759614], }, // 0
-    Vertex { position: [-0.49513406, 0.06958647, 0.0], tex_coords: [0.0048659444, 0.43041354], }, // 1
-    Vertex { position: [-0.21918549, -0.44939706, 0.0], tex_coords: [0.28081453, 0.949397], }, // 2
-    Vertex { position: [0.35966998, -0.3473291, 0.0], tex_coords: [0.85967, 0.84732914], }, // 3
-    Vertex { position: [0.44147372, 0.2347359, 0.0], tex_coords: [0.9414737, 0.2652641], }, // 4
+    Vertex { position: [-0.0868241, 0.49240386, 0.0], tex_coords: [0.4131759, 0.00759614], normal: [0.0, 0.0, 1.0], joints: [0; 4], weights: [0.0; 4], }, // 0
+    Vertex { position: [-0.49513406, 0.06958647, 0.0], tex_coords: [0.0048659444, 0.43041354], normal: [0.0, 0.0, 1.0], joints: [0; 4], weights: [0.0; 4], }, // 1
+    Vertex { position: [-0.21918549, -0.44939706, 0.0], tex_coords: [0.28081453, 0.949397], normal: [0.0, 0.0, 1.0], joints: [0; 4], weights: [0.0; 4], }, // 2
+    Vertex { position: [0.35966998, -0.3473291, 0.0], tex_coords: [0.85967, 0.84732914], normal: [0.0, 0.0, 1.0], joints: [0; 4], weights: [0.0; 4], }, // 3
+    Vertex { position: [0.44147372, 0.2347359, 0.0], tex_coords: [0.9414737, 0.2652641], normal: [0.0, 0.0, 1.0], joints: [0; 4], weights: [0.0; 4], }, // 4
 ];
 
 pub const INDICES: &[u16] = &[
@@ -41,65 +59,101 @@ pub const INDICES: &[u16] = &[
 ];
 
 
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Instance { // actual rotation and position
     pub position: glam::Vec3,
     pub rotation: glam::Quat,
+    pub scale: glam::Vec3, // along the model's own axes; a zero component leaves the normals undefined
+    pub color: glam::Vec4, // RGBA tint multiplied into the material's base color
+    pub custom: glam::Vec4, // not used by the built-in shaders; reaches the fragment stage as VertexOutput::custom
+    pub pose: u32, // which of State's poses a skinned mesh is drawn in, see State::set_pose
+    pub mesh: usize, // which of State's meshes is drawn, see State::add_mesh
+}
+
+impl Default for Instance {
+    fn default() -> Self {
+        Self {
+            position: glam::Vec3::ZERO,
+            rotation: glam::Quat::IDENTITY,
+            scale: glam::Vec3::ONE,
+            color: glam::Vec4::ONE,
+            custom: glam::Vec4::ZERO,
+            pose: 0,
+            mesh: 0,
+        }
+    }
 }
 
 impl Instance {
-    pub fn to_raw(&self) -> InstanceRaw {
-        // Build the model matrix by combining translation and rotation
-        let translation_matrix = glam::Mat4::from_translation(self.position);
-        let rotation_matrix = glam::Mat4::from_quat(self.rotation);
+    pub fn to_matrix(&self) -> glam::Mat4 {
+        // Build the model matrix by combining translation, rotation and scale
+        glam::Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.position)
+    }
 
-        let model_matrix = translation_matrix * rotation_matrix;
+    // inverse transpose of the model matrix's 3x3 part, so normals stay perpendicular under
+    // non-uniform scale; with the rotation orthonormal that is just the rotation over the scale
+    pub fn normal_matrix(&self) -> glam::Mat3 {
+        glam::Mat3::from_quat(self.rotation) * glam::Mat3::from_diagonal(self.scale.recip())
+    }
 
-        // Convert the model matrix to the InstanceRaw representation
-        InstanceRaw {
-            model: model_matrix.to_cols_array_2d(),
-        }
+    pub fn to_raw(&self) -> InstanceRaw {
+        // Convert the instance to the InstanceRaw representation
+        InstanceRaw::new(self.to_matrix(), self.normal_matrix(), self.color, self.custom, self.pose, self.mesh as u32)
     }
 }
 
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct InstanceRaw { // converted to shader-usable matrix of position + rotation
+pub struct InstanceRaw { // converted to shader-usable matrices plus the per-instance extras
     model: [[f32; 4]; 4],
+    normal: [[f32; 4]; 3], // columns padded to 16 bytes, the layout of a mat3x3 in a storage buffer
+    color: [f32; 4],
+    custom: [f32; 4],
+    pose: u32,
+    mesh: u32, // which of State's meshes the instance draws
+    _padding: [u32; 2], // a storage buffer rounds the struct up to 16 bytes
 }
 
 impl InstanceRaw {
-    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
-        use std::mem;
+    pub fn new(model: glam::Mat4, normal: glam::Mat3, color: glam::Vec4, custom: glam::Vec4, pose: u32, mesh: u32) -> Self {
+        Self {
+            model: model.to_cols_array_2d(),
+            normal: [normal.x_axis, normal.y_axis, normal.z_axis].map(|column| column.extend(0.0).to_array()),
+            color: color.to_array(),
+            custom: custom.to_array(),
+            pose,
+            mesh,
+            _padding: [0; 2],
+        }
+    }
+
+    pub fn model_matrix(&self) -> glam::Mat4 {
+        glam::Mat4::from_cols_array_2d(&self.model)
+    }
+
+    pub fn pose(&self) -> u32 {
+        self.pose
+    }
+
+    pub fn mesh(&self) -> usize {
+        self.mesh as usize
+    }
+
+    // the instances drawn are u32 indices into the instance buffer, one per instance, and the vertex
+    // shader reads the InstanceRaw behind each from storage
+    pub fn index_desc() -> wgpu::VertexBufferLayout<'static> {
         wgpu::VertexBufferLayout {
-            array_stride: mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
+            array_stride: std::mem::size_of::<u32>() as wgpu::BufferAddress,
             // We need to switch from using a step mode of Vertex to Instance
             // This means that our shaders will only change to use the next
             // instance when the shader starts processing a new instance
             step_mode: wgpu::VertexStepMode::Instance,
             attributes: &[
-                // A mat4 takes up 4 vertex slots as it is technically 4 vec4s. We need to define a slot
-                // for each vec4. We'll have to reassemble the mat4 in the shader.
+                // after Vertex's locations
                 wgpu::VertexAttribute {
                     offset: 0,
-                    // While our vertex shader only uses locations 0, and 1 now, in later tutorials we'll
-                    // be using 2, 3, and 4, for Vertex. We'll start at slot 5 not conflict with them later
                     shader_location: 5,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
-                    shader_location: 6,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
-                    shader_location: 7,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
-                    shader_location: 8,
-                    format: wgpu::VertexFormat::Float32x4,
+                    format: wgpu::VertexFormat::Uint32,
                 },
             ],
         }
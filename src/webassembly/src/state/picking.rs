@@ -0,0 +1,70 @@
+use crate::state::bounds::Aabb;
+
+mod id_buffer;
+
+pub use id_buffer::{IdBuffer, IdPick};
+
+// a half line from origin; direction doesn't have to be normalized, distances along the ray are
+// measured in multiples of it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Ray {
+    pub origin: glam::Vec3,
+    pub direction: glam::Vec3,
+}
+
+impl Ray {
+    pub fn new(origin: glam::Vec3, direction: glam::Vec3) -> Self {
+        Self { origin, direction }
+    }
+
+    pub fn at(&self, t: f32) -> glam::Vec3 {
+        self.origin + self.direction * t
+    }
+
+    // the same points in another space; t stays the same for every point along the ray
+    pub fn transform(&self, matrix: &glam::Mat4) -> Self {
+        Self::new(matrix.transform_point3(self.origin), matrix.transform_vector3(self.direction))
+    }
+
+    // slab test; Some(0) when the origin is inside the box
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        let inverse = self.direction.recip(); // infinities for axis parallel rays work out
+        let t1 = (aabb.min - self.origin) * inverse;
+        let t2 = (aabb.max - self.origin) * inverse;
+        let near = t1.min(t2).max_element().max(0.0);
+        let far = t1.max(t2).min_element();
+        (near <= far).then_some(near)
+    }
+
+    // Möller-Trumbore; hits both faces of the triangle
+    pub fn intersect_triangle(&self, a: glam::Vec3, b: glam::Vec3, c: glam::Vec3) -> Option<f32> {
+        let edge1 = b - a;
+        let edge2 = c - a;
+        let p = self.direction.cross(edge2);
+        let determinant = edge1.dot(p);
+        if determinant.abs() < f32::EPSILON {
+            return None; // parallel to the triangle
+        }
+        let inverse = 1.0 / determinant;
+        let s = self.origin - a;
+        let u = s.dot(p) * inverse;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = s.cross(edge1);
+        let v = self.direction.dot(q) * inverse;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = edge2.dot(q) * inverse;
+        (t >= 0.0).then_some(t)
+    }
+}
+
+// the closest instance along a ray
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Hit {
+    pub instance: usize, // index into the instances, then the scene graph's, see State::scene_node
+    pub distance: f32, // in world units from the ray origin
+    pub point: glam::Vec3, // in world space
+}
@@ -0,0 +1,223 @@
+use crate::state::{model, texture};
+use std::sync::{Arc, Mutex};
+
+// the instance under a pixel, as seen by the ID buffer
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct IdPick {
+    pub pixel: glam::UVec2,
+    pub instance: Option<usize>, // None for the background
+}
+
+enum Readback {
+    Idle,
+    Copied(glam::UVec2), // copy encoded, mapping starts after submit
+    Mapping(glam::UVec2, Arc<Mutex<Option<Result<(), wgpu::BufferAsyncError>>>>),
+}
+
+// pixel exact picking: on request, the instances the scene drew are drawn again with their index
+// into an R32Uint target, by the same draws and at the same levels of detail, with a depth buffer of
+// its own, and the one texel asked for is read back without blocking
+pub struct IdBuffer {
+    target: texture::Texture,
+    depth: texture::Texture,
+    depth_mode: texture::DepthMode,
+    pipeline: wgpu::RenderPipeline,
+    readback_buffer: wgpu::Buffer,
+    requested: Option<glam::UVec2>,
+    readback: Readback,
+}
+
+impl IdBuffer {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+
+    // scene_shader is the module with vs_id and fs_id
+    pub fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        depth_mode: texture::DepthMode,
+        scene_shader: &wgpu::ShaderModule,
+        material_layout: &wgpu::BindGroupLayout,
+        camera_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        // the scene's layouts, so its draws can be replayed here; the material goes unused
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("ID Buffer Pipeline Layout"),
+            bind_group_layouts: &[material_layout, camera_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("ID Buffer Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: scene_shader,
+                entry_point: "vs_id",
+                buffers: &[model::Vertex::desc(), model::InstanceRaw::index_desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: scene_shader,
+                entry_point: "fs_id",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: Self::FORMAT,
+                    blend: None, // integer targets can't blend
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: depth_mode.compare(),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("ID Buffer Readback Buffer"),
+            size: std::mem::size_of::<u32>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            target: Self::create_target(device, width, height, Self::FORMAT),
+            depth: Self::create_target(device, width, height, texture::Texture::DEPTH_FORMAT),
+            depth_mode,
+            pipeline,
+            readback_buffer,
+            requested: None,
+            readback: Readback::Idle,
+        }
+    }
+
+    fn create_target(device: &wgpu::Device, width: u32, height: u32, format: wgpu::TextureFormat) -> texture::Texture {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("id_buffer"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // never sampled, but Texture always carries one
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+        texture::Texture { texture, view, sampler }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.target = Self::create_target(device, width, height, Self::FORMAT);
+        self.depth = Self::create_target(device, width, height, texture::Texture::DEPTH_FORMAT);
+    }
+
+    // the latest request wins if the one before hasn't been drawn yet
+    pub fn request(&mut self, pixel: glam::UVec2) {
+        self.requested = Some(pixel);
+    }
+
+    // the pixel to draw the IDs for, if there is a request and no readback in flight
+    pub fn take_request(&mut self) -> Option<glam::UVec2> {
+        match self.readback {
+            Readback::Idle => self.requested.take(),
+            _ => None,
+        }
+    }
+
+    // the pass to draw the scene's instances into, with the scene's material bind group at 0 and its
+    // camera bind group at 1; call after take_request gave a pixel
+    pub fn begin_pass<'a>(&'a self, encoder: &'a mut wgpu::CommandEncoder) -> wgpu::RenderPass<'a> {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("ID Buffer Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.target.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT), // 0, the background
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.depth.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(self.depth_mode.far_depth()),
+                    store: false,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass
+    }
+
+    // copies the texel at pixel once the pass has ended
+    pub fn read_texel(&mut self, encoder: &mut wgpu::CommandEncoder, pixel: glam::UVec2) {
+        let size = self.target.texture.size();
+        let texel = pixel.min(glam::UVec2::new(size.width, size.height) - 1);
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.target.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: texel.x, y: texel.y, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &self.readback_buffer,
+                layout: wgpu::ImageDataLayout::default(), // a single row needs no bytes_per_row
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.readback = Readback::Copied(pixel);
+    }
+
+    // call once the encoder given to read_texel has been submitted
+    pub fn after_submit(&mut self) {
+        if let Readback::Copied(pixel) = self.readback {
+            let result = Arc::new(Mutex::new(None));
+            let callback_result = result.clone();
+            self.readback_buffer.slice(..).map_async(wgpu::MapMode::Read, move |mapped| {
+                *callback_result.lock().unwrap() = Some(mapped);
+            });
+            self.readback = Readback::Mapping(pixel, result);
+        }
+    }
+
+    // the finished pick, if the readback has arrived; never waits for the GPU
+    pub fn poll(&mut self, device: &wgpu::Device) -> Option<IdPick> {
+        let Readback::Mapping(pixel, result) = &self.readback else {
+            return None;
+        };
+        device.poll(wgpu::Maintain::Poll);
+        let mapped = result.lock().unwrap().take()?;
+        let pixel = *pixel;
+        self.readback = Readback::Idle;
+        // a failed mapping, e.g. after the device was lost, reads as the background
+        let id = match mapped {
+            Ok(()) => {
+                let id = bytemuck::pod_read_unaligned::<u32>(&self.readback_buffer.slice(..).get_mapped_range());
+                self.readback_buffer.unmap();
+                id
+            }
+            Err(_) => 0,
+        };
+        Some(IdPick {
+            pixel,
+            instance: id.checked_sub(1).map(|index| index as usize),
+        })
+    }
+}
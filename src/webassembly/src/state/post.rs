@@ -0,0 +1,185 @@
+use crate::state::{hdr, texture};
+
+mod bloom;
+mod color_grading;
+mod fxaa;
+mod vignette;
+
+pub use bloom::{Bloom, BloomSettings};
+pub use color_grading::{ColorGrading, ColorGradingSettings};
+pub use fxaa::Fxaa;
+pub use vignette::{Vignette, VignetteSettings};
+
+// where in the frame an effect runs
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PostStage {
+    Hdr, // before tonemapping, on HdrPipeline::FORMAT textures
+    Ldr, // after tonemapping, on surface format textures
+}
+
+pub trait PostEffect {
+    fn name(&self) -> &'static str;
+    fn stage(&self) -> PostStage;
+    fn enabled(&self) -> bool;
+    fn set_enabled(&mut self, enabled: bool);
+    // effects with their own textures recreate them here; called with the surface size
+    fn resize(&mut self, _device: &wgpu::Device, _width: u32, _height: u32) {}
+    // read input and overwrite every pixel of output; both are textures of the effect's stage
+    fn apply(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &texture::Texture,
+        output: &wgpu::TextureView,
+    );
+    // lets PostProcessStack::get_mut() hand out the concrete effect to change its settings
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any;
+}
+
+// runs the enabled effects in order: HDR effects, tonemapping, then LDR effects;
+// intermediate results ping-pong between two textures per stage
+pub struct PostProcessStack {
+    effects: Vec<Box<dyn PostEffect>>,
+    hdr_target: texture::Texture, // the other half is the HdrPipeline texture
+    ldr_targets: [texture::Texture; 2],
+    ldr_format: wgpu::TextureFormat,
+}
+
+impl PostProcessStack {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let (hdr_target, ldr_targets) = Self::create_targets(device, config.format, config.width, config.height);
+        Self {
+            effects: Vec::new(),
+            hdr_target,
+            ldr_targets,
+            ldr_format: config.format,
+        }
+    }
+
+    // stack with the built-in effects; vignette and color grading start disabled
+    pub fn with_default_effects(device: &wgpu::Device, queue: &wgpu::Queue, config: &wgpu::SurfaceConfiguration) -> Self {
+        let mut stack = Self::new(device, config);
+        stack.push(Box::new(Bloom::new(device, config.width, config.height)));
+        stack.push(Box::new(Fxaa::new(device, config.format)));
+        stack.push(Box::new(Vignette::new(device, config.format)));
+        stack.push(Box::new(ColorGrading::new(device, queue, config.format)));
+        stack.set_enabled(Vignette::NAME, false);
+        stack.set_enabled(ColorGrading::NAME, false);
+        stack
+    }
+
+    fn create_targets(
+        device: &wgpu::Device,
+        ldr_format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> (texture::Texture, [texture::Texture; 2]) {
+        let hdr_target = texture::Texture::create_render_target(device, width, height, hdr::HdrPipeline::FORMAT, "post_hdr_target");
+        let ldr_targets = [
+            texture::Texture::create_render_target(device, width, height, ldr_format, "post_ldr_target_0"),
+            texture::Texture::create_render_target(device, width, height, ldr_format, "post_ldr_target_1"),
+        ];
+        (hdr_target, ldr_targets)
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        (self.hdr_target, self.ldr_targets) = Self::create_targets(device, self.ldr_format, width, height);
+        for effect in self.effects.iter_mut() {
+            effect.resize(device, width, height);
+        }
+    }
+
+    // region: --- ORDERING
+    pub fn push(&mut self, effect: Box<dyn PostEffect>) {
+        self.effects.push(effect);
+    }
+
+    pub fn insert(&mut self, index: usize, effect: Box<dyn PostEffect>) {
+        self.effects.insert(index.min(self.effects.len()), effect);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Box<dyn PostEffect>> {
+        let index = self.position(name)?;
+        Some(self.effects.remove(index))
+    }
+
+    // moves an effect to a new position in the chain; returns false if there is no such effect
+    pub fn move_to(&mut self, name: &str, index: usize) -> bool {
+        match self.remove(name) {
+            Some(effect) => {
+                self.insert(index, effect);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.effects.iter().map(|effect| effect.name()).collect()
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.effects.iter().position(|effect| effect.name() == name)
+    }
+    // endregion: --- ORDERING
+
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self.position(name) {
+            Some(index) => {
+                self.effects[index].set_enabled(enabled);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.position(name).is_some_and(|index| self.effects[index].enabled())
+    }
+
+    // first effect of type T, e.g. stack.get_mut::<Bloom>()
+    pub fn get_mut<T: PostEffect + 'static>(&mut self) -> Option<&mut T> {
+        self.effects.iter_mut().find_map(|effect| effect.as_any_mut().downcast_mut::<T>())
+    }
+
+    // post-process the HdrPipeline texture into the output (usually the surface texture)
+    pub fn process(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        hdr: &hdr::HdrPipeline,
+        output: &wgpu::TextureView,
+    ) {
+        let Self { effects, hdr_target, ldr_targets, .. } = self;
+
+        // HDR effects ping-pong between the scene texture and hdr_target
+        let mut input = hdr.texture();
+        for effect in effects.iter_mut().filter(|e| e.enabled() && e.stage() == PostStage::Hdr) {
+            let target = if std::ptr::eq(input, &*hdr_target) { hdr.texture() } else { &*hdr_target };
+            effect.apply(device, queue, encoder, input, &target.view);
+            input = target;
+        }
+
+        let ldr_count = effects.iter().filter(|e| e.enabled() && e.stage() == PostStage::Ldr).count();
+        if ldr_count == 0 {
+            hdr.process(device, encoder, input, output);
+            return;
+        }
+
+        // LDR effects ping-pong between the ldr targets; the last one writes to the output
+        hdr.process(device, encoder, input, &ldr_targets[0].view);
+        let mut current = 0;
+        let mut remaining = ldr_count;
+        for effect in effects.iter_mut().filter(|e| e.enabled() && e.stage() == PostStage::Ldr) {
+            remaining -= 1;
+            if remaining == 0 {
+                effect.apply(device, queue, encoder, &ldr_targets[current], output);
+            } else {
+                effect.apply(device, queue, encoder, &ldr_targets[current], &ldr_targets[1 - current].view);
+                current = 1 - current;
+            }
+        }
+    }
+}
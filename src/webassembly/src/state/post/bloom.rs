@@ -0,0 +1,265 @@
+use crate::state::{fullscreen, hdr, texture};
+use crate::state::post::{PostEffect, PostStage};
+use wgpu::util::DeviceExt;
+
+// This is so we can store this in a buffer; layout matches BloomUniform in bloom.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BloomUniform {
+    threshold: f32,
+    knee: f32,
+    intensity: f32,
+    filter_radius: f32,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct BloomSettings {
+    pub threshold: f32,
+    pub knee: f32,
+    pub intensity: f32,
+    pub filter_radius: f32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            threshold: 1.0,
+            knee: 0.5,
+            intensity: 0.1,
+            filter_radius: 0.005,
+        }
+    }
+}
+
+// downsample/upsample bloom on a half resolution mip chain
+pub struct Bloom {
+    enabled: bool,
+    pub settings: BloomSettings,
+    mip_texture: wgpu::Texture, // kept alive for the mip views
+    mip_views: Vec<wgpu::TextureView>,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    layout: wgpu::BindGroupLayout,
+    composite_layout: wgpu::BindGroupLayout,
+    downsample_prefilter_pipeline: wgpu::RenderPipeline,
+    downsample_pipeline: wgpu::RenderPipeline,
+    upsample_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+}
+
+impl Bloom {
+    pub const NAME: &'static str = "bloom";
+    const MAX_MIP_LEVELS: u32 = 6;
+
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let settings = BloomSettings::default();
+        let uniform_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Bloom Buffer"),
+                contents: bytemuck::cast_slice(&[Self::uniform(&settings)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("bloom_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                fullscreen::texture_entry(0),
+                fullscreen::sampler_entry(1),
+                fullscreen::uniform_entry(2),
+            ],
+            label: Some("bloom_bind_group_layout"),
+        });
+        let composite_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                fullscreen::texture_entry(0),
+                fullscreen::sampler_entry(1),
+                fullscreen::uniform_entry(2),
+                fullscreen::texture_entry(3),
+            ],
+            label: Some("bloom_composite_bind_group_layout"),
+        });
+
+        let shader = fullscreen::create_shader(device, "bloom.wgsl", include_str!("../../bloom.wgsl"));
+        let format = hdr::HdrPipeline::FORMAT;
+        let downsample_prefilter_pipeline = fullscreen::create_pipeline(device, "Bloom Prefilter Pipeline", &[&layout], &shader, "fs_downsample_prefilter", format, wgpu::BlendState::REPLACE);
+        let downsample_pipeline = fullscreen::create_pipeline(device, "Bloom Downsample Pipeline", &[&layout], &shader, "fs_downsample", format, wgpu::BlendState::REPLACE);
+        // upsampled result is added on top of the downsampled mip it is drawn into
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let upsample_pipeline = fullscreen::create_pipeline(
+            device,
+            "Bloom Upsample Pipeline",
+            &[&layout],
+            &shader,
+            "fs_upsample",
+            format,
+            wgpu::BlendState { color: additive, alpha: additive },
+        );
+        let composite_pipeline = fullscreen::create_pipeline(device, "Bloom Composite Pipeline", &[&composite_layout], &shader, "fs_composite", format, wgpu::BlendState::REPLACE);
+
+        let (mip_texture, mip_views) = Self::create_mip_chain(device, width, height);
+
+        Self {
+            enabled: true,
+            settings,
+            mip_texture,
+            mip_views,
+            sampler,
+            uniform_buffer,
+            layout,
+            composite_layout,
+            downsample_prefilter_pipeline,
+            downsample_pipeline,
+            upsample_pipeline,
+            composite_pipeline,
+        }
+    }
+
+    fn uniform(settings: &BloomSettings) -> BloomUniform {
+        BloomUniform {
+            threshold: settings.threshold,
+            knee: settings.knee,
+            intensity: settings.intensity,
+            filter_radius: settings.filter_radius,
+        }
+    }
+
+    // one view per mip level, starting at half the surface size
+    fn create_mip_chain(device: &wgpu::Device, width: u32, height: u32) -> (wgpu::Texture, Vec<wgpu::TextureView>) {
+        let width = (width / 2).max(1);
+        let height = (height / 2).max(1);
+        let mip_level_count = width.min(height).ilog2().min(Self::MAX_MIP_LEVELS - 1) + 1;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("bloom_texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: hdr::HdrPipeline::FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let views = (0..mip_level_count)
+            .map(|mip_level| texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("bloom_mip"),
+                base_mip_level: mip_level,
+                mip_level_count: Some(1),
+                ..Default::default()
+            }))
+            .collect();
+        (texture, views)
+    }
+
+    fn bind_group(&self, device: &wgpu::Device, source: &wgpu::TextureView) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("bloom_bind_group"),
+        })
+    }
+}
+
+impl PostEffect for Bloom {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn stage(&self) -> PostStage {
+        PostStage::Hdr
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        (self.mip_texture, self.mip_views) = Self::create_mip_chain(device, width, height);
+    }
+
+    fn apply(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &texture::Texture,
+        output: &wgpu::TextureView,
+    ) {
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[Self::uniform(&self.settings)]));
+
+        // downsample: scene -> mip 0 -> mip 1 -> ...
+        let bind_group = self.bind_group(device, &input.view);
+        fullscreen::draw(encoder, "Bloom Prefilter Pass", &self.downsample_prefilter_pipeline, &bind_group, &self.mip_views[0], wgpu::LoadOp::Load);
+        for mip_level in 1..self.mip_views.len() {
+            let bind_group = self.bind_group(device, &self.mip_views[mip_level - 1]);
+            fullscreen::draw(encoder, "Bloom Downsample Pass", &self.downsample_pipeline, &bind_group, &self.mip_views[mip_level], wgpu::LoadOp::Load);
+        }
+
+        // upsample: ... -> mip 1 -> mip 0, each added onto the downsampled mip
+        for mip_level in (1..self.mip_views.len()).rev() {
+            let bind_group = self.bind_group(device, &self.mip_views[mip_level]);
+            fullscreen::draw(encoder, "Bloom Upsample Pass", &self.upsample_pipeline, &bind_group, &self.mip_views[mip_level - 1], wgpu::LoadOp::Load);
+        }
+
+        let composite_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.composite_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&input.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&self.mip_views[0]),
+                },
+            ],
+            label: Some("bloom_composite_bind_group"),
+        });
+        fullscreen::draw(encoder, "Bloom Composite Pass", &self.composite_pipeline, &composite_bind_group, output, wgpu::LoadOp::Load);
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
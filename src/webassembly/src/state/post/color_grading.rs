@@ -0,0 +1,225 @@
+use crate::state::{fullscreen, texture};
+use crate::state::post::{PostEffect, PostStage};
+use wgpu::util::DeviceExt;
+
+// This is so we can store this in a buffer; layout matches ColorGradingUniform in color_grading.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ColorGradingUniform {
+    strength: f32,
+    _padding: [f32; 3],
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ColorGradingSettings {
+    pub strength: f32,
+}
+
+impl Default for ColorGradingSettings {
+    fn default() -> Self {
+        Self { strength: 1.0 }
+    }
+}
+
+// LUT based color grading; starts with an identity LUT that leaves colors unchanged
+pub struct ColorGrading {
+    enabled: bool,
+    pub settings: ColorGradingSettings,
+    lut: wgpu::Texture,
+    lut_view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl ColorGrading {
+    pub const NAME: &'static str = "color_grading";
+    const IDENTITY_LUT_SIZE: u32 = 32;
+
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, format: wgpu::TextureFormat) -> Self {
+        let settings = ColorGradingSettings::default();
+        let uniform_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Color Grading Buffer"),
+                contents: bytemuck::cast_slice(&[Self::uniform(&settings)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        // linear filtering interpolates between LUT entries
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("color_grading_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                fullscreen::texture_entry(0),
+                fullscreen::sampler_entry(1),
+                fullscreen::uniform_entry(2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("color_grading_bind_group_layout"),
+        });
+        let shader = fullscreen::create_shader(device, "color_grading.wgsl", include_str!("../../color_grading.wgsl"));
+        let pipeline = fullscreen::create_pipeline(device, "Color Grading Pipeline", &[&layout], &shader, "fs_color_grading", format, wgpu::BlendState::REPLACE);
+
+        let size = Self::IDENTITY_LUT_SIZE;
+        let (lut, lut_view) = Self::create_lut(device, queue, &Self::identity_lut(size), size);
+
+        Self {
+            enabled: true,
+            settings,
+            lut,
+            lut_view,
+            sampler,
+            uniform_buffer,
+            layout,
+            pipeline,
+        }
+    }
+
+    fn uniform(settings: &ColorGradingSettings) -> ColorGradingUniform {
+        ColorGradingUniform {
+            strength: settings.strength,
+            _padding: [0.0; 3],
+        }
+    }
+
+    // red along x, green along y, blue along z
+    fn identity_lut(size: u32) -> Vec<u8> {
+        let scale = |i: u32| (i * 255 / (size - 1)) as u8;
+        let mut data = Vec::with_capacity((size * size * size * 4) as usize);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    data.extend_from_slice(&[scale(r), scale(g), scale(b), 255]);
+                }
+            }
+        }
+        data
+    }
+
+    fn create_lut(device: &wgpu::Device, queue: &wgpu::Queue, data: &[u8], size: u32) -> (wgpu::Texture, wgpu::TextureView) {
+        let extent = wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: size,
+        };
+        let lut = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("color_grading_lut"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::Rgba8Unorm, // holds display encoded colors
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &lut,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: Default::default(),
+            },
+            data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * size),
+                rows_per_image: Some(size),
+            },
+            extent,
+        );
+        let lut_view = lut.create_view(&wgpu::TextureViewDescriptor::default());
+        (lut, lut_view)
+    }
+
+    // LUT as a PNG strip of size x size slices next to each other (size * size wide, size high),
+    // blue increasing per slice; the common format exported by grading tools
+    pub fn set_lut_strip(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, bytes: &[u8]) -> Result<(), texture::TextureError> {
+        let (strip, width, size) = texture::Texture::decode_png(bytes)?;
+        if width != size * size {
+            return Err(texture::TextureError::InvalidDimensions(width, size));
+        }
+        let mut data = Vec::with_capacity(strip.len());
+        for b in 0..size {
+            for g in 0..size {
+                let start = ((g * width + b * size) * 4) as usize;
+                data.extend_from_slice(&strip[start..start + (size * 4) as usize]);
+            }
+        }
+        (self.lut, self.lut_view) = Self::create_lut(device, queue, &data, size);
+        Ok(())
+    }
+}
+
+impl PostEffect for ColorGrading {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn stage(&self) -> PostStage {
+        PostStage::Ldr
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn apply(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &texture::Texture,
+        output: &wgpu::TextureView,
+    ) {
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[Self::uniform(&self.settings)]));
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&input.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&self.lut_view),
+                },
+            ],
+            label: Some("color_grading_bind_group"),
+        });
+        fullscreen::draw(encoder, "Color Grading Pass", &self.pipeline, &bind_group, output, wgpu::LoadOp::Load);
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
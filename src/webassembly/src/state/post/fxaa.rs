@@ -0,0 +1,91 @@
+use crate::state::{fullscreen, texture};
+use crate::state::post::{PostEffect, PostStage};
+
+// fast approximate anti-aliasing on the tonemapped image
+pub struct Fxaa {
+    enabled: bool,
+    sampler: wgpu::Sampler,
+    layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl Fxaa {
+    pub const NAME: &'static str = "fxaa";
+
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        // FXAA relies on bilinear filtering between texels
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("fxaa_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                fullscreen::texture_entry(0),
+                fullscreen::sampler_entry(1),
+            ],
+            label: Some("fxaa_bind_group_layout"),
+        });
+        let shader = fullscreen::create_shader(device, "fxaa.wgsl", include_str!("../../fxaa.wgsl"));
+        let pipeline = fullscreen::create_pipeline(device, "FXAA Pipeline", &[&layout], &shader, "fs_fxaa", format, wgpu::BlendState::REPLACE);
+
+        Self {
+            enabled: true,
+            sampler,
+            layout,
+            pipeline,
+        }
+    }
+}
+
+impl PostEffect for Fxaa {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn stage(&self) -> PostStage {
+        PostStage::Ldr
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn apply(
+        &mut self,
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &texture::Texture,
+        output: &wgpu::TextureView,
+    ) {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&input.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+            label: Some("fxaa_bind_group"),
+        });
+        fullscreen::draw(encoder, "FXAA Pass", &self.pipeline, &bind_group, output, wgpu::LoadOp::Load);
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
@@ -0,0 +1,135 @@
+use crate::state::{fullscreen, texture};
+use crate::state::post::{PostEffect, PostStage};
+use wgpu::util::DeviceExt;
+
+// This is so we can store this in a buffer; layout matches VignetteUniform in vignette.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct VignetteUniform {
+    intensity: f32,
+    radius: f32,
+    smoothness: f32,
+    _padding: f32,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct VignetteSettings {
+    pub intensity: f32,
+    pub radius: f32,
+    pub smoothness: f32,
+}
+
+impl Default for VignetteSettings {
+    fn default() -> Self {
+        Self {
+            intensity: 0.5,
+            radius: 0.4,
+            smoothness: 0.4,
+        }
+    }
+}
+
+pub struct Vignette {
+    enabled: bool,
+    pub settings: VignetteSettings,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl Vignette {
+    pub const NAME: &'static str = "vignette";
+
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let settings = VignetteSettings::default();
+        let uniform_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Vignette Buffer"),
+                contents: bytemuck::cast_slice(&[Self::uniform(&settings)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                fullscreen::texture_entry(0),
+                fullscreen::sampler_entry(1),
+                fullscreen::uniform_entry(2),
+            ],
+            label: Some("vignette_bind_group_layout"),
+        });
+        let shader = fullscreen::create_shader(device, "vignette.wgsl", include_str!("../../vignette.wgsl"));
+        let pipeline = fullscreen::create_pipeline(device, "Vignette Pipeline", &[&layout], &shader, "fs_vignette", format, wgpu::BlendState::REPLACE);
+
+        Self {
+            enabled: true,
+            settings,
+            sampler,
+            uniform_buffer,
+            layout,
+            pipeline,
+        }
+    }
+
+    fn uniform(settings: &VignetteSettings) -> VignetteUniform {
+        VignetteUniform {
+            intensity: settings.intensity,
+            radius: settings.radius,
+            smoothness: settings.smoothness,
+            _padding: 0.0,
+        }
+    }
+}
+
+impl PostEffect for Vignette {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn stage(&self) -> PostStage {
+        PostStage::Ldr
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn apply(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &texture::Texture,
+        output: &wgpu::TextureView,
+    ) {
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[Self::uniform(&self.settings)]));
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&input.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("vignette_bind_group"),
+        });
+        fullscreen::draw(encoder, "Vignette Pass", &self.pipeline, &bind_group, output, wgpu::LoadOp::Load);
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
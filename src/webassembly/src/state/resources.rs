@@ -1,33 +1,75 @@
+use crate::state::mesh::Mesh;
+use crate::state::model::Vertex;
 use crate::state::texture;
 
 #[derive(Debug)]
 pub enum ResourceError {
+    Io(std::io::Error), // missing or unreadable file
     TextureError(texture::TextureError),
+    Obj(tobj::LoadError),
+    EmptyMesh, // a model file without triangles
 }
 
-pub async fn load_string(file_name: &str) -> String {
+pub async fn load_string(file_name: &str) -> Result<String, ResourceError> {
     let path = std::path::Path::new(env!("OUT_DIR"))
         .join("res")
         .join(file_name);
-    let txt = std::fs::read_to_string(path).unwrap();
+    let txt = std::fs::read_to_string(path).map_err(ResourceError::Io)?;
 
-    txt
+    Ok(txt)
 }
 
-pub async fn load_binary(file_name: &str) -> Vec<u8> {
+pub async fn load_binary(file_name: &str) -> Result<Vec<u8>, ResourceError> {
     let path = std::path::Path::new(env!("OUT_DIR"))
         .join("res")
         .join(file_name);
-    let data = std::fs::read(path).unwrap();
+    let data = std::fs::read(path).map_err(ResourceError::Io)?;
 
-    data
+    Ok(data)
 }
 
+// format is sRGB for color data, linear for everything else
 pub async fn load_texture(
     file_name: &str,
+    format: wgpu::TextureFormat,
     device: &wgpu::Device,
     queue: &wgpu::Queue,
-) -> Result<texture::Texture, texture::TextureError> {
-    let data = load_binary(file_name).await;
-    texture::Texture::from_bytes(device, queue, &data, file_name)
+) -> Result<texture::Texture, ResourceError> {
+    let data = load_binary(file_name).await?;
+    texture::Texture::from_bytes_with_format(device, queue, &data, format, file_name).map_err(ResourceError::TextureError)
+}
+
+// every model in an OBJ file as one mesh, without materials; normals are generated where the file has
+// none, and the triangles are optimized for drawing
+pub async fn load_mesh(file_name: &str) -> Result<Mesh, ResourceError> {
+    let text = load_string(file_name).await?;
+    let options = tobj::LoadOptions {
+        single_index: true, // one index per vertex, like the index buffer
+        triangulate: true,
+        ..Default::default()
+    };
+    let (models, _) = tobj::load_obj_buf(&mut std::io::Cursor::new(text), &options, |_| Ok(Default::default()))
+        .map_err(ResourceError::Obj)?;
+
+    let mut mesh = Mesh::default();
+    for model in models {
+        let obj = model.mesh;
+        let vertices = (0..obj.positions.len() / 3).map(|i| Vertex {
+            position: [obj.positions[i * 3], obj.positions[i * 3 + 1], obj.positions[i * 3 + 2]],
+            // OBJ's v grows upwards
+            tex_coords: obj.texcoords.get(i * 2..i * 2 + 2).map_or([0.0, 0.0], |uv| [uv[0], 1.0 - uv[1]]),
+            normal: obj.normals.get(i * 3..i * 3 + 3).map_or([0.0, 0.0, 0.0], |normal| [normal[0], normal[1], normal[2]]),
+            ..Default::default()
+        }).collect();
+        let mut part = Mesh::new(vertices, obj.indices);
+        if obj.normals.is_empty() {
+            part.compute_smooth_normals();
+        }
+        mesh.append(&part);
+    }
+    if mesh.indices.is_empty() {
+        return Err(ResourceError::EmptyMesh);
+    }
+    mesh.optimize();
+    Ok(mesh)
 }
@@ -0,0 +1,277 @@
+use crate::state::model::InstanceRaw;
+use std::collections::BTreeMap;
+use std::fmt;
+
+// a node's position relative to its parent
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transform {
+    pub translation: glam::Vec3,
+    pub rotation: glam::Quat,
+    pub scale: glam::Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: glam::Vec3::ZERO,
+            rotation: glam::Quat::IDENTITY,
+            scale: glam::Vec3::ONE,
+        }
+    }
+}
+
+impl Transform {
+    pub fn from_translation(translation: glam::Vec3) -> Self {
+        Self {
+            translation,
+            ..Default::default()
+        }
+    }
+
+    pub fn to_matrix(&self) -> glam::Mat4 {
+        glam::Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+// handle to a node; stays invalid once the node is removed, even if its slot is reused
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: u32,
+    generation: u32,
+}
+
+// what a node draws: a mesh by index, tinted like model::Instance
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NodeMesh {
+    pub mesh: usize,
+    pub color: glam::Vec4,
+    pub custom: glam::Vec4,
+    pub pose: u32,
+}
+
+impl NodeMesh {
+    pub fn new(mesh: usize) -> Self {
+        Self {
+            mesh,
+            color: glam::Vec4::ONE,
+            custom: glam::Vec4::ZERO,
+            pose: 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneGraphError {
+    Cycle, // the new parent is the node itself or one of its descendants
+}
+
+impl fmt::Display for SceneGraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneGraphError::Cycle => write!(f, "a node can't be parented to itself or its descendants"),
+        }
+    }
+}
+
+// the instances of one mesh in a flattened scene graph, with the node each came from
+#[derive(Default)]
+pub struct MeshBatch {
+    pub nodes: Vec<NodeId>,
+    pub instances: Vec<InstanceRaw>,
+}
+
+struct Node {
+    transform: Transform,
+    mesh: Option<NodeMesh>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    world: glam::Mat4, // cached, valid after update_world_matrices
+    dirty: bool, // transform or parent changed since world was computed
+}
+
+struct Slot {
+    generation: u32,
+    node: Option<Node>,
+}
+
+// nodes with local transforms in a hierarchy; world matrices are cached and only recomputed for
+// subtrees below a changed node. Methods taking a NodeId panic if the node was removed
+#[derive(Default)]
+pub struct SceneGraph {
+    slots: Vec<Slot>,
+    free: Vec<u32>, // slots without a node
+    roots: Vec<NodeId>,
+    changed: bool, // anything that changes the flattened instances since the last flatten
+}
+
+impl SceneGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, parent: Option<NodeId>, transform: Transform) -> NodeId {
+        if let Some(parent) = parent {
+            self.node(parent); // checked before anything changes
+        }
+        let node = Node {
+            transform,
+            mesh: None,
+            parent,
+            children: Vec::new(),
+            world: glam::Mat4::IDENTITY,
+            dirty: true,
+        };
+        let id = match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.node = Some(node);
+                NodeId { index, generation: slot.generation }
+            }
+            None => {
+                self.slots.push(Slot { generation: 0, node: Some(node) });
+                NodeId { index: self.slots.len() as u32 - 1, generation: 0 }
+            }
+        };
+        self.siblings_mut(parent).push(id);
+        self.changed = true;
+        id
+    }
+
+    // removes the node and everything below it
+    pub fn remove(&mut self, id: NodeId) {
+        let parent = self.node(id).parent;
+        self.siblings_mut(parent).retain(|&sibling| sibling != id);
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let slot = &mut self.slots[id.index as usize];
+            let node = slot.node.take().unwrap();
+            slot.generation = slot.generation.wrapping_add(1);
+            self.free.push(id.index);
+            stack.extend(node.children);
+        }
+        self.changed = true;
+    }
+
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.slots.get(id.index as usize)
+            .is_some_and(|slot| slot.generation == id.generation && slot.node.is_some())
+    }
+
+    // moves the node, keeping its local transform, so it follows the new parent from now on
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) -> Result<(), SceneGraphError> {
+        let mut ancestor = parent;
+        while let Some(node) = ancestor {
+            if node == id {
+                return Err(SceneGraphError::Cycle);
+            }
+            ancestor = self.node(node).parent;
+        }
+        let old_parent = self.node(id).parent;
+        self.siblings_mut(old_parent).retain(|&sibling| sibling != id);
+        self.siblings_mut(parent).push(id);
+        let node = self.node_mut(id);
+        node.parent = parent;
+        node.dirty = true;
+        self.changed = true;
+        Ok(())
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).parent
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.node(id).children
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    pub fn transform(&self, id: NodeId) -> &Transform {
+        &self.node(id).transform
+    }
+
+    pub fn set_transform(&mut self, id: NodeId, transform: Transform) {
+        let node = self.node_mut(id);
+        node.transform = transform;
+        node.dirty = true;
+        self.changed = true;
+    }
+
+    pub fn mesh(&self, id: NodeId) -> Option<&NodeMesh> {
+        self.node(id).mesh.as_ref()
+    }
+
+    // None makes the node a pure transform, e.g. a joint
+    pub fn set_mesh(&mut self, id: NodeId, mesh: Option<NodeMesh>) {
+        self.node_mut(id).mesh = mesh;
+        self.changed = true;
+    }
+
+    // the world matrix as of the last update_world_matrices or flatten
+    pub fn world_matrix(&self, id: NodeId) -> glam::Mat4 {
+        self.node(id).world
+    }
+
+    // whether flatten would give different instances than last time
+    pub fn is_changed(&self) -> bool {
+        self.changed
+    }
+
+    // recomputes the world matrices of changed nodes and everything below them
+    pub fn update_world_matrices(&mut self) {
+        let mut stack = self.roots.iter().map(|&root| (root, glam::Mat4::IDENTITY, false)).collect::<Vec<_>>();
+        while let Some((id, parent_world, parent_changed)) = stack.pop() {
+            let node = self.node_mut(id);
+            let changed = node.dirty || parent_changed;
+            if changed {
+                node.world = parent_world * node.transform.to_matrix();
+                node.dirty = false;
+            }
+            let world = node.world;
+            // reversed so children are visited in order
+            stack.extend(node.children.iter().rev().map(|&child| (child, world, changed)));
+        }
+    }
+
+    // the instances of every node with a mesh, grouped by mesh, depth first from the roots in the
+    // order nodes were added; the order only changes when nodes are added, removed or moved
+    pub fn flatten(&mut self) -> BTreeMap<usize, MeshBatch> {
+        self.update_world_matrices();
+        self.changed = false;
+        let mut batches = BTreeMap::<usize, MeshBatch>::new();
+        let mut stack = self.roots.iter().rev().copied().collect::<Vec<_>>();
+        while let Some(id) = stack.pop() {
+            let node = self.node(id);
+            if let Some(mesh) = &node.mesh {
+                // the parents' scale can shear, so the normal matrix is the general inverse transpose
+                let normal = glam::Mat3::from_mat4(node.world).inverse().transpose();
+                let batch = batches.entry(mesh.mesh).or_default();
+                batch.nodes.push(id);
+                batch.instances.push(InstanceRaw::new(node.world, normal, mesh.color, mesh.custom, mesh.pose, mesh.mesh as u32));
+            }
+            stack.extend(node.children.iter().rev());
+        }
+        batches
+    }
+
+    fn node(&self, id: NodeId) -> &Node {
+        let slot = &self.slots[id.index as usize];
+        assert_eq!(slot.generation, id.generation, "node was removed");
+        slot.node.as_ref().expect("node was removed")
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        let slot = &mut self.slots[id.index as usize];
+        assert_eq!(slot.generation, id.generation, "node was removed");
+        slot.node.as_mut().expect("node was removed")
+    }
+
+    fn siblings_mut(&mut self, parent: Option<NodeId>) -> &mut Vec<NodeId> {
+        match parent {
+            Some(parent) => &mut self.node_mut(parent).children,
+            None => &mut self.roots,
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use crate::state::camera::Projection;
+use crate::state::light::PointLight;
+use crate::state::material::MaterialFactors;
+use crate::state::mesh::{GpuMesh, Mesh, Primitive};
+use crate::state::model::Instance;
+use crate::state::resources::{self, ResourceError};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+// where a mesh comes from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MeshSource {
+    Builtin, // the pentagon in model.rs
+    Asset(String), // an OBJ file under res/, see resources::load_mesh
+    Primitive(Primitive),
+}
+
+impl MeshSource {
+    pub async fn load(&self) -> Result<Mesh, SceneFileError> {
+        match self {
+            MeshSource::Builtin => Ok(Mesh::pentagon()),
+            MeshSource::Asset(path) => resources::load_mesh(path).await
+                .map_err(|error| SceneFileError::Resource(path.clone(), error)),
+            MeshSource::Primitive(primitive) => Ok(primitive.mesh()),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneCamera {
+    pub eye: glam::Vec3,
+    pub target: glam::Vec3,
+    pub up: glam::Vec3,
+    pub projection: Projection,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneLight {
+    pub direction: glam::Vec3, // direction the light travels in
+    pub color: glam::Vec3, // linear rgb
+    pub intensity: f32,
+}
+
+// textures are paths under res/; None leaves only the factor
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneMaterial {
+    pub name: String,
+    pub factors: MaterialFactors,
+    pub base_color_texture: Option<String>,
+    pub metallic_roughness_texture: Option<String>,
+    pub occlusion_texture: Option<String>,
+    pub emissive_texture: Option<String>,
+}
+
+// anything left out takes the value of Instance::default
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneInstance {
+    pub mesh: usize, // index into SceneFile::meshes
+    pub position: glam::Vec3,
+    pub rotation: glam::Quat,
+    pub scale: glam::Vec3,
+    pub color: glam::Vec4,
+    pub custom: glam::Vec4,
+    pub pose: u32,
+}
+
+impl Default for SceneInstance {
+    fn default() -> Self {
+        Self::new(&Instance::default())
+    }
+}
+
+impl SceneInstance {
+    pub fn new(instance: &Instance) -> Self {
+        Self {
+            mesh: instance.mesh,
+            position: instance.position,
+            rotation: instance.rotation,
+            scale: instance.scale,
+            color: instance.color,
+            custom: instance.custom,
+            pose: instance.pose,
+        }
+    }
+
+    pub fn to_instance(&self) -> Instance {
+        Instance {
+            position: self.position,
+            rotation: self.rotation,
+            scale: self.scale,
+            color: self.color,
+            custom: self.custom,
+            pose: self.pose,
+            mesh: self.mesh,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneFileError {
+    Parse(ron::error::SpannedError),
+    MissingMesh(usize), // an instance refers to a mesh past the end of meshes
+    TooManyLods(usize), // more than GpuMesh::MAX_LODS minus the mesh itself
+    UnsavedMesh, // one of State's meshes was set from a Mesh, so there is no source to save
+    Resource(String, ResourceError), // a file that couldn't be loaded, by path
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneFileError::Parse(error) => write!(f, "{error}"),
+            SceneFileError::MissingMesh(mesh) => write!(f, "an instance uses mesh {mesh}, which isn't in meshes"),
+            SceneFileError::TooManyLods(count) => write!(f, "the scene asks for {count} levels of detail, but at most {} fit", GpuMesh::MAX_LODS - 1),
+            SceneFileError::UnsavedMesh => write!(f, "a mesh wasn't made from a MeshSource and can't be saved"),
+            SceneFileError::Resource(path, error) => write!(f, "{path}: {error:?}"),
+        }
+    }
+}
+
+// everything State draws, saved as RON so it can be edited by hand; see State::scene_file and
+// State::load_scene_file. Instances are saved in order, with the pose they are drawn in; the scene
+// graph, the ECS world, the skeleton and its poses are not
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneFile {
+    pub background: [f64; 4], // rgba
+    pub camera: SceneCamera,
+    pub light: SceneLight,
+    #[serde(default)]
+    pub point_lights: Vec<PointLight>,
+    pub material: SceneMaterial, // every instance is drawn with it
+    pub meshes: Vec<MeshSource>, // State's meshes in order
+    #[serde(default)]
+    pub lods: usize, // levels of detail generated from each mesh, see State::generate_lods
+    #[serde(default)]
+    pub instances: Vec<SceneInstance>,
+}
+
+impl SceneFile {
+    // what State can't load, before anything is changed
+    pub fn validate(&self) -> Result<(), SceneFileError> {
+        if self.lods >= GpuMesh::MAX_LODS {
+            return Err(SceneFileError::TooManyLods(self.lods));
+        }
+        match self.instances.iter().find(|instance| instance.mesh >= self.meshes.len()) {
+            Some(instance) => Err(SceneFileError::MissingMesh(instance.mesh)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for SceneFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).map_err(|_| fmt::Error)?;
+        writeln!(f, "{text}")
+    }
+}
+
+impl FromStr for SceneFile {
+    type Err = SceneFileError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        ron::from_str(text).map_err(SceneFileError::Parse)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene() -> SceneFile {
+        SceneFile {
+            background: [0.1, 0.2, 0.3, 1.0],
+            camera: SceneCamera {
+                eye: glam::Vec3::new(0.0, 1.5, 4.0),
+                target: glam::Vec3::ZERO,
+                up: glam::Vec3::Y,
+                projection: Projection::InfinitePerspectiveReverseZ { fovy: 45.0, znear: 0.1 },
+            },
+            light: SceneLight {
+                direction: glam::Vec3::new(-0.3, -1.0, 0.2),
+                color: glam::Vec3::ONE,
+                intensity: 2.5,
+            },
+            point_lights: vec![PointLight {
+                position: glam::Vec3::new(1.0, 2.0, 3.0),
+                color: glam::Vec3::new(1.0, 0.5, 0.25),
+                intensity: 10.0,
+                radius: 8.0,
+            }],
+            material: SceneMaterial {
+                name: "brick".to_string(),
+                factors: MaterialFactors { metallic: 0.25, roughness: 0.75, ..Default::default() },
+                base_color_texture: Some("brick.png".to_string()),
+                ..Default::default()
+            },
+            meshes: vec![
+                MeshSource::Builtin,
+                MeshSource::Asset("cube.obj".to_string()),
+                MeshSource::Primitive(Primitive::Torus { radius: 1.0, tube_radius: 0.3, segments: 32, tube_segments: 16 }),
+            ],
+            lods: 2,
+            instances: vec![
+                SceneInstance::default(),
+                SceneInstance {
+                    mesh: 2,
+                    position: glam::Vec3::new(-1.0, 0.0, 2.0),
+                    rotation: glam::Quat::from_rotation_y(0.7),
+                    scale: glam::Vec3::splat(0.5),
+                    color: glam::Vec4::new(1.0, 0.0, 0.0, 1.0),
+                    custom: glam::Vec4::new(0.1, 0.2, 0.3, 0.4),
+                    pose: 3,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        let scene = scene();
+        let text = scene.to_string();
+        assert_eq!(text.parse::<SceneFile>().unwrap(), scene);
+        assert!(scene.validate().is_ok());
+    }
+
+    #[test]
+    fn left_out_fields_take_their_defaults() {
+        let mut scene = scene();
+        scene.point_lights.clear();
+        scene.lods = 0;
+        scene.instances.clear();
+        let text = scene.to_string()
+            .lines()
+            .filter(|line| !line.contains("point_lights") && !line.contains("lods") && !line.contains("instances"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(text.parse::<SceneFile>().unwrap(), scene);
+    }
+
+    #[test]
+    fn parse_errors_are_reported() {
+        assert!(matches!("(background: (1, 2".parse::<SceneFile>(), Err(SceneFileError::Parse(_))));
+    }
+
+    #[test]
+    fn validate_finds_missing_meshes() {
+        let mut scene = scene();
+        scene.instances.push(SceneInstance { mesh: 3, ..Default::default() });
+        scene.instances.push(SceneInstance { mesh: 9, ..Default::default() });
+        assert!(matches!(scene.validate(), Err(SceneFileError::MissingMesh(3))));
+
+        scene.meshes.clear();
+        scene.instances.clear();
+        assert!(scene.validate().is_ok());
+    }
+
+    #[test]
+    fn validate_limits_the_levels_of_detail() {
+        let mut scene = scene();
+        scene.lods = GpuMesh::MAX_LODS - 1;
+        assert!(scene.validate().is_ok());
+        scene.lods = GpuMesh::MAX_LODS;
+        assert!(matches!(scene.validate(), Err(SceneFileError::TooManyLods(count)) if count == GpuMesh::MAX_LODS));
+    }
+}
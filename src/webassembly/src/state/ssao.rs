@@ -0,0 +1,307 @@
+use crate::state::{camera, fullscreen, texture};
+use wgpu::util::DeviceExt;
+
+const MAX_SAMPLES: usize = 64;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SsaoQuality {
+    Off,
+    Low,
+    Medium,
+    High,
+    Ultra,
+}
+
+impl SsaoQuality {
+    fn sample_count(self) -> u32 {
+        match self {
+            SsaoQuality::Off => 0,
+            SsaoQuality::Low => 8,
+            SsaoQuality::Medium => 16,
+            SsaoQuality::High => 32,
+            SsaoQuality::Ultra => MAX_SAMPLES as u32,
+        }
+    }
+
+    // AO is computed at the surface size divided by this
+    fn resolution_divisor(self) -> u32 {
+        match self {
+            SsaoQuality::Off | SsaoQuality::Low | SsaoQuality::Medium => 2,
+            SsaoQuality::High | SsaoQuality::Ultra => 1,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SsaoSettings {
+    pub quality: SsaoQuality,
+    pub radius: f32,
+    pub bias: f32,
+    pub intensity: f32,
+}
+
+impl Default for SsaoSettings {
+    fn default() -> Self {
+        Self {
+            quality: SsaoQuality::Medium,
+            radius: 0.5,
+            bias: 0.025,
+            intensity: 1.5,
+        }
+    }
+}
+
+// This is so we can store this in a buffer; layout matches SsaoUniform in ssao.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SsaoUniform {
+    proj: [[f32; 4]; 4],
+    inv_proj: [[f32; 4]; 4],
+    kernel: [[f32; 4]; MAX_SAMPLES],
+    radius: f32,
+    bias: f32,
+    intensity: f32,
+    sample_count: u32,
+    far_depth: f32,
+    _padding: [u32; 3],
+}
+
+// ambient occlusion from the depth buffer; the blurred result is bound at output_bind_group
+// for the forward shader to darken ambient light with
+pub struct Ssao {
+    settings: SsaoSettings,
+    uniform: SsaoUniform,
+    uniform_buffer: wgpu::Buffer,
+    layout: wgpu::BindGroupLayout,
+    blur_layout: wgpu::BindGroupLayout,
+    output_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+    blur_pipeline: wgpu::RenderPipeline,
+    ao_texture: texture::Texture,
+    blurred_texture: texture::Texture,
+    output_bind_group: wgpu::BindGroup,
+    width: u32,
+    height: u32,
+}
+
+impl Ssao {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+    pub fn new(device: &wgpu::Device, width: u32, height: u32, settings: SsaoSettings) -> Self {
+        let uniform = SsaoUniform {
+            proj: glam::Mat4::IDENTITY.to_cols_array_2d(),
+            inv_proj: glam::Mat4::IDENTITY.to_cols_array_2d(),
+            kernel: Self::kernel(settings.quality.sample_count()),
+            radius: settings.radius,
+            bias: settings.bias,
+            intensity: settings.intensity,
+            sample_count: settings.quality.sample_count(),
+            far_depth: 1.0, // set with the projection in update
+            _padding: [0; 3],
+        };
+        let uniform_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("SSAO Buffer"),
+                contents: bytemuck::cast_slice(&[uniform]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                fullscreen::uniform_entry(1),
+            ],
+            label: Some("ssao_bind_group_layout"),
+        });
+        let blur_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                fullscreen::texture_entry(0),
+            ],
+            label: Some("ssao_blur_bind_group_layout"),
+        });
+        let output_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                fullscreen::texture_entry(0),
+                fullscreen::sampler_entry(1),
+            ],
+            label: Some("ssao_output_bind_group_layout"),
+        });
+
+        let shader = fullscreen::create_shader(device, "ssao.wgsl", include_str!("../ssao.wgsl"));
+        let pipeline = fullscreen::create_pipeline(device, "SSAO Pipeline", &[&layout], &shader, "fs_ssao", Self::FORMAT, wgpu::BlendState::REPLACE);
+        let blur_pipeline = fullscreen::create_pipeline(device, "SSAO Blur Pipeline", &[&blur_layout], &shader, "fs_blur", Self::FORMAT, wgpu::BlendState::REPLACE);
+
+        let (ao_texture, blurred_texture, output_bind_group) = Self::create_targets(device, &output_layout, width, height, settings.quality);
+
+        Self {
+            settings,
+            uniform,
+            uniform_buffer,
+            layout,
+            blur_layout,
+            output_layout,
+            pipeline,
+            blur_pipeline,
+            ao_texture,
+            blurred_texture,
+            output_bind_group,
+            width,
+            height,
+        }
+    }
+
+    // hemisphere around +z, denser close to the center
+    fn kernel(sample_count: u32) -> [[f32; 4]; MAX_SAMPLES] {
+        // xorshift so the kernel is the same on every run
+        let mut state = 0x9E37_79B9_u32;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as f32 / u32::MAX as f32
+        };
+        let mut kernel = [[0.0; 4]; MAX_SAMPLES];
+        for (i, sample) in kernel.iter_mut().take(sample_count as usize).enumerate() {
+            let direction = glam::Vec3::new(random() * 2.0 - 1.0, random() * 2.0 - 1.0, random())
+                .normalize_or_zero();
+            let t = i as f32 / sample_count as f32;
+            let scale = 0.1 + 0.9 * t * t;
+            *sample = (direction * random() * scale).extend(0.0).to_array();
+        }
+        kernel
+    }
+
+    fn create_targets(
+        device: &wgpu::Device,
+        output_layout: &wgpu::BindGroupLayout,
+        width: u32,
+        height: u32,
+        quality: SsaoQuality,
+    ) -> (texture::Texture, texture::Texture, wgpu::BindGroup) {
+        let divisor = quality.resolution_divisor();
+        let width = (width / divisor).max(1);
+        let height = (height / divisor).max(1);
+        let ao_texture = texture::Texture::create_render_target(device, width, height, Self::FORMAT, "ssao_texture");
+        let blurred_texture = texture::Texture::create_render_target(device, width, height, Self::FORMAT, "ssao_blurred_texture");
+        let output_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: output_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&blurred_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&blurred_texture.sampler),
+                },
+            ],
+            label: Some("ssao_output_bind_group"),
+        });
+        (ao_texture, blurred_texture, output_bind_group)
+    }
+
+    // layout of output_bind_group(), for pipelines that read the occlusion
+    pub fn output_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.output_layout
+    }
+
+    pub fn output_bind_group(&self) -> &wgpu::BindGroup {
+        &self.output_bind_group
+    }
+
+    // with quality Off no depth prepass is needed and the output is plain white
+    pub fn enabled(&self) -> bool {
+        self.settings.quality != SsaoQuality::Off
+    }
+
+    pub fn settings(&self) -> SsaoSettings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, settings: SsaoSettings) {
+        if settings.quality.resolution_divisor() != self.settings.quality.resolution_divisor() {
+            (self.ao_texture, self.blurred_texture, self.output_bind_group) =
+                Self::create_targets(device, &self.output_layout, self.width, self.height, settings.quality);
+        }
+        self.settings = settings;
+        self.uniform.kernel = Self::kernel(settings.quality.sample_count());
+        self.uniform.sample_count = settings.quality.sample_count();
+        self.uniform.radius = settings.radius;
+        self.uniform.bias = settings.bias;
+        self.uniform.intensity = settings.intensity;
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        (self.ao_texture, self.blurred_texture, self.output_bind_group) =
+            Self::create_targets(device, &self.output_layout, width, height, self.settings.quality);
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &camera::Camera) {
+        let proj = camera.build_projection_matrix();
+        self.uniform.proj = proj.to_cols_array_2d();
+        self.uniform.inv_proj = proj.inverse().to_cols_array_2d();
+        self.uniform.far_depth = camera.depth_mode.far_depth();
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+
+    // needs the depth of the scene, e.g. from a depth prepass
+    pub fn process(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, depth: &texture::Texture) {
+        if !self.enabled() {
+            // no occlusion
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("SSAO Clear Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.blurred_texture.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            return;
+        }
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&depth.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("ssao_bind_group"),
+        });
+        fullscreen::draw(encoder, "SSAO Pass", &self.pipeline, &bind_group, &self.ao_texture.view, wgpu::LoadOp::Load);
+
+        let blur_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.blur_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&self.ao_texture.view),
+                },
+            ],
+            label: Some("ssao_blur_bind_group"),
+        });
+        fullscreen::draw(encoder, "SSAO Blur Pass", &self.blur_pipeline, &blur_bind_group, &self.blurred_texture.view, wgpu::LoadOp::Load);
+    }
+}
@@ -1,6 +1,41 @@
 #[derive(Debug)]
 pub enum TextureError {
     DecodingError(png::DecodingError), // load_from_memory() -> ImageError -> map_err -> TextureError -> ? operator
+    InvalidDimensions(u32, u32), // image decoded but width and height don't fit what it is used for
+}
+
+// which way depth grows; ReverseZ puts the most float precision far away, where perspective depth needs it
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum DepthMode {
+    #[default]
+    Standard, // 0 at the near plane, 1 at the far plane
+    ReverseZ, // 1 at the near plane, 0 at the far plane
+}
+
+impl DepthMode {
+    // what the depth buffer is cleared to, the depth of the background
+    pub fn far_depth(self) -> f32 {
+        match self {
+            DepthMode::Standard => 1.0,
+            DepthMode::ReverseZ => 0.0,
+        }
+    }
+
+    // passes for fragments closer than what is in the depth buffer
+    pub fn compare(self) -> wgpu::CompareFunction {
+        match self {
+            DepthMode::Standard => wgpu::CompareFunction::Less,
+            DepthMode::ReverseZ => wgpu::CompareFunction::Greater,
+        }
+    }
+
+    // also passes at equal depth, e.g. after a depth prepass
+    pub fn compare_equal(self) -> wgpu::CompareFunction {
+        match self {
+            DepthMode::Standard => wgpu::CompareFunction::LessEqual,
+            DepthMode::ReverseZ => wgpu::CompareFunction::GreaterEqual,
+        }
+    }
 }
 
 pub struct Texture {
@@ -16,6 +51,23 @@ impl Texture {
         bytes: &[u8],
         label: &str) -> Result<Self, TextureError>
     {
+        Self::from_bytes_with_format(device, queue, bytes, wgpu::TextureFormat::Rgba8UnormSrgb, label)
+    }
+
+    // color data (base color, emissive) is sRGB; data textures (roughness, occlusion) need a linear format
+    pub fn from_bytes_with_format(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        format: wgpu::TextureFormat,
+        label: &str) -> Result<Self, TextureError>
+    {
+        let (rgba_data, width, height) = Self::decode_png(bytes)?;
+        Ok(Self::from_rgba(device, queue, &rgba_data, width, height, format, label))
+    }
+
+    // returns the pixels with their width and height
+    pub fn decode_png(bytes: &[u8]) -> Result<(Vec<u8>, u32, u32), TextureError> {
         // Decode PNG
         let decoder = png::Decoder::new(bytes);
         let mut reader = decoder.read_info().map_err(|e| TextureError::DecodingError(e))?;
@@ -23,7 +75,29 @@ impl Texture {
         let buffer_size = width as usize * height as usize * 4; // Assuming RGBA8 format
         let mut rgba_data = vec![0; buffer_size];
         reader.next_frame(&mut rgba_data).map_err(|e| TextureError::DecodingError(e))?;
+        Ok((rgba_data, width, height))
+    }
 
+    // 1x1 texture used in place of a missing material texture
+    pub fn from_color(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        color: [u8; 4],
+        format: wgpu::TextureFormat,
+        label: &str) -> Self
+    {
+        Self::from_rgba(device, queue, &color, 1, 1, format, label)
+    }
+
+    pub fn from_rgba(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        rgba_data: &[u8],
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        label: &str) -> Self
+    {
         // Create a texture
         let size = wgpu::Extent3d {
             width,
@@ -37,7 +111,7 @@ impl Texture {
                 mip_level_count: 1,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                format,
                 usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
                 view_formats: &[],
             },
@@ -51,7 +125,7 @@ impl Texture {
                 origin: wgpu::Origin3d::ZERO,
                 aspect: Default::default(),
             },
-            &rgba_data,
+            rgba_data,
             wgpu::ImageDataLayout {
                 offset: 0,
                 bytes_per_row: Option::from(4 * width),
@@ -66,12 +140,181 @@ impl Texture {
         // Create a sampler
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
 
-        Ok(Self { texture, view, sampler })
+        Self { texture, view, sampler }
+    }
+
+    // linear HDR pixels as Rgba16Float with a box filtered mip chain, e.g. to build an environment
+    // from; each layer is width * height pixels row by row from the top, and six layers make a cube
+    // in the order +X, -X, +Y, -Y, +Z, -Z
+    pub fn from_hdr(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layers: &[&[[f32; 4]]],
+        width: u32,
+        height: u32,
+        label: &str) -> Self
+    {
+        let mip_level_count = width.max(height).ilog2() + 1;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: layers.len() as u32,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        for (layer, pixels) in layers.iter().enumerate() {
+            let mut level = pixels.to_vec();
+            let (mut level_width, mut level_height) = (width, height);
+            for mip_level in 0..mip_level_count {
+                let data = level.iter().flatten().map(|&value| to_f16(value)).collect::<Vec<_>>();
+                queue.write_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: &texture,
+                        mip_level,
+                        origin: wgpu::Origin3d { x: 0, y: 0, z: layer as u32 },
+                        aspect: Default::default(),
+                    },
+                    bytemuck::cast_slice(&data),
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(8 * level_width),
+                        rows_per_image: Some(level_height),
+                    },
+                    wgpu::Extent3d { width: level_width, height: level_height, depth_or_array_layers: 1 },
+                );
+                // every texel of the next level averages up to 2x2 of this one
+                let (next_width, next_height) = ((level_width / 2).max(1), (level_height / 2).max(1));
+                let texel = |x: u32, y: u32| glam::Vec4::from(level[(y.min(level_height - 1) * level_width + x.min(level_width - 1)) as usize]);
+                level = (0..next_height)
+                    .flat_map(|y| (0..next_width).map(move |x| (x * 2, y * 2)))
+                    .map(|(x, y)| ((texel(x, y) + texel(x + 1, y) + texel(x, y + 1) + texel(x + 1, y + 1)) * 0.25).to_array())
+                    .collect();
+                (level_width, level_height) = (next_width, next_height);
+            }
+        }
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some(label),
+            dimension: Some(if layers.len() == 6 { wgpu::TextureViewDimension::Cube } else { wgpu::TextureViewDimension::D2 }),
+            ..Default::default()
+        });
+        // repeats around the longitude of equirectangular images
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self { texture, view, sampler }
+    }
+
+    // cube texture that is rendered into one face and mip level at a time, see face_view()
+    pub fn create_cube(
+        device: &wgpu::Device,
+        size: u32,
+        mip_level_count: u32,
+        format: wgpu::TextureFormat,
+        label: &str) -> Self
+    {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 6, // one layer per face
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some(label),
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self { texture, view, sampler }
+    }
+
+    // 2D view on a single face and mip level of a cube texture, to use as render attachment
+    pub fn face_view(&self, face: u32, mip_level: u32) -> wgpu::TextureView {
+        self.texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("cube face view"),
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_mip_level: mip_level,
+            mip_level_count: Some(1),
+            base_array_layer: face,
+            array_layer_count: Some(1),
+            ..Default::default()
+        })
+    }
+
+    // texture that is rendered into by one pass and sampled by the next
+    pub fn create_render_target(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        label: &str) -> Self
+    {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Self { texture, view, sampler }
     }
 
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float; // 1.
 
-    pub fn create_depth_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, label: &str) -> Self {
+    pub fn create_depth_texture(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        depth_mode: DepthMode,
+        label: &str) -> Self
+    {
         let size = wgpu::Extent3d { // 2.
             width: config.width,
             height: config.height,
@@ -99,7 +342,7 @@ impl Texture {
                 mag_filter: wgpu::FilterMode::Linear,
                 min_filter: wgpu::FilterMode::Linear,
                 mipmap_filter: wgpu::FilterMode::Nearest,
-                compare: Some(wgpu::CompareFunction::LessEqual), // 5.
+                compare: Some(depth_mode.compare_equal()), // 5.
                 lod_min_clamp: 0.0,
                 lod_max_clamp: 100.0,
                 ..Default::default()
@@ -109,3 +352,21 @@ impl Texture {
         Self { texture, view, sampler }
     }
 }
+
+// the nearest half float; what is too large for one becomes the largest finite value
+fn to_f16(value: f32) -> u16 {
+    let sign = ((value.to_bits() >> 16) & 0x8000) as u16;
+    if value.is_nan() {
+        return sign | 0x7e00;
+    }
+    let value = value.abs().min(65504.0);
+    if value < 6.1035156e-5 {
+        // subnormal, in steps of 2^-24; rounding up to 1024 gives the smallest normal
+        return sign | (value * 16777216.0).round() as u16;
+    }
+    let bits = value.to_bits();
+    let exponent = (bits >> 23) + 15 - 127;
+    let mantissa = bits & 0x7f_ffff;
+    // rounds on the highest dropped bit; a carry into the exponent is still the right value
+    sign | (((exponent << 10) | (mantissa >> 13)) + ((mantissa >> 12) & 1)) as u16
+}
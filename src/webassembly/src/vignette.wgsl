@@ -0,0 +1,22 @@
+// Vignette: darkens the image towards the corners
+
+struct VignetteUniform {
+    intensity: f32, // 0 = off, 1 = black corners
+    radius: f32, // distance from the center where darkening starts, 0.5 reaches the edges
+    smoothness: f32, // width of the transition
+};
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+@group(0) @binding(2)
+var<uniform> vignette: VignetteUniform;
+
+@fragment
+fn fs_vignette(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_source, s_source, in.uv);
+    let distance = length(in.uv - vec2<f32>(0.5));
+    let darkening = smoothstep(vignette.radius, vignette.radius + vignette.smoothness, distance) * vignette.intensity;
+    return vec4<f32>(color.rgb * (1.0 - darkening), color.a);
+}